@@ -0,0 +1,70 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use green_egg_eater::{compile_program_with_options, parse_source, CompileOptions, START_LABEL};
+
+// Number of statements in the main expression of the generated program
+const NUM_STATEMENTS: usize = 5000;
+
+// Counts the allocations made through the global allocator
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        return System.alloc(layout);
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        return System.realloc(ptr, layout, new_size);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Generates a program whose code is mostly small checked operations, each of which emits a
+// handful of instructions
+fn source() -> String {
+    let mut source = String::from("(let ((acc (vec 0 0)) (i input)) (block\n");
+    for i in 0..NUM_STATEMENTS {
+        source.push_str(&format!(
+            "(if (< i {i}) (vec-set! acc 0 (+ (vec-get acc 0) i)) (set! i (* i 2)))\n"
+        ));
+    }
+    source.push_str("(vec-get acc 0)))\n");
+    return source;
+}
+
+fn main() {
+    let program = parse_source(&source()).unwrap();
+    let options = CompileOptions {
+        parallel: false,
+        ..CompileOptions::default()
+    };
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let instrs = compile_program_with_options(&program, START_LABEL.to_string(), &options).unwrap();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+
+    println!(
+        "compile {NUM_STATEMENTS} statements: {} instructions, {allocations} allocations, {bytes} bytes allocated",
+        instrs.len()
+    );
+    println!(
+        "allocations per instruction: {:.2}",
+        allocations as f64 / instrs.len() as f64
+    );
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+
+use green_egg_eater::constants::COMPILER_STACK_SIZE;
+use green_egg_eater::{compile_program_with_options, parse_source, CompileOptions, START_LABEL};
+
+// Number of function definitions in the generated program
+const NUM_FUNS: usize = 2000;
+const ITERATIONS: u32 = 10;
+// Nesting depth of the generated deep program
+const DEPTH: usize = 2000;
+// Number of statements in the main expression of the generated large program
+const NUM_STATEMENTS: usize = 20000;
+
+// Generates a program with many independent functions, each with some control flow
+fn many_functions_source() -> String {
+    let mut source = String::new();
+    for i in 0..NUM_FUNS {
+        source.push_str(&format!(
+            "(fun (f{i} x) (let ((i 0) (acc (vec x x x))) (loop (if (< i 10) (block (set! i (+ i 1)) (vec-set! acc 0 (* (vec-get acc 0) 2))) (break (vec-get acc 0))))))\n"
+        ));
+    }
+    source.push_str("(f0 input)\n");
+    return source;
+}
+
+// Generates a deeply nested program whose arithmetic is only partly constant, so every level of
+// nesting is checked for constants
+fn deep_source(depth: usize) -> String {
+    return format!(
+        "{}(+ input 1){}",
+        "(+ 1 (add1 ".repeat(depth),
+        "))".repeat(depth)
+    );
+}
+
+// Generates a program with one large main expression, so the time is dominated by emitting and
+// collecting the instructions rather than by the per-function setup
+fn large_source() -> String {
+    let mut source = String::from("(let ((acc (vec 0 0)) (i input)) (block\n");
+    for i in 0..NUM_STATEMENTS {
+        source.push_str(&format!(
+            "(if (< i {i}) (vec-set! acc 0 (+ (vec-get acc 0) i)) (set! i (* i 2)))\n"
+        ));
+    }
+    source.push_str("(vec-get acc 0)))\n");
+    return source;
+}
+
+fn time_compile(source: &str, options: &CompileOptions) -> Duration {
+    let program = parse_source(source).unwrap();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        compile_program_with_options(&program, START_LABEL.to_string(), options).unwrap();
+    }
+    return start.elapsed() / ITERATIONS;
+}
+
+fn main() {
+    let source = many_functions_source();
+    let sequential = time_compile(
+        &source,
+        &CompileOptions {
+            parallel: false,
+            ..CompileOptions::default()
+        },
+    );
+    let parallel = time_compile(
+        &source,
+        &CompileOptions {
+            parallel: true,
+            ..CompileOptions::default()
+        },
+    );
+    println!("compile {NUM_FUNS} functions (sequential): {sequential:?}");
+    println!("compile {NUM_FUNS} functions (parallel):   {parallel:?}");
+
+    let large = time_compile(&large_source(), &CompileOptions::default());
+    println!("compile {NUM_STATEMENTS} statements: {large:?}");
+
+    // With linear passes, doubling the depth should roughly double the time.
+    // Compiling deep programs needs the larger stack the compiler itself runs with.
+    let (deep, deeper) = std::thread::Builder::new()
+        .stack_size(COMPILER_STACK_SIZE)
+        .spawn(|| {
+            let options = CompileOptions::default();
+            let deep = time_compile(&deep_source(DEPTH), &options);
+            let deeper = time_compile(&deep_source(2 * DEPTH), &options);
+            (deep, deeper)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    println!("compile depth {DEPTH}: {deep:?}");
+    println!("compile depth {}: {deeper:?}", 2 * DEPTH);
+}
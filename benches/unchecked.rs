@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use green_egg_eater::link::link;
+use green_egg_eater::{compile_program_with_options, parse_source, write_program};
+use green_egg_eater::{CompileOptions, START_LABEL};
+
+const ITERATIONS: u32 = 5;
+
+// Sums the elements of a vector many times, so vec-get dominates the running time
+const SOURCE: &str = "
+(let ((v (make-vec 1000 3)) (round 0) (sum 0))
+  (loop
+    (if (= round 20000)
+      (break sum)
+      (let ((i 0))
+        (block
+          (loop
+            (if (= i 1000)
+              (break i)
+              (block
+                (set! sum (+ sum (vec-get v i)))
+                (set! i (add1 i)))))
+          (set! round (add1 round)))))))
+";
+
+// Compiles and links the benchmark program with the given options, returning the executable
+fn build(name: &str, options: &CompileOptions) -> PathBuf {
+    let program = parse_source(SOURCE).unwrap();
+    let instrs = compile_program_with_options(&program, START_LABEL.to_string(), options).unwrap();
+    let asm_path = std::env::temp_dir().join(format!("{name}.s"));
+    let mut asm_file = fs::File::create(&asm_path).unwrap();
+    write_program(&mut asm_file, &instrs).unwrap();
+    return link(&asm_path).unwrap();
+}
+
+fn time_run(exe_path: &Path) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let output = Command::new(exe_path).output().unwrap();
+        assert!(output.status.success());
+    }
+    return start.elapsed() / ITERATIONS;
+}
+
+fn main() {
+    let checked = build("bench_checked", &CompileOptions::default());
+    let unchecked = build(
+        "bench_unchecked",
+        &CompileOptions {
+            unchecked: true,
+            ..CompileOptions::default()
+        },
+    );
+    println!("indexing loop (checked):   {:?}", time_run(&checked));
+    println!("indexing loop (unchecked): {:?}", time_run(&unchecked));
+}
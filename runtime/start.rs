@@ -1,5 +1,8 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashSet;
 use std::env;
+use std::io::{self, BufWriter, IsTerminal, Stdout, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /**
  * Rust functions that are linked at runtime with the compiler
@@ -10,6 +13,64 @@ const I63_MAX: i64 = 4611686018427387903;
 const TRUE: i64 = 7;
 const FALSE: i64 = 3;
 const NIL: i64 = 1;
+// Number of inputs (input0 .. input15) passed to the compiled code
+const MAX_INPUTS: usize = 16;
+
+thread_local! {
+    // Buffered stdout shared by all printing functions. It must be flushed before the process exits.
+    static OUTPUT: RefCell<BufWriter<Stdout>> = RefCell::new(BufWriter::new(io::stdout()));
+    // State of the pseudo-random number generator, which is seeded when it is first used
+    static RANDOM_STATE: Cell<Option<u64>> = Cell::new(None);
+    // Vectors nested deeper than this are printed as ..., if SNEK_PRINT_DEPTH is set
+    static PRINT_DEPTH: Option<usize> = print_limit("SNEK_PRINT_DEPTH");
+    // Only this many elements of each vector are printed, if SNEK_PRINT_WIDTH is set
+    static PRINT_WIDTH: Option<usize> = print_limit("SNEK_PRINT_WIDTH");
+    // Start of the heap, used to report how much of it the program allocated
+    static HEAP_START: Cell<*const i64> = Cell::new(std::ptr::null());
+}
+
+// Returns the value of an environment variable that limits how much of a value is printed
+fn print_limit(var: &str) -> Option<usize> {
+    match env::var(var) {
+        Ok(limit) => match limit.parse::<usize>() {
+            Ok(limit) => Some(limit),
+            Err(_) => exit_invalid(&format!("Invalid: {} must be a non-negative integer", var)),
+        },
+        Err(_) => None,
+    }
+}
+
+// Prints a summary of the heap usage to stderr if SNEK_STATS is set, given the final heap pointer.
+// Nothing allocated is ever freed, so the peak heap usage is the number of words allocated. Errors
+// raised by the runtime itself do not know the heap pointer and pass null, which prints nothing.
+fn print_stats(heap_ptr: *const i64) {
+    if env::var_os("SNEK_STATS").is_none() || heap_ptr.is_null() {
+        return;
+    }
+    let words = HEAP_START.with(|start| (heap_ptr as usize - start.get() as usize) / 8);
+    eprintln!("stats: {words} words allocated, peak heap usage {words} words");
+}
+
+// Escape sequences that start and end red text in a terminal
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// Returns whether error messages are colored: only when stderr is a terminal, and not when
+// NO_COLOR is set to a non-empty value (see https://no-color.org)
+fn use_color() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && io::stderr().is_terminal()
+}
+
+// Flushes everything printed so far to stdout
+fn flush_output() {
+    OUTPUT.with(|output| {
+        output
+            .borrow_mut()
+            .flush()
+            .expect("could not write to stdout")
+    });
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(i64)]
@@ -18,165 +79,467 @@ pub enum ErrCode {
     InvalidType = 2,
     IndexOutOfBounds = 3,
     InvalidVecSize = 4,
+    MatchFailure = 5,
+    AssertionFailed = 6,
+    DivideByZero = 7,
+    WrongArgCount = 8,
+}
+
+impl ErrCode {
+    // Returns the built-in error with the given code, if there is one
+    fn from_code(code: i64) -> Option<ErrCode> {
+        match code {
+            1 => Some(ErrCode::Overflow),
+            2 => Some(ErrCode::InvalidType),
+            3 => Some(ErrCode::IndexOutOfBounds),
+            4 => Some(ErrCode::InvalidVecSize),
+            5 => Some(ErrCode::MatchFailure),
+            6 => Some(ErrCode::AssertionFailed),
+            7 => Some(ErrCode::DivideByZero),
+            8 => Some(ErrCode::WrongArgCount),
+            _ => None,
+        }
+    }
 }
 
 // Parse "input" values into their internal representations
-fn parse_input(input: &str) -> i64 {
+fn parse_input(input: &str) -> Result<i64, &'static str> {
     match input {
-        "false" => 3,
-        "true" => 7,
+        "false" => Ok(3),
+        "true" => Ok(7),
         _ => match input.parse::<i64>() {
             Ok(num) => {
                 if num < I63_MIN || num > I63_MAX {
-                    panic!("Invalid: input overflows a 63-bit signed integer");
+                    return Err("Invalid: input overflows a 63-bit signed integer");
                 }
-                return num << 1;
-            }
-            Err(_) => {
-                panic!("Invalid: error occurred parsing input");
+                return Ok(num << 1);
             }
+            Err(_) => Err("Invalid: error occurred parsing input"),
         },
     }
 }
 
+// Reports an invalid value found while the compiled code runs and exits with code 1. A panic would
+// abort the process instead, because it cannot unwind out of a function called by the compiled code.
+fn exit_invalid(message: &str) -> ! {
+    flush_output();
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
 // The \x01 used below is an undocumented feature of LLVM that ensures
 // it does not add an underscore in front of the name.
 // Courtesy of Max New (https://maxsnew.com/teaching/eecs-483-fa22/hw_adder_assignment.html)
 
+// The compiled code is only linked into programs, not into the tests of the runtime
+#[cfg(not(test))]
 #[link(name = "our_code")]
 extern "C" {
-    // input is the value provided by the "input" operator. Its value is in RDI.
+    // inputs is the address of the values provided by the input0, input1, ... operators. Its value is in RDI.
     // heap_start is the starting address of the heap. Its value is in RSI.
     // heap_end is the end address of the heap. Its value is in RDX.
     #[link_name = "\x01our_code_starts_here"]
-    fn our_code_starts_here(input: i64, heap_start: *mut i64, heap_end: *mut i64) -> i64;
+    fn our_code_starts_here(
+        inputs: *const i64,
+        heap_start: *mut i64,
+        heap_end: *mut i64,
+    ) -> RunResult;
+}
+
+// What the compiled code returns: the value of the program in RAX, and the final heap pointer in RDX
+#[cfg(not(test))]
+#[repr(C)]
+struct RunResult {
+    value: i64,
+    heap_ptr: *const i64,
 }
 
-// Prints an error message to standard error and then exits the process with a nonzero exit code
+// Prints an error message to standard error and then exits the process with a nonzero exit code.
+// Codes other than the built-in errors come from the error operator. heap_ptr is the heap pointer
+// at the time of the error.
 #[export_name = "\x01snek_error"]
-pub extern "C" fn snek_error(errcode: ErrCode) {
-    match errcode {
-        ErrCode::Overflow => eprintln!("an error occurred: numeric overflow"),
-        ErrCode::InvalidType => {
-            eprintln!("an error occurred: invalid argument")
-        }
-        ErrCode::IndexOutOfBounds => eprintln!("an error occurred: index out of bounds"),
-        ErrCode::InvalidVecSize => eprintln!("an error occurred: invalid vector size"),
+pub extern "C" fn snek_error(errcode: i64, heap_ptr: *const i64) {
+    // Make sure the output printed before the error comes out first
+    flush_output();
+    let message = match ErrCode::from_code(errcode) {
+        Some(ErrCode::Overflow) => "numeric overflow".to_string(),
+        Some(ErrCode::InvalidType) => "invalid argument".to_string(),
+        Some(ErrCode::IndexOutOfBounds) => "index out of bounds".to_string(),
+        Some(ErrCode::InvalidVecSize) => "invalid vector size".to_string(),
+        Some(ErrCode::MatchFailure) => "no matching pattern".to_string(),
+        Some(ErrCode::AssertionFailed) => "assertion failed".to_string(),
+        Some(ErrCode::DivideByZero) => "division by zero".to_string(),
+        Some(ErrCode::WrongArgCount) => "wrong number of arguments".to_string(),
+        None => format!("user error {errcode}"),
+    };
+    if use_color() {
+        eprintln!("{RED}an error occurred:{RESET} {message}");
+    } else {
+        eprintln!("an error occurred: {message}");
+    }
+    print_stats(heap_ptr);
+    // Exit codes must be in 1..=255, so other codes exit with 255
+    let exit_code = if (1..=255).contains(&errcode) {
+        errcode as i32
+    } else {
+        255
+    };
+    std::process::exit(error_exit_code().unwrap_or(exit_code));
+}
+
+// Returns the exit code of every runtime error, including user errors, if SNEK_ERROR_EXIT is set.
+// Otherwise each error exits with its own code. An invalid SNEK_ERROR_EXIT is reported after the
+// error, which then exits with code 1.
+fn error_exit_code() -> Option<i32> {
+    match env::var("SNEK_ERROR_EXIT") {
+        Ok(code) => match code.parse::<i32>() {
+            Ok(code) if (1..=255).contains(&code) => Some(code),
+            _ => exit_invalid("Invalid: SNEK_ERROR_EXIT must be an exit code from 1 to 255"),
+        },
+        Err(_) => None,
+    }
+}
+
+// Returns the greatest common divisor of the absolute values of two numbers. gcd(0, 0) is 0.
+fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// Converts a non-negative result to a value, raising an overflow error if it is out of range
+fn checked_number(num: u64) -> i64 {
+    if num > I63_MAX as u64 {
+        snek_error(ErrCode::Overflow as i64, std::ptr::null());
     }
-    std::process::exit(errcode as i32);
+    (num as i64) << 1
+}
+
+// Computes the greatest common divisor of two numbers, which is never negative
+#[export_name = "\x01snek_gcd"]
+pub extern "C" fn snek_gcd(val1: i64, val2: i64) -> i64 {
+    checked_number(gcd(val1 >> 1, val2 >> 1))
+}
+
+// Computes the least common multiple of two numbers, which is never negative. It is 0 if either
+// number is 0.
+#[export_name = "\x01snek_lcm"]
+pub extern "C" fn snek_lcm(val1: i64, val2: i64) -> i64 {
+    let (a, b) = (val1 >> 1, val2 >> 1);
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let lcm = (a.unsigned_abs() / gcd(a, b)).checked_mul(b.unsigned_abs());
+    checked_number(lcm.unwrap_or(u64::MAX))
+}
+
+// Returns the address of the vector with the given tagged value. Vectors are allocated at 8-byte
+// aligned addresses, so every bit of the address below the tag is clear.
+pub fn vector_addr(val: i64) -> *const u64 {
+    debug_assert_eq!((val - 1) & 7, 0, "misaligned vector address {val:#x}");
+    (val - 1) as *const u64
+}
+
+// Returns the start and end of a heap in the len bytes of memory at mem, rounded inwards to 8-byte
+// boundaries. The compiled code tags vectors in the low bits of their address, so it relies on
+// the heap, and with it every vector, being aligned.
+pub fn align_heap(mem: *mut u8, len: usize) -> (*mut i64, *mut i64) {
+    let offset = mem.align_offset(8).min(len);
+    let start = mem.wrapping_add(offset);
+    let words = (len - offset) / 8;
+    (start as *mut i64, (start as *mut i64).wrapping_add(words))
 }
 
 // Checks structural equality of two values.
 #[export_name = "\x01snek_equals"]
 pub unsafe extern "C" fn snek_equals(val1: i64, val2: i64) -> i64 {
-    snek_equals_helper(val1, val2, &mut HashSet::<(i64, i64)>::new())
+    snek_equals_helper(val1, val2)
 }
 
-// Helper function for structural equality
-unsafe fn snek_equals_helper(val1: i64, val2: i64, seen: &mut HashSet<(i64, i64)>) -> i64 {
+// Returns the index of the first element of the vector that is structurally equal to the value, or
+// -1 if there is none
+#[export_name = "\x01snek_vec_index_of"]
+pub unsafe extern "C" fn snek_vec_index_of(vec: i64, val: i64) -> i64 {
+    let addr = vector_addr(vec);
+    let size = *addr as usize;
+    for index in 0..size {
+        if snek_equals_helper(*addr.add(1 + index) as i64, val) == TRUE {
+            return (index as i64) << 1;
+        }
+    }
+    -1 << 1
+}
+
+// Sorts the elements of the vector, which are all numbers, in ascending order in place, and returns
+// the vector. Numbers are ordered the same way as their shifted representations.
+#[export_name = "\x01snek_sort"]
+pub unsafe extern "C" fn snek_sort(vec: i64) -> i64 {
+    let addr = vector_addr(vec) as *mut i64;
+    let size = *addr as usize;
+    std::slice::from_raw_parts_mut(addr.add(1), size).sort_unstable();
+    vec
+}
+
+// Result of comparing two values without looking at their elements
+enum Comparison {
+    Equal,
+    NotEqual,
+    // Both values are vectors of the given size whose elements must be compared
+    Elements(usize),
+}
+
+// Compares two values, deferring to their elements if both are distinct non-nil vectors.
+// seen holds the pairs of vectors currently being compared; finding a pair again means
+// the structures are cyclic, and the pair is assumed to be equal.
+unsafe fn compare(val1: i64, val2: i64, seen: &HashSet<(i64, i64)>) -> Comparison {
     if val1 & 3 == 1 && val2 & 3 == 1 {
         if val1 == val2 {
             // println!("Pointers are equal");
-            seen.remove(&(val1, val2));
-            return TRUE;
+            return Comparison::Equal;
         }
         if val1 == NIL || val2 == NIL {
             // println!("Pointer is NIL");
-            seen.remove(&(val1, val2));
-            return FALSE;
+            return Comparison::NotEqual;
         }
-        if !seen.insert((val1, val2)) {
+        if seen.contains(&(val1, val2)) {
             // println!("Pointers seen before");
-            seen.remove(&(val1, val2));
-            return TRUE;
+            return Comparison::Equal;
         }
 
-        let addr1 = (val1 - 1) as *const u64;
-        let addr2 = (val2 - 1) as *const u64;
-        let size1 = addr1.read();
-        let size2 = addr2.read();
+        let size1 = vector_addr(val1).read();
+        let size2 = vector_addr(val2).read();
         if size1 != size2 {
-            return FALSE;
-        }
-        // Compare each of the values of val1 and val2
-        for i in 0..size1 {
-            let elem1 = addr1.add(1 + i as usize).read() as i64;
-            let elem2 = addr2.add(1 + i as usize).read() as i64;
-            if snek_equals_helper(elem1, elem2, seen) == FALSE {
-                return FALSE;
-            }
+            return Comparison::NotEqual;
         }
-        seen.remove(&(val1, val2));
-        // println!("Structurally equal");
-        TRUE
-    } else {
+        Comparison::Elements(size1 as usize)
+    } else if val1 == val2 {
         // If val1 and val2 aren't pointers, use reference equality
-        seen.remove(&(val1, val2));
-        if val1 == val2 {
-            // println!("Referentially equal");
-            return TRUE;
-        } else {
-            // println!("Referentially unequal");
-            return FALSE;
+        // println!("Referentially equal");
+        Comparison::Equal
+    } else {
+        // println!("Referentially unequal");
+        Comparison::NotEqual
+    }
+}
+
+// Helper function for structural equality.
+// Uses an explicit stack of the vectors being compared so deep structures don't overflow the native stack.
+unsafe fn snek_equals_helper(val1: i64, val2: i64) -> i64 {
+    let mut seen = HashSet::<(i64, i64)>::new();
+    // Each entry is a pair of vectors, their size, and the index of the next elements to compare
+    let mut stack: Vec<(i64, i64, usize, usize)> = Vec::new();
+
+    match compare(val1, val2, &seen) {
+        Comparison::Equal => return TRUE,
+        Comparison::NotEqual => return FALSE,
+        Comparison::Elements(size) => {
+            seen.insert((val1, val2));
+            stack.push((val1, val2, size, 0));
+        }
+    }
+
+    while let Some((vec1, vec2, size, index)) = stack.last_mut() {
+        if *index == *size {
+            // All elements were equal
+            seen.remove(&(*vec1, *vec2));
+            stack.pop();
+            continue;
+        }
+
+        // Compare the next pair of elements
+        let addr1 = vector_addr(*vec1);
+        let addr2 = vector_addr(*vec2);
+        let elem1 = addr1.add(1 + *index).read() as i64;
+        let elem2 = addr2.add(1 + *index).read() as i64;
+        *index += 1;
+        match compare(elem1, elem2, &seen) {
+            Comparison::Equal => {}
+            Comparison::NotEqual => return FALSE,
+            Comparison::Elements(size) => {
+                seen.insert((elem1, elem2));
+                stack.push((elem1, elem2, size, 0));
+            }
         }
     }
+    // println!("Structurally equal");
+    TRUE
 }
 
 // Prints the formatted representation of the value and returns the original input value.
 #[export_name = "\x01snek_print"]
 pub unsafe extern "C" fn snek_print(val: i64) -> i64 {
-    let print_val = snek_str(val, &mut HashSet::<i64>::new());
-    println!("{print_val}");
+    let print_val = snek_str(val);
+    OUTPUT.with(|output| {
+        writeln!(output.borrow_mut(), "{print_val}").expect("could not write to stdout")
+    });
     val
 }
 
+// Prints the formatted representation of the value to stdout if the file descriptor is 1, or to
+// stderr if it is 2, and returns the original input value. The compiled code checks the file
+// descriptor.
+#[export_name = "\x01snek_print_fd"]
+pub unsafe extern "C" fn snek_print_fd(fd: i64, val: i64) -> i64 {
+    if fd >> 1 == 1 {
+        return snek_print(val);
+    }
+    let print_val = snek_str(val);
+    // Keep what was printed to stdout before this ahead of it when both go to a terminal
+    flush_output();
+    eprintln!("{print_val}");
+    val
+}
+
+// Returns the seed of the random number generator: SNEK_SEED if it is set, for reproducible runs,
+// and otherwise the current time
+fn random_seed() -> u64 {
+    match env::var("SNEK_SEED") {
+        Ok(seed) => match seed.parse::<u64>() {
+            Ok(seed) => seed,
+            Err(_) => exit_invalid("Invalid: SNEK_SEED must be a non-negative integer"),
+        },
+        Err(_) => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(0),
+    }
+}
+
+// Returns a pseudo-random number in 0..n for a positive number n, using a linear congruential
+// generator.
+#[export_name = "\x01snek_random"]
+pub extern "C" fn snek_random(val: i64) -> i64 {
+    let n = (val >> 1) as u64;
+    let state = RANDOM_STATE.with(|state| {
+        let next = state
+            .get()
+            .unwrap_or_else(random_seed)
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        state.set(Some(next));
+        next
+    });
+    // Scaling the whole state down to the range uses its high bits, which are the most random
+    let num = ((state as u128 * n as u128) >> 64) as i64;
+    num << 1
+}
+
+// Reads a line from stdin and parses it like an input value.
+#[export_name = "\x01snek_read"]
+pub extern "C" fn snek_read() -> i64 {
+    // Show any prompt printed before reading
+    flush_output();
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) => exit_invalid("Invalid: no more input to read"),
+        Ok(_) => parse_input(line.trim()).unwrap_or_else(|message| exit_invalid(message)),
+        Err(_) => exit_invalid("Invalid: error occurred reading input"),
+    }
+}
+
+// A step in rendering a value as a string
+enum RenderTask {
+    // A value and the number of vectors it is nested in
+    Value(i64, usize),
+    Separator,
+    // Marks the elements of a long vector that are not printed
+    Ellipsis,
+    // Closes the vector, which is no longer being rendered
+    Close(i64),
+}
+
 // Converts the internal representation of the value to its true value, formatted as a string.
-unsafe fn snek_str(val: i64, seen: &mut HashSet<i64>) -> String {
-    if val == 7 {
-        String::from("true")
-    } else if val == 3 {
-        String::from("false")
-    } else if val % 2 == 0 {
-        (val >> 1).to_string()
-    } else if val == 1 {
-        String::from("nil")
-    } else if val & 1 == 1 {
-        if !seen.insert(val) {
-            return String::from("[...]");
-        }
-        let addr = (val - 1) as *const u64;
-        let size = addr.read() as usize;
-        let mut result_str = String::from("[");
-        for i in 1..size + 1 {
-            let elem = addr.add(i).read() as i64;
-            result_str = result_str + &snek_str(elem, seen);
-            if i < size {
-                result_str = result_str + ", ";
+// Uses an explicit stack of render tasks so deep structures don't overflow the native stack.
+unsafe fn snek_str(val: i64) -> String {
+    let max_depth = PRINT_DEPTH.with(|depth| *depth);
+    let max_width = PRINT_WIDTH.with(|width| *width);
+    // Vectors currently being rendered; finding one again means the structure is cyclic
+    let mut seen = HashSet::<i64>::new();
+    let mut result_str = String::new();
+    let mut tasks = vec![RenderTask::Value(val, 0)];
+
+    while let Some(task) = tasks.pop() {
+        match task {
+            RenderTask::Separator => result_str.push_str(", "),
+            RenderTask::Ellipsis => result_str.push_str("..."),
+            RenderTask::Close(vec) => {
+                seen.remove(&vec);
+                result_str.push(']');
+            }
+            RenderTask::Value(val, depth) => {
+                if val == 7 {
+                    result_str.push_str("true");
+                } else if val == 3 {
+                    result_str.push_str("false");
+                } else if val % 2 == 0 {
+                    result_str.push_str(&(val >> 1).to_string());
+                } else if val == 1 {
+                    result_str.push_str("nil");
+                } else if val & 1 == 1 {
+                    if matches!(max_depth, Some(max) if depth >= max) {
+                        result_str.push_str("...");
+                        continue;
+                    }
+                    if !seen.insert(val) {
+                        result_str.push_str("[...]");
+                        continue;
+                    }
+                    result_str.push('[');
+                    // Push the tasks in reverse so the elements are rendered in order
+                    let addr = vector_addr(val);
+                    let size = addr.read() as usize;
+                    let shown = match max_width {
+                        Some(max_width) if size > max_width => max_width,
+                        _ => size,
+                    };
+                    tasks.push(RenderTask::Close(val));
+                    if shown < size {
+                        tasks.push(RenderTask::Ellipsis);
+                        if shown > 0 {
+                            tasks.push(RenderTask::Separator);
+                        }
+                    }
+                    for i in (1..shown + 1).rev() {
+                        tasks.push(RenderTask::Value(addr.add(i).read() as i64, depth + 1));
+                        if i > 1 {
+                            tasks.push(RenderTask::Separator);
+                        }
+                    }
+                } else {
+                    result_str.push_str(&format!("Unknown value: {}", val));
+                }
             }
         }
-        seen.remove(&val);
-        result_str + "]"
-    } else {
-        format!("Unknown value: {}", val)
     }
+    result_str
 }
 
+#[cfg(not(test))]
 fn main() {
     let args: Vec<String> = env::args().collect();
-    // The default value for the "input" operator is false
-    let input = if args.len() == 2 { &args[1] } else { "false" };
-    let input = parse_input(&input);
+    if args.len() > MAX_INPUTS + 1 {
+        panic!("Invalid: at most {} inputs are supported", MAX_INPUTS);
+    }
+    // The default value for each input operator is false
+    let mut inputs = [FALSE; MAX_INPUTS];
+    for (i, arg) in args.iter().skip(1).enumerate() {
+        inputs[i] = parse_input(arg).unwrap_or_else(|message| panic!("{}", message));
+    }
 
     // Allocate a large chunk of memory for the heap
     const HEAP_CAPACITY: usize = 1000000;
     let mut heap_mem = Vec::<i64>::with_capacity(HEAP_CAPACITY);
-    let heap_start: *mut i64 = heap_mem.as_mut_ptr();
-    let heap_end: *mut i64 = unsafe { heap_start.offset(HEAP_CAPACITY as isize) };
+    let (heap_start, heap_end) = align_heap(heap_mem.as_mut_ptr() as *mut u8, HEAP_CAPACITY * 8);
+    HEAP_START.with(|start| start.set(heap_start));
 
     // Run the compiled code
-    let output: i64 = unsafe { our_code_starts_here(input, heap_start, heap_end) };
+    let result = unsafe { our_code_starts_here(inputs.as_ptr(), heap_start, heap_end) };
     // Print the output
     unsafe {
-        let _ = snek_print(output);
+        let _ = snek_print(result.value);
     }
+    flush_output();
+    print_stats(result.heap_ptr);
 }
@@ -0,0 +1,390 @@
+/**
+ * Static analyses that report warnings about a program without rejecting it.
+ */
+use std::fmt;
+
+use im::{HashMap, HashSet};
+
+use crate::syntax::{Expr, FunDef, Op1, Op2, Pattern, Program};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    UnusedFunction(String), // function name
+    UnusedBinding(String),  // let binding name
+    // A comparison whose operands are known to have different types, which is an invalid
+    // argument error at runtime
+    TypeMismatch { op: String, left: Type, right: Type },
+    // A let binding with the name of a parameter of the enclosing function, which hides the
+    // parameter in the body of the let
+    ShadowedParameter { name: String, function: String },
+    // Expressions of a block after one that always breaks out of the loop, which never run. The
+    // function is None in the main expression.
+    UnreachableAfterBreak { function: Option<String> },
+}
+
+// Types of values that can be known before the program runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Number,
+    Boolean,
+    Vector, // including nil
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "a number"),
+            Type::Boolean => write!(f, "a boolean"),
+            Type::Vector => write!(f, "a vector"),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnusedFunction(name) => write!(f, "Warning: function {name} is never used"),
+            Warning::UnusedBinding(name) => write!(f, "Warning: binding {name} is never used"),
+            Warning::TypeMismatch { op, left, right } => {
+                write!(f, "Warning: {op} compares {left} with {right}")
+            }
+            Warning::ShadowedParameter { name, function } => write!(
+                f,
+                "Warning: binding {name} shadows a parameter of function {function}"
+            ),
+            Warning::UnreachableAfterBreak { function } => match function {
+                Some(function) => write!(
+                    f,
+                    "Warning: code after break in function {function} is unreachable"
+                ),
+                None => write!(
+                    f,
+                    "Warning: code after break in the main expression is unreachable"
+                ),
+            },
+        }
+    }
+}
+
+// Returns the warnings for the program: unused functions first, then unused bindings in source
+// order, then comparisons of mismatched types in source order, then shadowed parameters in source
+// order, then unreachable code in source order
+pub fn find_warnings(prog: &Program) -> Vec<Warning> {
+    let mut warnings: Vec<Warning> = Vec::new();
+    for name in unused_functions(prog) {
+        warnings.push(Warning::UnusedFunction(name));
+    }
+
+    let mut unused: Vec<String> = Vec::new();
+    for def in prog.defs.iter() {
+        collect_unused_bindings(&def.body, &mut unused);
+    }
+    collect_unused_bindings(&prog.main, &mut unused);
+    for name in unused {
+        warnings.push(Warning::UnusedBinding(name));
+    }
+
+    for def in prog.defs.iter() {
+        collect_type_mismatches(&def.body, &mut warnings);
+    }
+    collect_type_mismatches(&prog.main, &mut warnings);
+
+    for def in prog.defs.iter() {
+        let params: Vec<&String> = def.params.iter().chain(def.rest.iter()).collect();
+        collect_shadowed_params(&def.body, &params, &def.name, &mut warnings);
+    }
+
+    for def in prog.defs.iter() {
+        collect_unreachable(&def.body, Some(&def.name), &mut warnings);
+    }
+    collect_unreachable(&prog.main, None, &mut warnings);
+    return warnings;
+}
+
+// Adds a warning for each block within the expression with an expression after one that always
+// breaks
+fn collect_unreachable(expr: &Expr, function: Option<&String>, warnings: &mut Vec<Warning>) {
+    if let Expr::Block(es) = expr {
+        if es.len() > 1 && es[..es.len() - 1].iter().any(always_breaks) {
+            warnings.push(Warning::UnreachableAfterBreak {
+                function: function.cloned(),
+            });
+        }
+    }
+    for e in sub_exprs(expr) {
+        collect_unreachable(e, function, warnings);
+    }
+}
+
+// Returns true if evaluating the expression always breaks out of the enclosing loop: it is a
+// break, an if whose condition or both branches always break, or a block or let with a part that
+// always breaks. A break within a loop inside the expression only leaves that loop.
+fn always_breaks(expr: &Expr) -> bool {
+    match expr {
+        Expr::Break(_, _) => true,
+        Expr::If(cond, thn, els) => {
+            always_breaks(cond) || (always_breaks(thn) && always_breaks(els))
+        }
+        Expr::Block(es) => es.iter().any(always_breaks),
+        Expr::Let(bindings, body) => {
+            bindings.iter().any(|(_, e)| always_breaks(e)) || always_breaks(body)
+        }
+        _ => false,
+    }
+}
+
+// Adds a warning for each let binding within the expression whose name is one of the parameters
+fn collect_shadowed_params(
+    expr: &Expr,
+    params: &[&String],
+    function: &str,
+    warnings: &mut Vec<Warning>,
+) {
+    if let Expr::Let(bindings, _) | Expr::NamedLet(_, bindings, _) = expr {
+        for (name, _) in bindings.iter() {
+            if params.contains(&name) {
+                warnings.push(Warning::ShadowedParameter {
+                    name: name.to_string(),
+                    function: function.to_string(),
+                });
+            }
+        }
+    }
+    for e in sub_exprs(expr) {
+        collect_shadowed_params(e, params, function, warnings);
+    }
+}
+
+// Adds a warning for each comparison within the expression whose operands have known, different
+// types
+fn collect_type_mismatches(expr: &Expr, warnings: &mut Vec<Warning>) {
+    if let Expr::BinOp(op, e1, e2) = expr {
+        let op_name = match op {
+            Op2::Equal => Some("="),
+            Op2::Less => Some("<"),
+            Op2::LessEqual => Some("<="),
+            Op2::Greater => Some(">"),
+            Op2::GreaterEqual => Some(">="),
+            _ => None,
+        };
+        if let (Some(op_name), Some(left), Some(right)) =
+            (op_name, static_type(e1), static_type(e2))
+        {
+            if left != right {
+                warnings.push(Warning::TypeMismatch {
+                    op: op_name.to_string(),
+                    left,
+                    right,
+                });
+            }
+        }
+    }
+    for e in sub_exprs(expr) {
+        collect_type_mismatches(e, warnings);
+    }
+}
+
+// Returns the type of the expression's value if it is known from literals and the results of
+// operators alone. Variables, inputs, and function results have unknown types.
+pub(crate) fn static_type(expr: &Expr) -> Option<Type> {
+    match expr {
+        Expr::Number(_) => Some(Type::Number),
+        Expr::Boolean(_) => Some(Type::Boolean),
+        Expr::Nil
+        | Expr::Vec(_)
+        | Expr::MakeVec(_, _)
+        | Expr::VecReverse(_)
+        | Expr::VecSort(_)
+        | Expr::MakeStruct(_, _) => Some(Type::Vector),
+        Expr::VecLen(_) | Expr::VecIndexOf(_, _) | Expr::VecSum(_) | Expr::VecProduct(_) => {
+            Some(Type::Number)
+        }
+        Expr::VecContains(_, _) => Some(Type::Boolean),
+        Expr::UnOp(Op1::IsNum | Op1::IsBool | Op1::IsVec, _) => Some(Type::Boolean),
+        Expr::UnOp(Op1::Print, e) | Expr::BinOp(Op2::PrintTo, _, e) => static_type(e),
+        Expr::UnOp(_, _) => Some(Type::Number),
+        Expr::BinOp(
+            Op2::Equal
+            | Op2::Greater
+            | Op2::GreaterEqual
+            | Op2::Less
+            | Op2::LessEqual
+            | Op2::StructEqual
+            | Op2::BoolXor,
+            _,
+            _,
+        ) => Some(Type::Boolean),
+        Expr::BinOp(_, _, _) => Some(Type::Number),
+        Expr::Let(_, body) => static_type(body),
+        Expr::Block(es) => es.last().and_then(static_type),
+        Expr::If(_, thn, els) => {
+            let thn = static_type(thn)?;
+            if static_type(els)? == thn {
+                Some(thn)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+// Returns the names of the functions that cannot be reached by calls from the main expression
+pub fn unused_functions(prog: &Program) -> Vec<String> {
+    let mut fun_map: HashMap<String, &FunDef> = HashMap::new();
+    for def in prog.defs.iter() {
+        fun_map = fun_map.update(def.name.to_string(), def);
+    }
+
+    // Visit the functions called from main, then the functions they call, and so on
+    let mut used: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = Vec::new();
+    collect_calls(&prog.main, &mut pending);
+    while let Some(name) = pending.pop() {
+        if used.contains(&name) {
+            continue;
+        }
+        if let Some(def) = fun_map.get(&name) {
+            used.insert(name);
+            collect_calls(&def.body, &mut pending);
+        }
+    }
+
+    let mut unused: Vec<String> = Vec::new();
+    for def in prog.defs.iter() {
+        if !used.contains(&def.name) {
+            unused.push(def.name.to_string());
+        }
+    }
+    return unused;
+}
+
+// Adds the name of every function called or passed by name within the expression to calls
+fn collect_calls(expr: &Expr, calls: &mut Vec<String>) {
+    match expr {
+        Expr::Call(name, _) => calls.push(name.to_string()),
+        Expr::VecMap(f, _) | Expr::VecForEach(f, _) | Expr::Apply(f, _) => {
+            if let Expr::Id(name) = f.as_ref() {
+                calls.push(name.to_string());
+            }
+        }
+        _ => (),
+    }
+    for e in sub_exprs(expr) {
+        collect_calls(e, calls);
+    }
+}
+
+// Adds the name of every let binding within the expression that is never referenced to unused.
+// Bindings starting with an underscore are assumed to be unused on purpose.
+fn collect_unused_bindings(expr: &Expr, unused: &mut Vec<String>) {
+    if let Expr::Let(bindings, body) = expr {
+        for (i, (name, _)) in bindings.iter().enumerate() {
+            if !name.starts_with('_') && !references_in_scope(name, &bindings[i + 1..], body) {
+                unused.push(name.to_string());
+            }
+        }
+    }
+    for e in sub_exprs(expr) {
+        collect_unused_bindings(e, unused);
+    }
+}
+
+// Returns true if the variable is read or assigned within the expression, ignoring uses where an
+// inner binding of the same name shadows it
+fn references(name: &str, expr: &Expr) -> bool {
+    match expr {
+        Expr::Id(id) => id == name,
+        Expr::Set(id, e) | Expr::SetGet(id, e) => id == name || references(name, e),
+        Expr::Let(bindings, body) | Expr::NamedLet(_, bindings, body) => {
+            references_in_scope(name, bindings, body)
+        }
+        Expr::Match(e, arms) => {
+            references(name, e)
+                || arms
+                    .iter()
+                    .any(|(pattern, body)| !binds(pattern, name) && references(name, body))
+        }
+        _ => sub_exprs(expr).into_iter().any(|e| references(name, e)),
+    }
+}
+
+// Returns true if the variable is referenced by the bindings, which are evaluated in order, or by
+// the body, stopping at the first binding that shadows it
+fn references_in_scope(name: &str, bindings: &[(String, Expr)], body: &Expr) -> bool {
+    for (id, e) in bindings.iter() {
+        if references(name, e) {
+            return true;
+        }
+        if id == name {
+            return false;
+        }
+    }
+    return references(name, body);
+}
+
+// Returns true if the pattern binds the variable
+pub(crate) fn binds(pattern: &Pattern, name: &str) -> bool {
+    match pattern {
+        Pattern::Id(id) => id == name,
+        Pattern::Vec(patterns) => patterns.iter().any(|p| binds(p, name)),
+        Pattern::Wildcard | Pattern::Number(_) | Pattern::Boolean(_) | Pattern::Nil => false,
+    }
+}
+
+// Returns the immediate subexpressions of the expression, in evaluation order
+pub(crate) fn sub_exprs(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Number(_)
+        | Expr::Boolean(_)
+        | Expr::Input(_)
+        | Expr::Read
+        | Expr::Nil
+        | Expr::Continue(_)
+        | Expr::Id(_) => vec![],
+        Expr::Let(bindings, body) | Expr::NamedLet(_, bindings, body) => {
+            let mut es: Vec<&Expr> = bindings.iter().map(|(_, e)| e).collect();
+            es.push(body);
+            es
+        }
+        Expr::UnOp(_, e)
+        | Expr::Loop(_, e)
+        | Expr::Break(_, e)
+        | Expr::Set(_, e)
+        | Expr::SetGet(_, e)
+        | Expr::Assert(e)
+        | Expr::UserError(e)
+        | Expr::VecLen(e)
+        | Expr::VecReverse(e)
+        | Expr::VecSort(e)
+        | Expr::VecSum(e)
+        | Expr::VecProduct(e)
+        | Expr::GetField(e, _) => vec![e],
+        Expr::BinOp(_, e1, e2)
+        | Expr::VecGet(e1, e2)
+        | Expr::VecContains(e1, e2)
+        | Expr::VecIndexOf(e1, e2)
+        | Expr::MakeVec(e1, e2)
+        | Expr::VecMap(e1, e2)
+        | Expr::VecForEach(e1, e2)
+        | Expr::Apply(e1, e2)
+        | Expr::SetField(e1, _, e2) => vec![e1, e2],
+        Expr::If(e1, e2, e3) | Expr::VecSet(e1, e2, e3) => vec![e1, e2, e3],
+        Expr::Block(es) | Expr::Call(_, es) | Expr::Vec(es) | Expr::MakeStruct(_, es) => {
+            es.iter().collect()
+        }
+        Expr::Match(e, arms) => {
+            let mut es: Vec<&Expr> = vec![e];
+            es.extend(arms.iter().map(|(_, body)| body));
+            es
+        }
+        Expr::Case(e, clauses, else_clause) => {
+            let mut es: Vec<&Expr> = vec![e];
+            es.extend(clauses.iter().map(|(_, body)| body));
+            if let Some(body) = else_clause {
+                es.push(body);
+            }
+            es
+        }
+    }
+}
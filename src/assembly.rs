@@ -1,3 +1,5 @@
+use std::io::{self, Write};
+
 // Assembly values
 #[derive(Debug, Copy, Clone)]
 pub enum Val {
@@ -6,6 +8,9 @@ pub enum Val {
     // Offsets are always subtracted from the register.
     // A positive offset corresponds to moving to lower memory; a negative offset corresponds to moving to higher memory.
     RegOff(Reg, i64),
+    // Address base + index * scale + displacement, where the scale is 1, 2, 4, or 8.
+    // Unlike RegOff, the displacement is added.
+    Scaled(Reg, Reg, i64, i64),
 }
 
 // Registers
@@ -15,16 +20,34 @@ pub enum Reg {
     RDI, // first function arg, caller-saved
     RSI, // second function arg, caller-saved
     RDX, // third function arg, caller-saved
+    RCX, // fourth function arg, caller-saved
+    R8,  // fifth function arg, caller-saved
+    R9,  // sixth function arg, caller-saved
     RSP, // stack pointer
     RBP, // base pointer
     RBX, // scratch register
     R10, // scratch register
     R11, // heap start
     R12, // stack base
-    R13, // stores "input"
+    R13, // stores the address of the inputs
     R14, // heap end
     R15, // heap pointer
     EDI, // first input for snek_error
+    CL,  // low byte of RCX, the count of variable shifts
+}
+
+// Syntaxes the assembly instructions can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Intel, // NASM syntax, assembled with nasm
+    Att,   // AT&T syntax, assembled with as
+}
+
+// Sections of the assembly file
+#[derive(Debug, Clone, Copy)]
+pub enum Section {
+    Text, // instructions
+    Data, // writable data
 }
 
 // Assembly instructions
@@ -37,6 +60,10 @@ pub enum Instr {
     Add(Val, Val),
     Sub(Val, Val),
     Mul(Val, Val),
+    // Sign-extends RAX into RDX:RAX, for IDiv
+    Cqo(),
+    // Divides RDX:RAX by the operand, leaving the quotient in RAX and the remainder in RDX
+    IDiv(Val),
 
     // Comparison
     Cmp(Val, Val),
@@ -52,12 +79,23 @@ pub enum Instr {
     // Shifts
     Sar(Val, Val),
     Shl(Val, Val),
+    Shr(Val, Val),
 
     // Bitwise
     And(Val, Val),
     Or(Val, Val),
     Xor(Val, Val),
     Not(Val),
+    Neg(Val),
+
+    // Bit counting
+    Popcnt(Val, Val),
+    Tzcnt(Val, Val),
+
+    // Address computation
+    Lea(Reg, Val),
+    // Address of the label plus an offset, relative to the instruction pointer
+    LeaLabel(Reg, String, i64),
 
     // Label
     Label(String),
@@ -76,6 +114,13 @@ pub enum Instr {
     Pop(Val),
     Call(String),
     Ret(),
+
+    // Data
+    Section(Section),
+    Align(i64),
+    Quad(i64),
+    // Address of the label plus an offset
+    QuadLabel(String, i64),
 }
 
 // Formats the assembly instruction as a string
@@ -91,6 +136,9 @@ pub fn instr_to_str(instr: &Instr) -> String {
         Instr::Add(val1, val2) => format!("add {}, {}", val_to_str(val1), val_to_str(val2)),
         Instr::Sub(val1, val2) => format!("sub {}, {}", val_to_str(val1), val_to_str(val2)),
         Instr::Mul(val1, val2) => format!("imul {}, {}", val_to_str(val1), val_to_str(val2)),
+        Instr::Cqo() => "cqo".to_string(),
+        Instr::IDiv(val @ Val::RegOff(..)) => format!("idiv qword {}", val_to_str(val)),
+        Instr::IDiv(val) => format!("idiv {}", val_to_str(val)),
         // Comparison
         Instr::Cmp(val1, val2) => format!("cmp {}, {}", val_to_str(val1), val_to_str(val2)),
         Instr::Test(val1, val2) => format!("test {}, {}", val_to_str(val1), val_to_str(val2)),
@@ -105,6 +153,16 @@ pub fn instr_to_str(instr: &Instr) -> String {
         Instr::Or(val1, val2) => format!("or {}, {}", val_to_str(val1), val_to_str(val2)),
         Instr::Xor(val1, val2) => format!("xor {}, {}", val_to_str(val1), val_to_str(val2)),
         Instr::Not(val) => format!("not {}", val_to_str(val)),
+        Instr::Neg(val @ Val::RegOff(..)) => format!("neg qword {}", val_to_str(val)),
+        Instr::Neg(val) => format!("neg {}", val_to_str(val)),
+        // Bit counting
+        Instr::Popcnt(val1, val2) => format!("popcnt {}, {}", val_to_str(val1), val_to_str(val2)),
+        Instr::Tzcnt(val1, val2) => format!("tzcnt {}, {}", val_to_str(val1), val_to_str(val2)),
+        // Address computation
+        Instr::Lea(dst, addr) => format!("lea {}, {}", reg_to_str(dst), val_to_str(addr)),
+        Instr::LeaLabel(dst, label, offset) => {
+            format!("lea {}, [rel {label} + {offset}]", reg_to_str(dst))
+        }
         // Label
         Instr::Label(label) => format!("{label}:"),
         // Jumps
@@ -122,11 +180,148 @@ pub fn instr_to_str(instr: &Instr) -> String {
         Instr::Shl(src, shift_amount) => {
             format!("shl {}, {}", val_to_str(src), val_to_str(shift_amount))
         }
+        Instr::Shr(src, shift_amount) => {
+            format!("shr {}, {}", val_to_str(src), val_to_str(shift_amount))
+        }
         // Function calling
         Instr::Push(val) => format!("push qword {}", val_to_str(val)),
         Instr::Pop(val) => format!("pop {}", val_to_str(val)),
         Instr::Call(label) => format!("call {label}"),
         Instr::Ret() => format!("ret"),
+        // Data
+        Instr::Section(Section::Text) => "section .text".to_string(),
+        Instr::Section(Section::Data) => "section .data".to_string(),
+        Instr::Align(n) => format!("align {n}, db 0"),
+        Instr::Quad(n) => format!("dq {n}"),
+        Instr::QuadLabel(label, offset) => format!("dq {label} + {offset}"),
+    }
+}
+
+// Formats the assembly instruction as a string in the given syntax
+pub fn instr_to_str_in(instr: &Instr, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Intel => instr_to_str(instr),
+        Syntax::Att => instr_to_att(instr),
+    }
+}
+
+// Formats the assembly instruction as a string in AT&T syntax: the source operand comes first,
+// registers are prefixed with %, immediates with $, and the mnemonic has an operand size suffix.
+pub fn instr_to_att(instr: &Instr) -> String {
+    match instr {
+        // Mov
+        Instr::Mov(dst, src) => att_binary("mov", dst, src),
+        // Arithmetic
+        Instr::Add(dst, src) => att_binary("add", dst, src),
+        Instr::Sub(dst, src) => att_binary("sub", dst, src),
+        Instr::Mul(dst, src) => att_binary("imul", dst, src),
+        Instr::Cqo() => "cqto".to_string(),
+        Instr::IDiv(val) => format!("idivq {}", val_to_att(val)),
+        // Comparison
+        Instr::Cmp(val1, val2) => att_binary("cmp", val1, val2),
+        Instr::Test(val1, val2) => att_binary("test", val1, val2),
+        // Conditional move
+        Instr::CMove(dst, src) => att_binary("cmove", dst, src),
+        Instr::CMovg(dst, src) => att_binary("cmovg", dst, src),
+        Instr::CMovge(dst, src) => att_binary("cmovge", dst, src),
+        Instr::CMovl(dst, src) => att_binary("cmovl", dst, src),
+        Instr::CMovle(dst, src) => att_binary("cmovle", dst, src),
+        // Bitwise
+        Instr::And(dst, src) => att_binary("and", dst, src),
+        Instr::Or(dst, src) => att_binary("or", dst, src),
+        Instr::Xor(dst, src) => att_binary("xor", dst, src),
+        Instr::Not(val) => format!("notq {}", val_to_att(val)),
+        Instr::Neg(val) => format!("negq {}", val_to_att(val)),
+        // Bit counting
+        Instr::Popcnt(dst, src) => att_binary("popcnt", dst, src),
+        Instr::Tzcnt(dst, src) => att_binary("tzcnt", dst, src),
+        // Address computation
+        Instr::Lea(dst, addr) => format!("leaq {}, %{}", val_to_att(addr), reg_to_str(dst)),
+        Instr::LeaLabel(dst, label, offset) => {
+            format!("leaq {label}+{offset}(%rip), %{}", reg_to_str(dst))
+        }
+        // Shifts
+        Instr::Sar(dst, shift_amount) => att_binary("sar", dst, shift_amount),
+        Instr::Shl(dst, shift_amount) => att_binary("shl", dst, shift_amount),
+        Instr::Shr(dst, shift_amount) => att_binary("shr", dst, shift_amount),
+        // Function calling
+        Instr::Push(val) => format!("pushq {}", val_to_att(val)),
+        Instr::Pop(val) => format!("popq {}", val_to_att(val)),
+        // Labels, jumps, calls and returns are written the same way in both syntaxes
+        Instr::Label(_)
+        | Instr::Jump(_)
+        | Instr::JumpEqual(_)
+        | Instr::JumpNotEqual(_)
+        | Instr::JumpNotZero(_)
+        | Instr::JumpGreaterEqual(_)
+        | Instr::JumpLess(_)
+        | Instr::JumpOverflow(_)
+        | Instr::Call(_)
+        | Instr::Ret() => instr_to_str(instr),
+        // Data
+        Instr::Section(Section::Text) => ".text".to_string(),
+        Instr::Section(Section::Data) => ".data".to_string(),
+        Instr::Align(n) => format!(".balign {n}"),
+        Instr::Quad(n) => format!(".quad {n}"),
+        Instr::QuadLabel(label, offset) => format!(".quad {label}+{offset}"),
+    }
+}
+
+// Formats a two-operand instruction in AT&T syntax, with the Intel destination operand last
+fn att_binary(mnemonic: &str, dst: &Val, src: &Val) -> String {
+    let suffix = match (dst, src) {
+        (Val::Reg(Reg::EDI), _) | (_, Val::Reg(Reg::EDI)) => "l",
+        (Val::Reg(Reg::CL), _) => "b",
+        _ => "q",
+    };
+    return format!(
+        "{mnemonic}{suffix} {}, {}",
+        val_to_att(src),
+        val_to_att(dst)
+    );
+}
+
+// Formats an assembly value as a String in AT&T syntax
+fn val_to_att(val: &Val) -> String {
+    match val {
+        Val::Imm(num) => format!("${num}"),
+        Val::Reg(reg) => format!("%{}", reg_to_str(reg)),
+        Val::RegOff(reg, 0) => format!("(%{})", reg_to_str(reg)),
+        Val::RegOff(reg, offset) => format!("{}(%{})", -1 * offset, reg_to_str(reg)),
+        Val::Scaled(base, index, scale, 0) => {
+            format!("(%{},%{},{scale})", reg_to_str(base), reg_to_str(index))
+        }
+        Val::Scaled(base, index, scale, disp) => {
+            format!(
+                "{disp}(%{},%{},{scale})",
+                reg_to_str(base),
+                reg_to_str(index)
+            )
+        }
+    }
+}
+
+// Returns the name of the register
+fn reg_to_str(reg: &Reg) -> &'static str {
+    match reg {
+        Reg::RAX => "rax",
+        Reg::RDI => "rdi",
+        Reg::RSI => "rsi",
+        Reg::RDX => "rdx",
+        Reg::RCX => "rcx",
+        Reg::R8 => "r8",
+        Reg::R9 => "r9",
+        Reg::RSP => "rsp",
+        Reg::RBP => "rbp",
+        Reg::RBX => "rbx",
+        Reg::R10 => "r10",
+        Reg::R11 => "r11",
+        Reg::R12 => "r12",
+        Reg::R13 => "r13",
+        Reg::R14 => "r14",
+        Reg::R15 => "r15",
+        Reg::EDI => "edi",
+        Reg::CL => "cl",
     }
 }
 
@@ -134,77 +329,26 @@ pub fn instr_to_str(instr: &Instr) -> String {
 fn val_to_str(val: &Val) -> String {
     match val {
         Val::Imm(num) => format!("{num}"),
-        Val::Reg(Reg::RAX) => format!("rax"),
-        Val::Reg(Reg::RBX) => format!("rbx"),
-        Val::Reg(Reg::RDI) => format!("rdi"),
-        Val::Reg(Reg::RSI) => format!("rsi"),
-        Val::Reg(Reg::RDX) => format!("rdx"),
-        Val::Reg(Reg::RSP) => format!("rsp"),
-        Val::Reg(Reg::RBP) => format!("rbp"),
-        Val::Reg(Reg::R10) => format!("r10"),
-        Val::Reg(Reg::R11) => format!("r11"),
-        Val::Reg(Reg::R12) => format!("r12"),
-        Val::Reg(Reg::R13) => format!("r13"),
-        Val::Reg(Reg::R14) => format!("r14"),
-        Val::Reg(Reg::R15) => format!("r15"),
-        Val::Reg(Reg::EDI) => format!("edi"),
-
-        Val::RegOff(Reg::RAX, offset) => {
+        Val::Reg(reg) => reg_to_str(reg).to_string(),
+        Val::RegOff(reg, offset) => {
             if *offset > 0 {
-                format!("[rax - {offset}]")
+                format!("[{} - {offset}]", reg_to_str(reg))
             } else if *offset < 0 {
-                format!("[rax + {}]", -1 * offset)
+                format!("[{} + {}]", reg_to_str(reg), -1 * offset)
             } else {
-                format!("[rax]")
+                format!("[{}]", reg_to_str(reg))
             }
         }
-        Val::RegOff(Reg::RSP, offset) => {
-            if *offset > 0 {
-                format!("[rsp - {offset}]")
-            } else if *offset < 0 {
-                format!("[rsp + {}]", -1 * offset)
+        Val::Scaled(base, index, scale, disp) => {
+            let (base, index) = (reg_to_str(base), reg_to_str(index));
+            if *disp > 0 {
+                format!("[{base} + {index}*{scale} + {disp}]")
+            } else if *disp < 0 {
+                format!("[{base} + {index}*{scale} - {}]", -1 * disp)
             } else {
-                format!("[rsp]")
+                format!("[{base} + {index}*{scale}]")
             }
         }
-        Val::RegOff(Reg::RBP, offset) => {
-            if *offset > 0 {
-                format!("[rbp - {offset}]")
-            } else if *offset < 0 {
-                format!("[rbp + {}]", -1 * offset)
-            } else {
-                format!("[rbp]")
-            }
-        }
-        Val::RegOff(Reg::RBX, offset) => {
-            if *offset > 0 {
-                format!("[rbx - {offset}]")
-            } else if *offset < 0 {
-                format!("[rbx + {}]", -1 * offset)
-            } else {
-                format!("[rbx]")
-            }
-        }
-        Val::RegOff(Reg::R10, offset) => {
-            if *offset > 0 {
-                format!("[r10 - {offset}]")
-            } else if *offset < 0 {
-                format!("[r10 + {}]", -1 * offset)
-            } else {
-                format!("[r10]")
-            }
-        }
-        Val::RegOff(Reg::R15, offset) => {
-            if *offset > 0 {
-                format!("[r15 - {offset}]")
-            } else if *offset < 0 {
-                format!("[r15 + {}]", -1 * offset)
-            } else {
-                format!("[r15]")
-            }
-        }
-
-        _ => panic!("Unhandled Instruction value: {val:?}"),
     }
 }
 
@@ -217,3 +361,20 @@ pub fn instructions_to_string(instrs: Vec<Instr>) -> String {
     }
     return strs.join("\n");
 }
+
+// Writes the instructions line by line, without building the whole program in memory
+pub fn write_instructions<W: Write>(w: &mut W, instrs: &[Instr]) -> io::Result<()> {
+    return write_instructions_in(w, instrs, Syntax::Intel);
+}
+
+// Writes the instructions line by line in the given syntax
+pub fn write_instructions_in<W: Write>(
+    w: &mut W,
+    instrs: &[Instr],
+    syntax: Syntax,
+) -> io::Result<()> {
+    for instr in instrs.iter() {
+        writeln!(w, "{}", instr_to_str_in(instr, syntax))?;
+    }
+    return Ok(());
+}
@@ -7,53 +7,228 @@
  */
 use im::HashMap;
 use im::HashSet;
+use std::cell::{Cell, RefCell};
+use std::thread;
 
+use crate::analysis::{binds, sub_exprs};
 use crate::assembly::*;
 use crate::constants::*;
+use crate::error::CompileError;
 use crate::syntax::*;
 
-static mut LABEL_CTR: usize = 0;
-
 // Contains contextual information the compiler uses to compile each expression.
 #[derive(Debug, Clone)]
-struct Context<'a> {
-    si: i64,                                   // stack index
+struct Context<'a, 'p> {
+    si: i64,                                       // stack index
     env: &'a HashMap<String, i64>, // maps ids to their (positive) stack offsets relative to the stack pointer
-    break_label: &'a str,          // current label to break to
-    fun_map: &'a HashMap<String, Vec<String>>, // maps each function name to its parameters
+    loops: &'a HashMap<String, LoopLabels>, // maps loop names to their labels; "" is the innermost loop
+    fun_map: &'a HashMap<String, &'p FunDef>, // maps each function name to its definition
     compiling_main: bool, // whether this context is being used to compile the main expression
+    label_ctr: &'a Cell<usize>, // counter used to generate unique labels within a function
+    label_prefix: &'a str, // prepended to generated labels so each function has its own namespace
+    stable_labels: Option<&'a StableLabels>, // replaces the counter when labels are named by path
+    label_path: &'a str,  // path of the expression being compiled, used by stable labels
+    loop_targets: &'a HashMap<String, LoopTarget>, // maps each named let in scope to its loop
+    structs: &'a HashMap<String, StructInfo>, // maps each struct name to its type id and fields
+    unchecked: bool,      // whether to omit vector bounds checks
+    numeric_equal: bool,  // whether = only compares numbers
+    input: Option<i64>,   // value of input0 known at compile time, as represented at runtime
+    constants: &'a Constants, // values computed at compile time
+    hoisted: &'a HashMap<*const Expr, i64>, // stack offsets of loop-invariant expressions computed before their loop
+    loop_invariants: &'a LoopInvariants<'p>, // loop-invariant expressions of each loop in the body
+    static_vecs: &'a HashMap<usize, String>, // labels of the vector literals in the data section
+}
+
+// Expression paths used to name the labels of a function, so that the labels of an expression do
+// not depend on the code compiled before it
+#[derive(Debug)]
+struct StableLabels {
+    paths: HashMap<*const Expr, String>, // dot-separated child indices from the body, which is 0
+    used: RefCell<HashSet<String>>,      // labels already generated in the function
+}
+
+// Labels at the start and end of a loop, used by continue and break
+#[derive(Debug, Clone)]
+struct LoopLabels {
+    start: String,
+    end: String,
+}
+
+// Where a tail call to a named let jumps to
+#[derive(Debug, Clone)]
+struct LoopTarget {
+    label: String,             // label at the start of the named let body
+    binding_offsets: Vec<i64>, // stack offsets of the named let bindings
+}
+
+// Type id and fields of a struct. Struct values are vectors whose first element is the type id.
+#[derive(Debug, Clone)]
+struct StructInfo {
+    id: i64,
+    fields: Vec<String>,
+}
+
+// Values of a function body that are known at compile time, keyed by the address of the expression
+#[derive(Debug, Default)]
+struct Constants {
+    values: HashMap<*const Expr, i64>, // values of constant arithmetic and of ids bound to constants
+    propagated: HashSet<*const Expr>,  // let binding values substituted at every use of the id
+}
+
+// Loop-invariant expressions of each loop of a function body or the main expression, keyed by the
+// address of the loop body
+type LoopInvariants<'a> = HashMap<*const Expr, Vec<&'a Expr>>;
+
+// Vector literals that are laid out in the data section instead of being allocated on the heap
+#[derive(Debug, Default)]
+struct StaticVectors {
+    // Label of each literal, keyed by the address of the expression. The address is stored as an
+    // integer so that the map can be shared with the threads compiling functions.
+    labels: HashMap<usize, String>,
+    // Label of each distinct vector, keyed by its words, so identical literals share storage
+    interned: HashMap<Vec<StaticWord>, String>,
+    data: Vec<Instr>, // the words of the vectors, each after its label
 }
 
-// Returns a tuple of (instructions for function definitions, instructions for main expression)
-pub fn compile_program(prog: &Program, start_label: String) -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
-    instrs.append(&mut compile_error_instrs());
+// Word of a vector in the data section
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum StaticWord {
+    Value(i64),     // the size or an element that is not a vector, as represented at runtime
+    Vector(String), // a nested vector, by its label
+}
+
+// Rough number of instructions generated per expression, used to size the instruction buffer up
+// front instead of growing it while compiling
+const INSTRS_PER_EXPR: usize = 8;
+
+// Options that control how a program is compiled
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub parallel: bool, // compile function definitions on multiple threads
+    // Omit the index bounds checks of vec-get and vec-set!. This is not memory safe: an out of
+    // bounds index reads or overwrites whatever is next to the vector on the heap.
+    pub unchecked: bool,
+    // Make = compare only numbers, as in most Lisps, raising an invalid argument error for any
+    // other operand. By default = compares any two values of the same type, vectors by identity.
+    pub numeric_equal: bool,
+    // Value of the first input, as represented at runtime, to compile into the program instead of
+    // reading it when the program runs. The other inputs are still read at runtime.
+    pub input: Option<i64>,
+    // Name labels after the position of their expression in the function instead of numbering
+    // them in compilation order, so that changing one expression leaves the labels of the others
+    // unchanged
+    pub stable_labels: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            parallel: true,
+            unchecked: false,
+            numeric_equal: false,
+            input: None,
+            stable_labels: false,
+        }
+    }
+}
+
+// Compiles the program with the default options
+pub fn compile_program(prog: &Program, start_label: String) -> Result<Vec<Instr>, CompileError> {
+    return compile_program_with_options(prog, start_label, &CompileOptions::default());
+}
+
+// Returns the instructions for the function definitions followed by the main expression
+pub fn compile_program_with_options(
+    prog: &Program,
+    start_label: String,
+    options: &CompileOptions,
+) -> Result<Vec<Instr>, CompileError> {
+    let num_exprs = count_exprs(&prog.main)
+        + prog
+            .defs
+            .iter()
+            .map(|def| count_exprs(&def.body))
+            .sum::<usize>();
+    let mut instrs: Vec<Instr> = Vec::with_capacity(num_exprs * INSTRS_PER_EXPR);
+    compile_error_instrs(&mut instrs);
 
-    // Maps each function name to its parameters.
+    // Maps each function name to its definition.
     // This map enables checking for:
     // 1. calling undefined functions
     // 2. calling a function with the wrong number of arguments
-    let mut fun_map: HashMap<String, Vec<String>> = HashMap::new();
+    let mut fun_map: HashMap<String, &FunDef> = HashMap::new();
 
-    for def in prog.defs.iter() {
-        if fun_map.contains_key(&def.name) {
-            panic!("Function {} already defined", def.name);
+    // Maps each struct name to its type id and fields, used to check struct operations
+    let mut struct_map: HashMap<String, StructInfo> = HashMap::new();
+    for (id, def) in prog.structs.iter().enumerate() {
+        if struct_map.contains_key(&def.name) {
+            return Err(CompileError::DuplicateStruct(def.name.to_string()));
+        }
+
+        let mut seen_fields: HashSet<String> = HashSet::new();
+        for field in def.fields.iter() {
+            if seen_fields.contains(field) {
+                return Err(CompileError::DuplicateField(field.to_string()));
+            }
+            seen_fields = seen_fields.update(field.to_string());
+        }
+        let info = StructInfo {
+            id: id as i64,
+            fields: def.fields.to_vec(),
+        };
+        struct_map = struct_map.update(def.name.to_string(), info);
+    }
+
+    for (i, def) in prog.defs.iter().enumerate() {
+        if let Some(first_def) = fun_map.get(&def.name) {
+            let first = prog.defs.iter().position(|d| d.name == def.name).unwrap();
+            return Err(CompileError::DuplicateFunction {
+                name: def.name.to_string(),
+                first: first + 1,
+                first_params: first_def.params.to_vec(),
+                redefinition: i + 1,
+                params: def.params.to_vec(),
+            });
         }
 
         let mut seen_params: HashSet<String> = HashSet::new();
-        for param in def.params.iter() {
+        for param in def.params.iter().chain(def.rest.iter()) {
             if seen_params.contains(param) {
-                panic!("Duplicate parameter {param}");
+                return Err(CompileError::DuplicateParameter(param.to_string()));
             }
             seen_params = seen_params.update(param.to_string());
         }
-        fun_map = fun_map.update(def.name.to_string(), def.params.to_vec());
+
+        // Callers pass the default values themselves, so they must not depend on the function
+        let first_default = def.params.len() - def.defaults.len();
+        for (param, default) in def.params[first_default..].iter().zip(def.defaults.iter()) {
+            default_arg(param, default)?;
+        }
+        fun_map = fun_map.update(def.name.to_string(), def);
     }
 
-    instrs.append(&mut compile_funs(&prog.defs, &fun_map));
+    // Check every call before generating any code, so the first bad call is reported even when
+    // it is in a branch that is never taken
+    for def in prog.defs.iter() {
+        check_calls(&def.body, &fun_map, &HashMap::new())?;
+    }
+    check_calls(&prog.main, &fun_map, &HashMap::new())?;
+
+    let static_vecs = find_static_vectors(prog);
+    compile_funs(
+        &prog.defs,
+        &fun_map,
+        &struct_map,
+        &static_vecs.labels,
+        options,
+        &mut instrs,
+    )?;
     instrs.push(Instr::Label(start_label.to_string()));
 
-    let locals = depth(&prog.main);
+    let mut main_loops = HashMap::new();
+    find_loop_invariants(&prog.main, &mut main_loops);
+    let locals = depth(&prog.main, &main_loops);
+    let constants = find_constants(&prog.main, options.input)?;
     let callee_saved = [
         Val::Reg(Reg::RBP),
         Val::Reg(Reg::R11),
@@ -61,70 +236,177 @@ pub fn compile_program(prog: &Program, start_label: String) -> Vec<Instr> {
         Val::Reg(Reg::R13),
     ];
 
-    instrs.append(&mut fun_entry(locals, &callee_saved));
+    fun_entry(locals, &callee_saved, &mut instrs);
     instrs.push(Instr::Mov(Val::Reg(Reg::R15), Val::Reg(Reg::RSI)));
     instrs.push(Instr::Mov(Val::Reg(Reg::R13), Val::Reg(Reg::RDI)));
     instrs.push(Instr::Mov(Val::Reg(Reg::R14), Val::Reg(Reg::RDX)));
     instrs.push(Instr::Mov(Val::Reg(Reg::R11), Val::Reg(Reg::RSI)));
 
     // Main body
-    instrs.append(&mut compile_expr(
+    let main_labels = stable_labels(&prog.main, options);
+    compile_expr(
         &prog.main,
         &Context {
             si: 0,
             env: &HashMap::default(),
-            break_label: "",
+            loops: &HashMap::new(),
             fun_map: &fun_map,
             compiling_main: true,
+            label_ctr: &Cell::new(0),
+            label_prefix: "",
+            stable_labels: main_labels.as_ref(),
+            label_path: "",
+            loop_targets: &HashMap::new(),
+            structs: &struct_map,
+            unchecked: options.unchecked,
+            numeric_equal: options.numeric_equal,
+            input: options.input,
+            constants: &constants,
+            hoisted: &HashMap::new(),
+            loop_invariants: &main_loops,
+            static_vecs: &static_vecs.labels,
         },
-    ));
-    instrs.append(&mut fun_exit(locals, &callee_saved));
+        &mut instrs,
+    )?;
+    // The final heap pointer is returned alongside the value, for the runtime to report heap usage
+    instrs.push(Instr::Mov(Val::Reg(Reg::RDX), Val::Reg(Reg::R15)));
+    fun_exit(locals, &callee_saved, &mut instrs);
+
+    if !static_vecs.data.is_empty() {
+        instrs.push(Instr::Section(Section::Data));
+        instrs.extend(static_vecs.data);
+    }
+
+    return Ok(instrs);
+}
 
-    return instrs;
+// Compile all functions.
+// Functions are independent of each other, so with parallel set they are split into chunks that are
+// compiled on separate threads. The output is the same as compiling them in order.
+fn compile_funs<'p>(
+    funs: &'p Vec<FunDef>,
+    fun_map: &HashMap<String, &'p FunDef>,
+    struct_map: &HashMap<String, StructInfo>,
+    static_vecs: &HashMap<usize, String>,
+    options: &CompileOptions,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    let num_threads = match thread::available_parallelism() {
+        Ok(n) if options.parallel => n.get().min(funs.len()),
+        _ => 1,
+    };
+    if num_threads <= 1 {
+        return compile_fun_chunk(funs, fun_map, struct_map, static_vecs, options, instrs);
+    }
+
+    let chunk_size = (funs.len() + num_threads - 1) / num_threads;
+    let results: Vec<Result<Vec<Instr>, CompileError>> = thread::scope(|scope| {
+        let handles: Vec<_> = funs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                thread::Builder::new()
+                    .stack_size(COMPILER_STACK_SIZE)
+                    .spawn_scoped(scope, move || {
+                        let num_exprs: usize = chunk.iter().map(|fun| count_exprs(&fun.body)).sum();
+                        let mut instrs = Vec::with_capacity(num_exprs * INSTRS_PER_EXPR);
+                        compile_fun_chunk(
+                            chunk,
+                            fun_map,
+                            struct_map,
+                            static_vecs,
+                            options,
+                            &mut instrs,
+                        )?;
+                        Ok(instrs)
+                    })
+                    .expect("could not spawn compiler thread")
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("compiler thread panicked"))
+            .collect()
+    });
+
+    // Report the error of the earliest function that failed
+    for result in results {
+        instrs.append(&mut result?);
+    }
+    return Ok(());
 }
 
-// Compile all functions
-fn compile_funs(funs: &Vec<FunDef>, fun_map: &HashMap<String, Vec<String>>) -> Vec<Instr> {
-    let mut instrs = Vec::new();
+// Compile the given functions in order
+fn compile_fun_chunk<'p>(
+    funs: &'p [FunDef],
+    fun_map: &HashMap<String, &'p FunDef>,
+    struct_map: &HashMap<String, StructInfo>,
+    static_vecs: &HashMap<usize, String>,
+    options: &CompileOptions,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
     for fun in funs.iter() {
-        instrs.append(&mut compile_fun(fun, fun_map));
+        compile_fun(fun, fun_map, struct_map, static_vecs, options, instrs)?;
     }
-    return instrs;
+    return Ok(());
 }
 
-// Compile given function
-fn compile_fun(fun: &FunDef, fun_map: &HashMap<String, Vec<String>>) -> Vec<Instr> {
-    let mut instrs = Vec::new();
-    let locals = depth(&fun.body);
+// Compile given function, appending its instructions
+fn compile_fun<'p>(
+    fun: &'p FunDef,
+    fun_map: &HashMap<String, &'p FunDef>,
+    struct_map: &HashMap<String, StructInfo>,
+    static_vecs: &HashMap<usize, String>,
+    options: &CompileOptions,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    let mut loops = HashMap::new();
+    find_loop_invariants(&fun.body, &mut loops);
+    let locals = depth(&fun.body, &loops);
+    let constants = find_constants(&fun.body, None)?;
     let callee_saved = &[Val::Reg(Reg::RBP)];
 
     instrs.push(Instr::Label(fun.name.to_string()));
-    instrs.append(&mut fun_entry(locals, callee_saved));
+    fun_entry(locals, callee_saved, instrs);
 
-    // The " + 2 " skips over the saved RBP and return address
+    // The " + 2 " skips over the saved RBP and return address.
+    // The caller passes the vector of surplus arguments after the others.
     let env: HashMap<String, i64> = fun
         .params
         .iter()
+        .chain(fun.rest.iter())
         .enumerate()
         .map(|(i, param)| (param.to_string(), (-1) * WORD_SIZE * (i as i64 + 2)))
         .collect();
+    let label_prefix = format!("{}_", fun.name);
+    let labels = stable_labels(&fun.body, options);
     let ctxt = Context {
         si: 0,
         env: &env,
-        break_label: "",
+        loops: &HashMap::new(),
         fun_map: fun_map,
         compiling_main: false,
+        label_ctr: &Cell::new(0),
+        label_prefix: &label_prefix,
+        stable_labels: labels.as_ref(),
+        label_path: "",
+        loop_targets: &HashMap::new(),
+        structs: struct_map,
+        unchecked: options.unchecked,
+        numeric_equal: options.numeric_equal,
+        input: options.input,
+        constants: &constants,
+        hoisted: &HashMap::new(),
+        loop_invariants: &loops,
+        static_vecs,
     };
-    instrs.append(&mut compile_expr(&fun.body, &ctxt));
-    instrs.append(&mut fun_exit(locals, callee_saved));
+    compile_expr(&fun.body, &ctxt, instrs)?;
+    fun_exit(locals, callee_saved, instrs);
 
-    return instrs;
+    return Ok(());
 }
 
 // Instructions for the beginning of every function.
-fn fun_entry(locals: u32, callee_saved: &[Val]) -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
-
+fn fun_entry(locals: u32, callee_saved: &[Val], instrs: &mut Vec<Instr>) {
     for reg in callee_saved {
         instrs.push(Instr::Push(*reg));
     }
@@ -137,20 +419,19 @@ fn fun_entry(locals: u32, callee_saved: &[Val]) -> Vec<Instr> {
     ));
 
     // Set all of the allocated stack space words to NIL; this ensures we don't
-    // try to process garbage "heap" values in garbage collection
-    // for i in 0..size {
-    //     instrs.push(Instr::Mov(
-    //         Val::RegOff(Reg::RBP, WORD_SIZE * (1 + i as i64)),
-    //         Val::Imm(NIL_VAL),
-    //     ));
-    // }
-
-    return instrs;
+    // try to process garbage "heap" values in garbage collection.
+    // Locals live below RBP at [rbp - 8 * si] for si in 1..=locals, and the alignment
+    // padding word, if any, is the last slot at [rbp - 8 * size].
+    for si in 1..=size {
+        instrs.push(Instr::Mov(
+            Val::RegOff(Reg::RBP, WORD_SIZE * si as i64),
+            Val::Imm(NIL_VAL),
+        ));
+    }
 }
 
 // Instructions for the end of every function
-fn fun_exit(locals: u32, callee_saved: &[Val]) -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
+fn fun_exit(locals: u32, callee_saved: &[Val], instrs: &mut Vec<Instr>) {
     let size = frame_size(locals, callee_saved);
     instrs.push(Instr::Add(
         Val::Reg(Reg::RSP),
@@ -160,8 +441,6 @@ fn fun_exit(locals: u32, callee_saved: &[Val]) -> Vec<Instr> {
         instrs.push(Instr::Pop(*reg));
     }
     instrs.push(Instr::Ret());
-
-    return instrs;
 }
 
 // Returns amount of words to subtract for RSP
@@ -176,15 +455,39 @@ fn frame_size(locals: u32, callee_saved: &[Val]) -> u32 {
     }
 }
 
-// Recursively compiles an expression into a list of assembly instruction
-fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
+// Recursively compiles an expression, appending its assembly instructions to instrs
+fn compile_expr(expr: &Expr, ctxt: &Context, instrs: &mut Vec<Instr>) -> Result<(), CompileError> {
     // println!("Expr is {:?}, si is {}", expr, ctxt.si);
 
+    // With stable labels, the labels generated for this expression are named after its path
+    let path_ctxt;
+    let ctxt = match ctxt
+        .stable_labels
+        .and_then(|labels| labels.paths.get(&(expr as *const Expr)))
+    {
+        Some(path) => {
+            path_ctxt = Context {
+                label_path: path,
+                ..*ctxt
+            };
+            &path_ctxt
+        }
+        None => ctxt,
+    };
+
+    // Loop-invariant expressions were already computed before the loop
+    if let Some(stack_offset) = ctxt.hoisted.get(&(expr as *const Expr)) {
+        instrs.push(Instr::Mov(
+            Val::Reg(Reg::RAX),
+            Val::RegOff(Reg::RBP, *stack_offset),
+        ));
+        return Ok(());
+    }
+
     match expr {
         Expr::Number(num) => {
             if int_overflow(*num) {
-                panic!("Invalid: number must be in the range of a 63-bit signed integer");
+                return Err(CompileError::NumberOverflow);
             } else {
                 // Convert the number to our internal representation
                 let num = *num << 1;
@@ -193,61 +496,69 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
         }
         Expr::Boolean(false) => instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL))),
         Expr::Boolean(true) => instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(TRUE_VAL))),
-        Expr::Input => {
+        Expr::Input(index) => {
             if !ctxt.compiling_main {
-                panic!("Invalid: input can only be used in the main expression");
+                return Err(CompileError::InputOutsideMain);
+            }
+            if let (0, Some(value)) = (index, ctxt.input) {
+                instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(value)));
+                return Ok(());
             }
-            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Reg(Reg::R13)));
+            // R13 points to the array of inputs
+            let offset = (*index as i64) * WORD_SIZE;
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RAX),
+                Val::RegOff(Reg::R13, -offset),
+            ));
+        }
+        Expr::Assert(e) => {
+            // Fail if the value is false, otherwise the value is the result
+            compile_expr(e, ctxt, instrs)?;
+            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
+            instrs.push(Instr::JumpEqual(ASSERTION_FAILED_LABEL.to_string()));
+        }
+        Expr::UserError(e) => {
+            // The code must be a number; it is passed to snek_error untagged
+            compile_expr(e, ctxt, instrs)?;
+            is_number_with_error(instrs);
+            instrs.push(Instr::Sar(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RDI), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RSI), Val::Reg(Reg::R15)));
+            instrs.push(Instr::Call("snek_error".to_string()));
+        }
+        Expr::Read => {
+            // The runtime reads and parses a line from stdin
+            instrs.push(Instr::Call(String::from("snek_read")));
         }
         Expr::Nil => instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(NIL_VAL))),
 
+        // Ids bound to constants are replaced by their value
+        Expr::Id(_) if ctxt.constants.values.contains_key(&(expr as *const Expr)) => {
+            let num = ctxt.constants.values[&(expr as *const Expr)];
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(num << 1)));
+        }
         Expr::Id(s) => {
             let stack_offset = match ctxt.env.get(s) {
                 Some(offset) => offset,
-                None => panic!("Unbound variable identifier {s}"),
+                None => return Err(CompileError::UnboundId(s.to_string())),
             };
             instrs.push(Instr::Mov(
                 Val::Reg(Reg::RAX),
                 Val::RegOff(Reg::RBP, *stack_offset),
             ));
         }
-        Expr::UnOp(op, e) => instrs.append(&mut compile_unary_op(*op, e, ctxt)),
-        Expr::BinOp(op, e1, e2) => instrs.append(&mut compile_binary_op(*op, e1, e2, ctxt)),
+        // Arithmetic on constants is computed at compile time
+        Expr::UnOp(op, e) => match ctxt.constants.values.get(&(expr as *const Expr)) {
+            Some(num) => instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(*num << 1))),
+            None => compile_unary_op(*op, e, ctxt, instrs)?,
+        },
+        Expr::BinOp(op, e1, e2) => match ctxt.constants.values.get(&(expr as *const Expr)) {
+            Some(num) => instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(*num << 1))),
+            None => compile_binary_op(*op, e1, e2, ctxt, instrs)?,
+        },
 
         Expr::Let(bindings, body) => {
-            let mut new_env: HashMap<String, i64> = ctxt.env.clone();
-            let mut locally_bound_ids: HashSet<String> = HashSet::new();
-
-            for (index, (id, e)) in bindings.iter().enumerate() {
-                if locally_bound_ids.contains(id) {
-                    panic!("Duplicate binding");
-                }
-
-                let stack_index = ctxt.si + 1 + index as i64;
-                let stack_offset = stack_index * WORD_SIZE;
-
-                // Compile the instructions of the let binding.
-                let new_ctxt = Context {
-                    si: ctxt.si + index as i64,
-                    env: &new_env,
-                    ..*ctxt
-                };
-                let mut e_instrs = compile_expr(e, &new_ctxt);
-                instrs.append(&mut e_instrs);
-
-                // Store the let-binded variable on the stack
-                instrs.push(Instr::Mov(
-                    Val::RegOff(Reg::RBP, stack_offset),
-                    Val::Reg(Reg::RAX),
-                ));
-
-                // Track which identifiers have been bound locally.
-                locally_bound_ids = locally_bound_ids.update(id.to_string());
-
-                // Update the environment mapping of identifier -> memory location.
-                // IMPORTANT: This must be done after compiling the let expression.
-                new_env = new_env.update(id.to_string(), stack_offset);
-            }
+            let new_env = compile_bindings(bindings, ctxt, instrs)?;
 
             // The body is offset by the number of let bindings at the top level.
             let body_stack_index = ctxt.si + bindings.len() as i64;
@@ -257,180 +568,333 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 ..*ctxt
             };
 
-            instrs.append(&mut compile_expr(body, &new_ctxt));
+            compile_expr(body, &new_ctxt, instrs)?;
+        }
+        Expr::NamedLet(name, bindings, body) => {
+            check_tail_calls(name, body, true)?;
+            let new_env = compile_bindings(bindings, ctxt, instrs)?;
+
+            // Tail calls to the named let update the bindings and jump back to the start of the body
+            let target = LoopTarget {
+                label: get_new_label("named_let", ctxt),
+                binding_offsets: (0..bindings.len() as i64)
+                    .map(|i| (ctxt.si + 1 + i) * WORD_SIZE)
+                    .collect(),
+            };
+            instrs.push(Instr::Label(target.label.clone()));
+            let loop_targets = ctxt.loop_targets.update(name.to_string(), target);
+
+            let new_ctxt = Context {
+                si: ctxt.si + bindings.len() as i64,
+                env: &new_env,
+                loop_targets: &loop_targets,
+                ..*ctxt
+            };
+            compile_expr(body, &new_ctxt, instrs)?;
         }
 
         Expr::If(cond, then_ex, else_ex) => {
-            let end_label = get_new_label("ifend");
-            let else_label = get_new_label("ifelse");
+            let end_label = get_new_label("ifend", ctxt);
+            let else_label = get_new_label("ifelse", ctxt);
 
             // Evaluate the condition
-            instrs.append(&mut compile_expr(cond, ctxt));
+            compile_expr(cond, ctxt, instrs)?;
 
             // If the condition evaluated to false, jump to the else branch.
             instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
             instrs.push(Instr::JumpEqual(else_label.clone()));
 
             // If the condition evaluated to any other value, continue on with the then branch.
-            instrs.append(&mut compile_expr(then_ex, &Context { ..*ctxt }));
+            compile_expr(then_ex, &Context { ..*ctxt }, instrs)?;
             // Jump to the end of the if statement
             instrs.push(Instr::Jump(end_label.clone()));
 
             // Insert the else branch label
             instrs.push(Instr::Label(else_label.clone()));
-            instrs.append(&mut compile_expr(else_ex, &Context { ..*ctxt }));
+            compile_expr(else_ex, &Context { ..*ctxt }, instrs)?;
 
             // Insert the end of the if statement label
             instrs.push(Instr::Label(end_label.clone()));
         }
         Expr::Block(exprs) => {
             for e in exprs.iter() {
-                instrs.append(&mut compile_expr(e, ctxt));
+                compile_expr(e, ctxt, instrs)?;
             }
         }
         Expr::Set(name, e) => {
             let stack_offset = match ctxt.env.get(name) {
                 Some(offset) => *offset,
-                None => panic!("Unbound variable identifier {name}"),
+                None => return Err(CompileError::UnboundId(name.to_string())),
             };
 
             // Evaluate expression
-            instrs.append(&mut compile_expr(e, ctxt));
+            compile_expr(e, ctxt, instrs)?;
             // Update value of variable
             instrs.push(Instr::Mov(
                 Val::RegOff(Reg::RBP, stack_offset),
                 Val::Reg(Reg::RAX),
             ));
         }
+        Expr::SetGet(name, e) => {
+            let stack_offset = match ctxt.env.get(name) {
+                Some(offset) => *offset,
+                None => return Err(CompileError::UnboundId(name.to_string())),
+            };
+
+            // Load the old value into RBX before storing the new one, then return the old value
+            compile_expr(e, ctxt, instrs)?;
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RBX),
+                Val::RegOff(Reg::RBP, stack_offset),
+            ));
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
+        }
+
+        Expr::Loop(name, e) => {
+            let start_label = get_new_label("loop", ctxt);
+            let end_label = get_new_label("endloop", ctxt);
+
+            // Unnamed breaks and continues go to this loop, as do those using its name
+            let labels = LoopLabels {
+                start: start_label.clone(),
+                end: end_label.clone(),
+            };
+            let mut loops = ctxt.loops.update(String::new(), labels.clone());
+            if let Some(name) = name {
+                loops = loops.update(name.to_string(), labels);
+            }
+
+            // Compute the loop-invariant expressions once, storing them after the current stack index
+            let no_invariants = Vec::new();
+            let invariants = ctxt
+                .loop_invariants
+                .get(&(&**e as *const Expr))
+                .unwrap_or(&no_invariants);
+            let mut hoisted = ctxt.hoisted.clone();
+            for (i, invariant) in invariants.iter().enumerate() {
+                let si = ctxt.si + i as i64;
+                compile_expr(
+                    invariant,
+                    &Context {
+                        si,
+                        hoisted: &hoisted,
+                        ..*ctxt
+                    },
+                    instrs,
+                )?;
+                let stack_offset = (si + 1) * WORD_SIZE;
+                instrs.push(Instr::Mov(
+                    Val::RegOff(Reg::RBP, stack_offset),
+                    Val::Reg(Reg::RAX),
+                ));
+                hoisted = hoisted.update(*invariant as *const Expr, stack_offset);
+            }
 
-        Expr::Loop(e) => {
-            let start_label = get_new_label("loop");
-            let end_label = get_new_label("endloop");
             instrs.push(Instr::Label(start_label.clone()));
-            instrs.append(&mut compile_expr(
+            compile_expr(
                 e,
                 &Context {
-                    break_label: &end_label,
+                    si: ctxt.si + invariants.len() as i64,
+                    loops: &loops,
+                    hoisted: &hoisted,
                     ..*ctxt
                 },
-            ));
+                instrs,
+            )?;
             instrs.push(Instr::Jump(start_label.clone()));
             instrs.push(Instr::Label(end_label.clone()));
         }
-        Expr::Break(e) => {
-            if ctxt.break_label.is_empty() {
-                panic!("Error: break without surrounding loop");
-            }
-            instrs.append(&mut compile_expr(e, ctxt));
-            instrs.push(Instr::Jump(ctxt.break_label.to_string()));
+        Expr::Break(name, e) => {
+            let break_label = match (name, ctxt.loops.get(name.as_deref().unwrap_or(""))) {
+                (_, Some(labels)) => labels.end.to_string(),
+                (None, None) => return Err(CompileError::BreakOutsideLoop),
+                (Some(name), None) => return Err(CompileError::UnknownLoop(name.to_string())),
+            };
+            compile_expr(e, ctxt, instrs)?;
+            instrs.push(Instr::Jump(break_label));
+        }
+        Expr::Continue(name) => {
+            let start_label = match (name, ctxt.loops.get(name.as_deref().unwrap_or(""))) {
+                (_, Some(labels)) => labels.start.to_string(),
+                (None, None) => return Err(CompileError::ContinueOutsideLoop),
+                (Some(name), None) => return Err(CompileError::UnknownLoop(name.to_string())),
+            };
+            instrs.push(Instr::Jump(start_label));
         }
 
         // Function call
         Expr::Call(name, args) => {
-            if !ctxt.fun_map.contains_key(name) {
-                panic!("Invalid: undefined function {name}");
-            }
-            let expected_num = ctxt.fun_map.get(name).unwrap().len();
-            if expected_num != args.len() {
-                panic!(
-                    "Invalid: function {name} called with {} args, expected {}",
-                    args.len(),
-                    expected_num
-                );
-            }
+            // The callee and number of arguments were checked by check_calls
+            let target = ctxt.loop_targets.get(name);
 
+            // Each argument is stored in the slot after the previous one, so it can use that slot
+            // and the ones after it while it is computed
             let mut curr_ctxt = ctxt.clone();
             for arg in args {
-                let next_ctxt = Context {
+                compile_expr(arg, &curr_ctxt, instrs)?;
+                curr_ctxt = Context {
                     si: curr_ctxt.si + 1,
                     ..curr_ctxt
                 };
-                instrs.append(&mut compile_expr(arg, &next_ctxt));
                 instrs.push(Instr::Mov(
-                    Val::RegOff(Reg::RBP, WORD_SIZE * next_ctxt.si),
+                    Val::RegOff(Reg::RBP, WORD_SIZE * curr_ctxt.si),
                     Val::Reg(Reg::RAX),
                 ));
-                curr_ctxt = next_ctxt;
             }
 
             let stack_offsets: Vec<i64> = (ctxt.si..ctxt.si + args.len() as i64)
                 .map(|i| WORD_SIZE * (i + 1))
                 .collect();
 
-            let mut fun_args: Vec<Val> = stack_offsets
-                .iter()
-                .map(|off| Val::RegOff(Reg::RBP, *off))
-                .collect();
-
-            // Maintain RSP alignment if needed by pushing an extra value
-            if fun_args.len() % 2 != 0 {
-                fun_args.push(Val::Imm(NIL_VAL));
-            }
-
-            // Push computed arguments onto stack for function call
-            for fun_arg in fun_args.iter().rev() {
-                instrs.push(Instr::Push(*fun_arg));
+            if let Some(target) = target {
+                // Tail call to a named let: rebind its variables and loop
+                for (arg_offset, binding_offset) in
+                    stack_offsets.iter().zip(target.binding_offsets.iter())
+                {
+                    instrs.push(Instr::Mov(
+                        Val::Reg(Reg::RAX),
+                        Val::RegOff(Reg::RBP, *arg_offset),
+                    ));
+                    instrs.push(Instr::Mov(
+                        Val::RegOff(Reg::RBP, *binding_offset),
+                        Val::Reg(Reg::RAX),
+                    ));
+                }
+                instrs.push(Instr::Jump(target.label.clone()));
+            } else {
+                let def = ctxt.fun_map[name];
+                let num_params = def.params.len().min(args.len());
+                let mut fun_args: Vec<Val> = stack_offsets[..num_params]
+                    .iter()
+                    .map(|off| Val::RegOff(Reg::RBP, *off))
+                    .collect();
+                // Parameters left out of the call get their default values
+                let first_default = def.params.len() - def.defaults.len();
+                for i in num_params..def.params.len() {
+                    fun_args.push(default_arg(
+                        &def.params[i],
+                        &def.defaults[i - first_default],
+                    )?);
+                }
+                // A variadic function gets the surplus arguments in a new vector, passed last
+                if def.rest.is_some() {
+                    compile_rest_vec(&stack_offsets[num_params..], instrs);
+                    fun_args.push(Val::Reg(Reg::RAX));
+                }
+                fun_call(name, fun_args, instrs);
             }
-
-            // Call function
-            instrs.push(Instr::Call(name.to_string()));
-            // Reset stack pointer
-            instrs.push(Instr::Add(
-                Val::Reg(Reg::RSP),
-                Val::Imm(WORD_SIZE * fun_args.len() as i64),
-            ));
+        }
+        Expr::Vec(_)
+            if ctxt
+                .static_vecs
+                .contains_key(&(expr as *const Expr as usize)) =>
+        {
+            let label = &ctxt.static_vecs[&(expr as *const Expr as usize)];
+            instrs.push(Instr::LeaLabel(Reg::RAX, label.to_string(), 1));
         }
         Expr::Vec(args) => {
-            // Save the current value of the heap pointer on the stack; this is the return value.
-            let vec_stack_offset = (ctxt.si + 1) * WORD_SIZE;
+            let elems: Vec<&Expr> = args.iter().collect();
+            compile_vec(&elems, ctxt, instrs)?;
+        }
+        Expr::MakeStruct(name, args) => {
+            let info = match ctxt.structs.get(name) {
+                Some(info) => info,
+                None => return Err(CompileError::UndefinedStruct(name.to_string())),
+            };
+            if args.len() != info.fields.len() {
+                return Err(CompileError::WrongFieldCount {
+                    name: name.to_string(),
+                    found: args.len(),
+                    expected: info.fields.len(),
+                });
+            }
+            // The type id is stored as a hidden first element, followed by the fields
+            let type_id = Expr::Number(info.id);
+            let elems: Vec<&Expr> = std::iter::once(&type_id).chain(args.iter()).collect();
+            compile_vec(&elems, ctxt, instrs)?;
+        }
+        Expr::GetField(e, field) => {
+            compile_expr(e, ctxt, instrs)?;
+            struct_field_address(field, ctxt, instrs)?;
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RBX, 0)));
+        }
+        Expr::SetField(e, field, value) => {
+            compile_expr(e, ctxt, instrs)?;
+
+            // Save the struct on the stack; this is the return value
+            let struct_stack_offset = (ctxt.si + 1) * WORD_SIZE;
             instrs.push(Instr::Mov(
-                Val::RegOff(Reg::RBP, vec_stack_offset),
-                Val::Reg(Reg::R15),
+                Val::RegOff(Reg::RBP, struct_stack_offset),
+                Val::Reg(Reg::RAX),
             ));
 
-            // Allocate space for the vector on the heap
-            instrs.push(Instr::Add(
-                Val::Reg(Reg::R15),
-                Val::Imm(WORD_SIZE * (1 + args.len() as i64)),
+            // Save the address of the field on the stack
+            struct_field_address(field, ctxt, instrs)?;
+            let field_stack_offset = (ctxt.si + 2) * WORD_SIZE;
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, field_stack_offset),
+                Val::Reg(Reg::RBX),
             ));
 
-            // Store the size of the vector
-            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::Imm(args.len() as i64)));
+            compile_expr(
+                value,
+                &Context {
+                    si: ctxt.si + 2,
+                    ..*ctxt
+                },
+                instrs,
+            )?;
             instrs.push(Instr::Mov(
                 Val::Reg(Reg::RBX),
-                Val::RegOff(Reg::RBP, vec_stack_offset),
+                Val::RegOff(Reg::RBP, field_stack_offset),
+            ));
+            instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RAX),
+                Val::RegOff(Reg::RBP, struct_stack_offset),
             ));
-            instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::R10)));
+        }
+        Expr::VecContains(vec, e) | Expr::VecIndexOf(vec, e) => {
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
 
-            // Evaluate each argument and store it in the allocated heap space
-            for (i, arg) in args.iter().enumerate() {
-                instrs.append(&mut compile_expr(
-                    arg,
-                    &Context {
-                        si: ctxt.si + 1,
-                        ..*ctxt
-                    },
-                ));
-                instrs.push(Instr::Mov(
-                    Val::Reg(Reg::RBX),
-                    Val::RegOff(Reg::RBP, vec_stack_offset),
-                ));
-                instrs.push(Instr::Add(
-                    Val::Reg(Reg::RBX),
-                    Val::Imm(WORD_SIZE * (1 + i as i64)),
-                ));
-                instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::RAX)));
-            }
-            // Tag the start address of the heap pointer before returning it
+            // Save the vector on the stack
+            let vec_stack_offset = (ctxt.si + 1) * WORD_SIZE;
             instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, vec_stack_offset),
                 Val::Reg(Reg::RAX),
+            ));
+            compile_expr(
+                e,
+                &Context {
+                    si: ctxt.si + 1,
+                    ..*ctxt
+                },
+                instrs,
+            )?;
+
+            // The runtime compares the elements with the value as == does
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RDI),
                 Val::RegOff(Reg::RBP, vec_stack_offset),
             ));
-            instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RSI), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Call("snek_vec_index_of".to_string()));
+            if let Expr::VecContains(..) = expr {
+                // The element is missing exactly when the index is -1
+                instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(-1 << 1)));
+                instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(FALSE_VAL)));
+                instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(TRUE_VAL)));
+                instrs.push(Instr::CMove(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
+            }
         }
         Expr::VecGet(vec, index) => {
-            instrs.append(&mut compile_expr(vec, ctxt));
-            instrs.append(&mut is_non_nil_vector());
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
 
             // Save the address on the stack
             let vec_stack_offset = (ctxt.si + 1) * WORD_SIZE;
@@ -439,15 +903,16 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 Val::Reg(Reg::RAX),
             ));
 
-            instrs.append(&mut compile_expr(
+            compile_expr(
                 index,
                 &Context {
                     si: ctxt.si + 1,
                     ..*ctxt
                 },
-            ));
+                instrs,
+            )?;
             // If the offset expression did not actually evaluate to a number, error.
-            instrs.append(&mut is_number());
+            is_number(instrs);
             instrs.push(Instr::JumpNotEqual(String::from(INVALID_TYPE_LABEL)));
 
             // Convert the offset to its actual number representation
@@ -460,27 +925,21 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
             ));
             instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
 
-            // Get the vector size at the address
-            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
-            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Reg(Reg::R10)));
-            instrs.push(Instr::JumpGreaterEqual(
-                INDEX_OUT_OF_BOUNDS_LABEL.to_string(),
-            ));
-            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(0)));
-            instrs.push(Instr::JumpLess(INDEX_OUT_OF_BOUNDS_LABEL.to_string()));
+            if !ctxt.unchecked {
+                index_in_bounds(index, instrs);
+            }
 
-            // Add 1 because the address is currently at the tuple size, not the first element.
-            instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
-            // Multiply the offset by the word size
-            instrs.push(Instr::Shl(Val::Reg(Reg::RAX), Val::Imm(WORD_SIZE_SHIFT)));
-            // Add the offset to the base address
-            instrs.push(Instr::Add(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+            // Compute the element address; skip over the size word at the start of the vector
+            instrs.push(Instr::Lea(
+                Reg::RBX,
+                Val::Scaled(Reg::RBX, Reg::RAX, WORD_SIZE, WORD_SIZE),
+            ));
             // Load the value from the heap
             instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RBX, 0)));
         }
         Expr::VecSet(vec, index, value) => {
-            instrs.append(&mut compile_expr(vec, ctxt));
-            instrs.append(&mut is_non_nil_vector());
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
 
             // Save vector address on stack
             let vec_stack_offset = (ctxt.si + 1) * WORD_SIZE;
@@ -493,8 +952,8 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 si: ctxt.si + 1,
                 ..*ctxt
             };
-            instrs.append(&mut compile_expr(index, &index_ctxt));
-            instrs.append(&mut is_number());
+            compile_expr(index, &index_ctxt, instrs)?;
+            is_number(instrs);
             instrs.push(Instr::JumpNotEqual(String::from(INVALID_TYPE_LABEL)));
 
             // Convert the offset to its actual number representation
@@ -506,14 +965,9 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 Val::RegOff(Reg::RBP, vec_stack_offset),
             ));
             instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
-            // Get the vector size at the address
-            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
-            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Reg(Reg::R10)));
-            instrs.push(Instr::JumpGreaterEqual(
-                INDEX_OUT_OF_BOUNDS_LABEL.to_string(),
-            ));
-            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(0)));
-            instrs.push(Instr::JumpLess(INDEX_OUT_OF_BOUNDS_LABEL.to_string()));
+            if !ctxt.unchecked {
+                index_in_bounds(index, instrs);
+            }
 
             // Save index on stack
             let index_stack_offset = (ctxt.si + 2) * WORD_SIZE;
@@ -526,7 +980,7 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 si: ctxt.si + 2,
                 ..*ctxt
             };
-            instrs.append(&mut compile_expr(value, &value_ctxt));
+            compile_expr(value, &value_ctxt, instrs)?;
 
             // Get vector address from stack
             instrs.push(Instr::Mov(
@@ -540,24 +994,135 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 Val::Reg(Reg::R10),
                 Val::RegOff(Reg::RBP, index_stack_offset),
             ));
-            instrs.push(Instr::Add(Val::Reg(Reg::R10), Val::Imm(1)));
-            instrs.push(Instr::Shl(Val::Reg(Reg::R10), Val::Imm(WORD_SIZE_SHIFT)));
-            instrs.push(Instr::Add(Val::Reg(Reg::R10), Val::Reg(Reg::RBX)));
+            // Compute the element address; skip over the size word at the start of the vector
+            instrs.push(Instr::Lea(
+                Reg::R10,
+                Val::Scaled(Reg::RBX, Reg::R10, WORD_SIZE, WORD_SIZE),
+            ));
             // Set value
             instrs.push(Instr::Mov(Val::RegOff(Reg::R10, 0), Val::Reg(Reg::RAX)));
             instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
         }
         Expr::VecLen(vec) => {
-            instrs.append(&mut compile_expr(vec, ctxt));
-            instrs.append(&mut is_non_nil_vector());
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
             instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1)));
             instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RAX, 0)));
             instrs.push(Instr::Shl(Val::Reg(Reg::RAX), Val::Imm(1)));
         }
+        Expr::VecReverse(vec) => {
+            let loop_label = get_new_label("vec_reverse", ctxt);
+            let end_label = get_new_label("vec_reverse_end", ctxt);
+
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
+
+            // The new vector starts at the heap pointer and has the same size. RBX walks forward
+            // through the source elements and RDX backward from the last new element, while R10
+            // counts the elements left to copy.
+            instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+            instrs.push(Instr::Mov(Val::RegOff(Reg::R15, 0), Val::Reg(Reg::R10)));
+            instrs.push(Instr::Lea(
+                Reg::RDX,
+                Val::Scaled(Reg::R15, Reg::R10, WORD_SIZE, 0),
+            ));
+            instrs.push(Instr::Label(loop_label.clone()));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(0)));
+            instrs.push(Instr::JumpEqual(end_label.clone()));
+            instrs.push(Instr::Add(Val::Reg(Reg::RBX), Val::Imm(WORD_SIZE)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RBX, 0)));
+            instrs.push(Instr::Mov(Val::RegOff(Reg::RDX, 0), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RDX), Val::Imm(WORD_SIZE)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::R10), Val::Imm(1)));
+            instrs.push(Instr::Jump(loop_label));
+            instrs.push(Instr::Label(end_label));
+
+            // Return the tagged address of the new vector and move the heap pointer past it
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Reg(Reg::R15)));
+            instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::R15, 0)));
+            instrs.push(Instr::Lea(
+                Reg::R15,
+                Val::Scaled(Reg::R15, Reg::R10, WORD_SIZE, WORD_SIZE),
+            ));
+        }
+        // Sorting returns a new vector, like the other vector operations, so that the source is
+        // unchanged even when it is shared
+        Expr::VecSort(vec) => {
+            let loop_label = get_new_label("vec_sort", ctxt);
+            let end_label = get_new_label("vec_sort_end", ctxt);
+
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
+
+            // Copy the elements to a new vector at the heap pointer, checking that each is a
+            // number. RBX walks through the source and RDX through the copy, while R10 counts the
+            // elements left to copy.
+            instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+            instrs.push(Instr::Mov(Val::RegOff(Reg::R15, 0), Val::Reg(Reg::R10)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RDX), Val::Reg(Reg::R15)));
+            instrs.push(Instr::Label(loop_label.clone()));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(0)));
+            instrs.push(Instr::JumpEqual(end_label.clone()));
+            instrs.push(Instr::Add(Val::Reg(Reg::RBX), Val::Imm(WORD_SIZE)));
+            instrs.push(Instr::Add(Val::Reg(Reg::RDX), Val::Imm(WORD_SIZE)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RBX, 0)));
+            instrs.push(Instr::Test(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::JumpNotZero(INVALID_TYPE_LABEL.to_string()));
+            instrs.push(Instr::Mov(Val::RegOff(Reg::RDX, 0), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::R10), Val::Imm(1)));
+            instrs.push(Instr::Jump(loop_label));
+            instrs.push(Instr::Label(end_label));
+
+            // Move the heap pointer past the copy, then sort it in place in the runtime, which
+            // returns the tagged vector
+            instrs.push(Instr::Mov(Val::Reg(Reg::RDI), Val::Reg(Reg::R15)));
+            instrs.push(Instr::Add(Val::Reg(Reg::RDI), Val::Imm(1)));
+            instrs.push(Instr::Lea(Reg::R15, Val::RegOff(Reg::RDX, -WORD_SIZE)));
+            instrs.push(Instr::Call("snek_sort".to_string()));
+        }
+        Expr::VecSum(vec) | Expr::VecProduct(vec) => {
+            let loop_label = get_new_label("vec_fold", ctxt);
+            let end_label = get_new_label("vec_fold_end", ctxt);
+
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
+
+            // RBX walks through the elements while R10 counts the elements left. RAX holds the
+            // tagged result so far, starting from 0 for a sum and 1 for a product.
+            instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+            let is_sum = matches!(expr, Expr::VecSum(_));
+            let initial = if is_sum { 0 } else { 1 << 1 };
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(initial)));
+            instrs.push(Instr::Label(loop_label.clone()));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(0)));
+            instrs.push(Instr::JumpEqual(end_label.clone()));
+            instrs.push(Instr::Add(Val::Reg(Reg::RBX), Val::Imm(WORD_SIZE)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RDX), Val::RegOff(Reg::RBX, 0)));
+            instrs.push(Instr::Test(Val::Reg(Reg::RDX), Val::Imm(1)));
+            instrs.push(Instr::JumpNotZero(INVALID_TYPE_LABEL.to_string()));
+            if is_sum {
+                instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Reg(Reg::RDX)));
+            } else {
+                // Multiplying by the untagged element keeps the result tagged
+                instrs.push(Instr::Sar(Val::Reg(Reg::RDX), Val::Imm(1)));
+                instrs.push(Instr::Mul(Val::Reg(Reg::RAX), Val::Reg(Reg::RDX)));
+            }
+            get_num_overflow_instrs(instrs);
+            instrs.push(Instr::Sub(Val::Reg(Reg::R10), Val::Imm(1)));
+            instrs.push(Instr::Jump(loop_label));
+            instrs.push(Instr::Label(end_label));
+        }
         Expr::MakeVec(size, elem) => {
             // Allocate a vector with the given size
-            instrs.append(&mut compile_expr(size, ctxt));
-            instrs.append(&mut is_positive_int());
+            compile_expr(size, ctxt, instrs)?;
+            is_non_negative_int(instrs);
             instrs.push(Instr::Shl(
                 Val::Reg(Reg::RAX),
                 Val::Imm(SNEK_NUMBER_TO_OFFSET_SHIFT),
@@ -570,11 +1135,12 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 Val::Reg(Reg::R15),
             ));
 
-            // Allocate space for the vector on the heap
+            // Allocate space for the size word and the elements on the heap
             instrs.push(Instr::Add(Val::Reg(Reg::R15), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Add(Val::Reg(Reg::R15), Val::Imm(WORD_SIZE)));
 
-            // Store the size of the vector
-            instrs.push(Instr::Sar(
+            // Store the size of the vector; it is non-negative, so no sign extension is needed
+            instrs.push(Instr::Shr(
                 Val::Reg(Reg::RAX),
                 Val::Imm(OFFSET_TO_NUMBER_SHIFT),
             ));
@@ -586,14 +1152,14 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
 
             // Compute the element to fill the vector with
             let elem_ctxt = Context {
-                si: ctxt.si + 2,
+                si: ctxt.si + 1,
                 ..*ctxt
             };
-            instrs.append(&mut compile_expr(elem, &elem_ctxt));
+            compile_expr(elem, &elem_ctxt, instrs)?;
 
             // Loop to fill vector
-            let make_vec_start = get_new_label("make_vec_start");
-            let make_vec_end = get_new_label("make_vec_end");
+            let make_vec_start = get_new_label("make_vec_start", ctxt);
+            let make_vec_end = get_new_label("make_vec_end", ctxt);
             // R10 serves as the loop index
             instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::Imm(0)));
             instrs.push(Instr::Label(make_vec_start.clone()));
@@ -613,10 +1179,11 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
                 Val::RegOff(Reg::RBP, vec_stack_offset),
             ));
             // Store the element
-            instrs.push(Instr::Shl(Val::Reg(Reg::R10), Val::Imm(WORD_SIZE_SHIFT)));
-            instrs.push(Instr::Add(Val::Reg(Reg::RBX), Val::Reg(Reg::R10)));
+            instrs.push(Instr::Lea(
+                Reg::RBX,
+                Val::Scaled(Reg::RBX, Reg::R10, WORD_SIZE, 0),
+            ));
             instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::RAX)));
-            instrs.push(Instr::Sar(Val::Reg(Reg::R10), Val::Imm(WORD_SIZE_SHIFT)));
             instrs.push(Instr::Jump(make_vec_start));
             instrs.push(Instr::Label(make_vec_end));
             // Return the vector address
@@ -626,88 +1193,954 @@ fn compile_expr(expr: &Expr, ctxt: &Context) -> Vec<Instr> {
             ));
             instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
         }
-    }
-    return instrs;
-}
+        Expr::VecMap(f, vec) => {
+            let name = fun_name_arg(f, "vec-map")?;
+            check_fixed_fun_call("vec-map", name, 1, ctxt)?;
 
-// Returns error labels and instructions
-fn compile_error_instrs() -> Vec<Instr> {
-    let mut error_instrs: Vec<Instr> = Vec::new();
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
 
-    error_instrs.append(&mut get_error_instrs(ErrCode::Overflow));
-    error_instrs.append(&mut get_error_instrs(ErrCode::InvalidType));
-    error_instrs.append(&mut get_error_instrs(ErrCode::IndexOutOfBounds));
-    error_instrs.append(&mut get_error_instrs(ErrCode::InvalidVecSize));
+            // Save the source vector on the stack
+            let src_stack_offset = (ctxt.si + 1) * WORD_SIZE;
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, src_stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
 
-    return error_instrs;
-}
+            // Allocate a vector of the same size; save its address on the stack
+            let dst_stack_offset = (ctxt.si + 2) * WORD_SIZE;
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, dst_stack_offset),
+                Val::Reg(Reg::R15),
+            ));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RAX, 0)));
+            instrs.push(Instr::Mov(Val::RegOff(Reg::R15, 0), Val::Reg(Reg::R10)));
+            instrs.push(Instr::Add(Val::Reg(Reg::R10), Val::Imm(1)));
+            instrs.push(Instr::Shl(Val::Reg(Reg::R10), Val::Imm(WORD_SIZE_SHIFT)));
+            instrs.push(Instr::Add(Val::Reg(Reg::R15), Val::Reg(Reg::R10)));
+
+            let index_stack_offset = (ctxt.si + 3) * WORD_SIZE;
+            vec_call_loop(
+                name,
+                ctxt,
+                src_stack_offset,
+                index_stack_offset,
+                Some(dst_stack_offset),
+                instrs,
+            );
+
+            // Return the tagged address of the new vector
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RAX),
+                Val::RegOff(Reg::RBP, dst_stack_offset),
+            ));
+            instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
+        }
+        Expr::VecForEach(f, vec) => {
+            let name = fun_name_arg(f, "vec-for-each")?;
+            check_fixed_fun_call("vec-for-each", name, 1, ctxt)?;
 
-// Helper for unary operators
-fn compile_unary_op(op: Op1, e: &Expr, ctxt: &Context) -> Vec<Instr> {
-    let mut instrs = Vec::new();
-    match op {
-        Op1::Add1 => {
-            instrs.append(&mut compile_expr(e, ctxt));
-            instrs.append(&mut is_number_with_error());
-            instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1 << 1)));
-            instrs.append(&mut get_num_overflow_instrs());
+            compile_expr(vec, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
+
+            // Save the source vector on the stack
+            let src_stack_offset = (ctxt.si + 1) * WORD_SIZE;
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, src_stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
+
+            let index_stack_offset = (ctxt.si + 2) * WORD_SIZE;
+            vec_call_loop(
+                name,
+                ctxt,
+                src_stack_offset,
+                index_stack_offset,
+                None,
+                instrs,
+            );
+            instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(NIL_VAL)));
+        }
+        Expr::Apply(f, args) => {
+            let name = fun_name_arg(f, "apply")?;
+            let def = match ctxt.fun_map.get(name) {
+                Some(def) if def.rest.is_some() => {
+                    return Err(CompileError::VariadicFunctionArg {
+                        op: "apply".to_string(),
+                        name: name.to_string(),
+                    });
+                }
+                Some(def) => *def,
+                None => return Err(CompileError::UndefinedFunction(name.to_string())),
+            };
+            let num_params = def.params.len() as i64;
+            let first_default = num_params - def.defaults.len() as i64;
+
+            compile_expr(args, ctxt, instrs)?;
+            is_non_nil_vector(instrs);
+
+            // The vector must hold one argument for each parameter, but may leave out parameters
+            // with default values
+            instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(first_default)));
+            instrs.push(Instr::JumpLess(WRONG_ARG_COUNT_LABEL.to_string()));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(num_params + 1)));
+            instrs.push(Instr::JumpGreaterEqual(WRONG_ARG_COUNT_LABEL.to_string()));
+
+            apply_call(name, def, ctxt, instrs)?;
+        }
+        Expr::Case(e, clauses, else_clause) => {
+            let end_label = get_new_label("case_end", ctxt);
+            let else_label = get_new_label("case_else", ctxt);
+            let clause_labels: Vec<String> = clauses
+                .iter()
+                .map(|_| get_new_label("case_clause", ctxt))
+                .collect();
+
+            compile_expr(e, ctxt, instrs)?;
+            is_number_with_error(instrs);
+
+            // Jump to the first clause whose key equals the value
+            for ((key, _), label) in clauses.iter().zip(clause_labels.iter()) {
+                if int_overflow(*key) {
+                    return Err(CompileError::NumberOverflow);
+                }
+                instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(*key << 1)));
+                instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
+                instrs.push(Instr::JumpEqual(label.clone()));
+            }
+            instrs.push(Instr::Jump(else_label.clone()));
+
+            for ((_, body), label) in clauses.iter().zip(clause_labels) {
+                instrs.push(Instr::Label(label));
+                compile_expr(body, ctxt, instrs)?;
+                instrs.push(Instr::Jump(end_label.clone()));
+            }
+
+            // Without an else clause, the value is nil
+            instrs.push(Instr::Label(else_label));
+            match else_clause {
+                Some(body) => compile_expr(body, ctxt, instrs)?,
+                None => instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(NIL_VAL))),
+            }
+            instrs.push(Instr::Label(end_label));
+        }
+        Expr::Match(e, arms) => {
+            let end_label = get_new_label("match_end", ctxt);
+
+            // Evaluate the matched value and save it on the stack
+            compile_expr(e, ctxt, instrs)?;
+            let value_stack_offset = (ctxt.si + 1) * WORD_SIZE;
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, value_stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
+
+            for (pattern, body) in arms.iter() {
+                let next_arm_label = get_new_label("match_next", ctxt);
+
+                // Check the pattern, binding its variables to the stack slots holding the matched parts
+                let mut arm_env = ctxt.env.clone();
+                let mut arm_si = ctxt.si + 1;
+                compile_pattern(
+                    pattern,
+                    value_stack_offset,
+                    &next_arm_label,
+                    &mut arm_si,
+                    &mut arm_env,
+                    &mut HashSet::new(),
+                    instrs,
+                )?;
+
+                // The pattern matched, so evaluate the body of the arm
+                compile_expr(
+                    body,
+                    &Context {
+                        si: arm_si,
+                        env: &arm_env,
+                        ..*ctxt
+                    },
+                    instrs,
+                )?;
+                instrs.push(Instr::Jump(end_label.clone()));
+                instrs.push(Instr::Label(next_arm_label));
+            }
+
+            // None of the arms matched
+            instrs.push(Instr::Jump(MATCH_FAILURE_LABEL.to_string()));
+            instrs.push(Instr::Label(end_label));
+        }
+    }
+    return Ok(());
+}
+
+// Compiles let bindings into consecutive stack slots after ctxt.si, appending the instructions.
+// Returns the environment with the bindings added.
+fn compile_bindings(
+    bindings: &[(String, Expr)],
+    ctxt: &Context,
+    instrs: &mut Vec<Instr>,
+) -> Result<HashMap<String, i64>, CompileError> {
+    let mut new_env: HashMap<String, i64> = ctxt.env.clone();
+    let mut locally_bound_ids: HashSet<String> = HashSet::new();
+
+    for (index, (id, e)) in bindings.iter().enumerate() {
+        if locally_bound_ids.contains(id) {
+            return Err(CompileError::DuplicateBinding);
+        }
+
+        let stack_index = ctxt.si + 1 + index as i64;
+        let stack_offset = stack_index * WORD_SIZE;
+
+        // Bindings whose constant value replaces every use of the id are never stored
+        if !ctxt.constants.propagated.contains(&(e as *const Expr)) {
+            // Compile the instructions of the let binding.
+            let new_ctxt = Context {
+                si: ctxt.si + index as i64,
+                env: &new_env,
+                ..*ctxt
+            };
+            compile_expr(e, &new_ctxt, instrs)?;
+
+            // Store the let-binded variable on the stack
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
+        }
+
+        // Track which identifiers have been bound locally.
+        locally_bound_ids = locally_bound_ids.update(id.to_string());
+
+        // Update the environment mapping of identifier -> memory location.
+        // IMPORTANT: This must be done after compiling the let expression.
+        new_env = new_env.update(id.to_string(), stack_offset);
+    }
+
+    return Ok(new_env);
+}
+
+// Returns an error if the named let is called anywhere but in tail position of expr.
+// Tail calls are compiled into jumps, so a call with work left to do after it cannot be supported.
+fn check_tail_calls(name: &str, expr: &Expr, tail: bool) -> Result<(), CompileError> {
+    match expr {
+        Expr::Number(_)
+        | Expr::Boolean(_)
+        | Expr::Input(_)
+        | Expr::Read
+        | Expr::Nil
+        | Expr::Continue(_)
+        | Expr::Id(_) => Ok(()),
+        Expr::Call(callee, args) => {
+            if callee == name && !tail {
+                return Err(CompileError::NonTailCall(name.to_string()));
+            }
+            for arg in args {
+                check_tail_calls(name, arg, false)?;
+            }
+            Ok(())
+        }
+        Expr::If(cond, thn, els) => {
+            check_tail_calls(name, cond, false)?;
+            check_tail_calls(name, thn, tail)?;
+            check_tail_calls(name, els, tail)
+        }
+        Expr::Block(es) => {
+            for (i, e) in es.iter().enumerate() {
+                check_tail_calls(name, e, tail && i == es.len() - 1)?;
+            }
+            Ok(())
+        }
+        Expr::Let(bindings, body) => {
+            for (_, e) in bindings {
+                check_tail_calls(name, e, false)?;
+            }
+            check_tail_calls(name, body, tail)
+        }
+        Expr::NamedLet(inner, bindings, body) => {
+            for (_, e) in bindings {
+                check_tail_calls(name, e, false)?;
+            }
+            // An inner named let with the same name shadows this one
+            if inner == name {
+                return Ok(());
+            }
+            check_tail_calls(name, body, tail)
+        }
+        Expr::Case(e, clauses, else_clause) => {
+            check_tail_calls(name, e, false)?;
+            for (_, body) in clauses {
+                check_tail_calls(name, body, tail)?;
+            }
+            match else_clause {
+                Some(body) => check_tail_calls(name, body, tail),
+                None => Ok(()),
+            }
+        }
+        Expr::Match(e, arms) => {
+            check_tail_calls(name, e, false)?;
+            for (_, body) in arms {
+                check_tail_calls(name, body, tail)?;
+            }
+            Ok(())
+        }
+        Expr::UnOp(_, e)
+        | Expr::Loop(_, e)
+        | Expr::Break(_, e)
+        | Expr::Set(_, e)
+        | Expr::SetGet(_, e)
+        | Expr::Assert(e)
+        | Expr::UserError(e)
+        | Expr::VecLen(e)
+        | Expr::VecReverse(e)
+        | Expr::VecSort(e)
+        | Expr::VecSum(e)
+        | Expr::VecProduct(e)
+        | Expr::VecMap(_, e)
+        | Expr::VecForEach(_, e)
+        | Expr::Apply(_, e)
+        | Expr::GetField(e, _) => check_tail_calls(name, e, false),
+        Expr::BinOp(_, e1, e2)
+        | Expr::VecGet(e1, e2)
+        | Expr::VecContains(e1, e2)
+        | Expr::VecIndexOf(e1, e2)
+        | Expr::MakeVec(e1, e2)
+        | Expr::SetField(e1, _, e2) => {
+            check_tail_calls(name, e1, false)?;
+            check_tail_calls(name, e2, false)
+        }
+        Expr::VecSet(e1, e2, e3) => {
+            check_tail_calls(name, e1, false)?;
+            check_tail_calls(name, e2, false)?;
+            check_tail_calls(name, e3, false)
+        }
+        Expr::Vec(es) | Expr::MakeStruct(_, es) => {
+            for e in es {
+                check_tail_calls(name, e, false)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Appends instructions that allocate a vector on the heap holding the values of the expressions,
+// leaving the tagged vector in RAX
+fn compile_vec(
+    args: &[&Expr],
+    ctxt: &Context,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    // Save the current value of the heap pointer on the stack; this is the return value.
+    let vec_stack_offset = (ctxt.si + 1) * WORD_SIZE;
+    instrs.push(Instr::Mov(
+        Val::RegOff(Reg::RBP, vec_stack_offset),
+        Val::Reg(Reg::R15),
+    ));
+
+    // Allocate space for the vector on the heap
+    instrs.push(Instr::Add(
+        Val::Reg(Reg::R15),
+        Val::Imm(WORD_SIZE * (1 + args.len() as i64)),
+    ));
+
+    // Store the size of the vector
+    instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::Imm(args.len() as i64)));
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::RBX),
+        Val::RegOff(Reg::RBP, vec_stack_offset),
+    ));
+    instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::R10)));
+
+    // Evaluate each argument and store it in the allocated heap space
+    for (i, arg) in args.iter().enumerate() {
+        compile_expr(
+            arg,
+            &Context {
+                si: ctxt.si + 1,
+                ..*ctxt
+            },
+            instrs,
+        )?;
+        instrs.push(Instr::Mov(
+            Val::Reg(Reg::RBX),
+            Val::RegOff(Reg::RBP, vec_stack_offset),
+        ));
+        instrs.push(Instr::Add(
+            Val::Reg(Reg::RBX),
+            Val::Imm(WORD_SIZE * (1 + i as i64)),
+        ));
+        instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::RAX)));
+    }
+    // Tag the start address of the heap pointer before returning it
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::RAX),
+        Val::RegOff(Reg::RBP, vec_stack_offset),
+    ));
+    instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
+
+    return Ok(());
+}
+
+// Appends instructions that check that the value in RAX is a struct with the given field, leaving
+// the address of the field in RBX. Structs that share a field name are told apart by their type id.
+fn struct_field_address(
+    field: &str,
+    ctxt: &Context,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    // (type id, index of the field, number of fields) of each struct with the field
+    let mut candidates: Vec<(i64, usize, usize)> = ctxt
+        .structs
+        .values()
+        .filter_map(|info| {
+            let index = info.fields.iter().position(|f| f == field)?;
+            Some((info.id, index, info.fields.len()))
+        })
+        .collect();
+    if candidates.is_empty() {
+        return Err(CompileError::UndefinedField(field.to_string()));
+    }
+    candidates.sort();
+
+    is_non_nil_vector(instrs);
+    let end_label = get_new_label("field_end", ctxt);
+
+    // Unmask the address; a struct holds its size, its type id, then its fields
+    instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+    instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
+    instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+    instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(0)));
+    instrs.push(Instr::JumpEqual(INVALID_TYPE_LABEL.to_string()));
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::RAX),
+        Val::RegOff(Reg::RBX, -WORD_SIZE),
+    ));
+
+    for (id, index, num_fields) in candidates {
+        let next_label = get_new_label("field_next", ctxt);
+        instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(id << 1)));
+        instrs.push(Instr::JumpNotEqual(next_label.clone()));
+        instrs.push(Instr::Cmp(
+            Val::Reg(Reg::R10),
+            Val::Imm(1 + num_fields as i64),
+        ));
+        instrs.push(Instr::JumpNotEqual(INVALID_TYPE_LABEL.to_string()));
+        instrs.push(Instr::Add(
+            Val::Reg(Reg::RBX),
+            Val::Imm(WORD_SIZE * (2 + index as i64)),
+        ));
+        instrs.push(Instr::Jump(end_label.clone()));
+        instrs.push(Instr::Label(next_label));
+    }
+    instrs.push(Instr::Jump(INVALID_TYPE_LABEL.to_string()));
+    instrs.push(Instr::Label(end_label));
+
+    return Ok(());
+}
+
+// Appends instructions that check whether the value at the given stack offset matches the pattern,
+// jumping to fail_label if it does not. Pattern variables are added to env, and si is advanced past
+// the stack slots used to hold vector elements.
+fn compile_pattern(
+    pattern: &Pattern,
+    stack_offset: i64,
+    fail_label: &str,
+    si: &mut i64,
+    env: &mut HashMap<String, i64>,
+    bound_ids: &mut HashSet<String>,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    match pattern {
+        Pattern::Wildcard => {}
+        Pattern::Id(id) => {
+            if bound_ids.contains(id) {
+                return Err(CompileError::DuplicateBinding);
+            }
+            bound_ids.insert(id.to_string());
+            // The variable refers directly to the slot holding the matched value
+            env.insert(id.to_string(), stack_offset);
+        }
+        Pattern::Number(_) | Pattern::Boolean(_) | Pattern::Nil => {
+            let expected = match pattern {
+                Pattern::Number(num) => {
+                    if int_overflow(*num) {
+                        return Err(CompileError::NumberOverflow);
+                    }
+                    *num << 1
+                }
+                Pattern::Boolean(false) => FALSE_VAL,
+                Pattern::Boolean(true) => TRUE_VAL,
+                _ => NIL_VAL,
+            };
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RAX),
+                Val::RegOff(Reg::RBP, stack_offset),
+            ));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(expected)));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
+            instrs.push(Instr::JumpNotEqual(fail_label.to_string()));
+        }
+        Pattern::Vec(elems) => {
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RAX),
+                Val::RegOff(Reg::RBP, stack_offset),
+            ));
+            // The value must be a non-nil vector
+            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(NIL_VAL)));
+            instrs.push(Instr::JumpEqual(fail_label.to_string()));
+            is_vector(instrs);
+            instrs.push(Instr::JumpNotEqual(fail_label.to_string()));
+
+            // The vector must have exactly as many elements as the pattern
+            instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RAX, 0)));
+            instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(elems.len() as i64)));
+            instrs.push(Instr::JumpNotEqual(fail_label.to_string()));
+
+            // Save each element on the stack and match it against its sub-pattern
+            for (i, elem) in elems.iter().enumerate() {
+                *si += 1;
+                let elem_stack_offset = *si * WORD_SIZE;
+                instrs.push(Instr::Mov(
+                    Val::Reg(Reg::RAX),
+                    Val::RegOff(Reg::RBP, stack_offset),
+                ));
+                instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1)));
+                instrs.push(Instr::Mov(
+                    Val::Reg(Reg::RAX),
+                    Val::RegOff(Reg::RAX, -WORD_SIZE * (1 + i as i64)),
+                ));
+                instrs.push(Instr::Mov(
+                    Val::RegOff(Reg::RBP, elem_stack_offset),
+                    Val::Reg(Reg::RAX),
+                ));
+                compile_pattern(
+                    elem,
+                    elem_stack_offset,
+                    fail_label,
+                    si,
+                    env,
+                    bound_ids,
+                    instrs,
+                )?;
+            }
+        }
+    }
+    return Ok(());
+}
+
+// Returns an error if any call in the expression is to an undefined function or passes the wrong
+// number of arguments. named_lets maps each named let in scope to its number of bindings.
+fn check_calls(
+    expr: &Expr,
+    fun_map: &HashMap<String, &FunDef>,
+    named_lets: &HashMap<String, usize>,
+) -> Result<(), CompileError> {
+    match expr {
+        Expr::Call(name, args) => match named_lets.get(name) {
+            Some(num_bindings) if *num_bindings != args.len() => {
+                return Err(CompileError::WrongArgCount {
+                    name: name.to_string(),
+                    found: args.len(),
+                    expected: *num_bindings,
+                });
+            }
+            Some(_) => {}
+            None => check_fun_call(name, args.len(), fun_map)?,
+        },
+        // The named let can only be called from its body
+        Expr::NamedLet(name, bindings, body) => {
+            for (_, e) in bindings.iter() {
+                check_calls(e, fun_map, named_lets)?;
+            }
+            let named_lets = named_lets.update(name.to_string(), bindings.len());
+            return check_calls(body, fun_map, &named_lets);
+        }
+        _ => {}
+    }
+    for e in sub_exprs(expr) {
+        check_calls(e, fun_map, named_lets)?;
+    }
+    return Ok(());
+}
+
+// Returns an error if the named function does not exist or takes a different number of arguments.
+// A variadic function takes any number of arguments after its fixed parameters.
+fn check_fun_call(
+    name: &str,
+    num_args: usize,
+    fun_map: &HashMap<String, &FunDef>,
+) -> Result<(), CompileError> {
+    let def = match fun_map.get(name) {
+        Some(def) => *def,
+        None => return Err(CompileError::UndefinedFunction(name.to_string())),
+    };
+    let required_num = def.params.len() - def.defaults.len();
+    let expected_num = if num_args < required_num {
+        required_num
+    } else {
+        def.params.len()
+    };
+    if num_args < required_num || (num_args > expected_num && def.rest.is_none()) {
+        return Err(CompileError::WrongArgCount {
+            name: name.to_string(),
+            found: num_args,
+            expected: expected_num,
+        });
+    }
+    return Ok(());
+}
+
+// Returns an error if the named function cannot be called by the higher-order operator, which
+// passes exactly the given number of arguments: the function cannot be variadic or leave out
+// parameters with default values
+fn check_fixed_fun_call<'p>(
+    op: &str,
+    name: &str,
+    num_args: usize,
+    ctxt: &Context<'_, 'p>,
+) -> Result<&'p FunDef, CompileError> {
+    let def = match ctxt.fun_map.get(name) {
+        Some(def) => *def,
+        None => return Err(CompileError::UndefinedFunction(name.to_string())),
+    };
+    if def.rest.is_some() {
+        return Err(CompileError::VariadicFunctionArg {
+            op: op.to_string(),
+            name: name.to_string(),
+        });
+    }
+    if def.params.len() != num_args {
+        return Err(CompileError::WrongArgCount {
+            name: name.to_string(),
+            found: num_args,
+            expected: def.params.len(),
+        });
+    }
+    return Ok(def);
+}
+
+// Returns the value of a parameter's default, which must be a literal
+fn default_arg(param: &str, default: &Expr) -> Result<Val, CompileError> {
+    match default {
+        Expr::Number(num) if int_overflow(*num) => return Err(CompileError::NumberOverflow),
+        Expr::Number(num) => return Ok(Val::Imm(*num << 1)),
+        Expr::Boolean(false) => return Ok(Val::Imm(FALSE_VAL)),
+        Expr::Boolean(true) => return Ok(Val::Imm(TRUE_VAL)),
+        Expr::Nil => return Ok(Val::Imm(NIL_VAL)),
+        _ => return Err(CompileError::InvalidDefault(param.to_string())),
+    }
+}
+
+// Appends instructions that allocate a vector holding the values at the given stack offsets,
+// leaving its tagged address in RAX
+fn compile_rest_vec(stack_offsets: &[i64], instrs: &mut Vec<Instr>) {
+    instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::R15)));
+    instrs.push(Instr::Add(
+        Val::Reg(Reg::R15),
+        Val::Imm(WORD_SIZE * (1 + stack_offsets.len() as i64)),
+    ));
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::R10),
+        Val::Imm(stack_offsets.len() as i64),
+    ));
+    instrs.push(Instr::Mov(Val::RegOff(Reg::RBX, 0), Val::Reg(Reg::R10)));
+    for (i, offset) in stack_offsets.iter().enumerate() {
+        instrs.push(Instr::Mov(
+            Val::Reg(Reg::RAX),
+            Val::RegOff(Reg::RBP, *offset),
+        ));
+        instrs.push(Instr::Mov(
+            Val::RegOff(Reg::RBX, -WORD_SIZE * (i as i64 + 1)),
+            Val::Reg(Reg::RAX),
+        ));
+    }
+    instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
+    instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1)));
+}
+
+// Returns the name of the function passed to a higher-order operator.
+// Functions are not values, so the argument must name a top-level function.
+fn fun_name_arg<'a>(f: &'a Expr, op: &str) -> Result<&'a str, CompileError> {
+    match f {
+        Expr::Id(name) => Ok(name),
+        _ => Err(CompileError::ExpectedFunctionName(op.to_string())),
+    }
+}
+
+// Appends instructions that call the named function with the given arguments.
+// The arguments are pushed in reverse order and popped after the call returns.
+fn fun_call(name: &str, mut fun_args: Vec<Val>, instrs: &mut Vec<Instr>) {
+    // Maintain RSP alignment if needed by pushing an extra value
+    if fun_args.len() % 2 != 0 {
+        fun_args.push(Val::Imm(NIL_VAL));
+    }
+
+    // Push computed arguments onto stack for function call.
+    // Only 32-bit immediates can be pushed directly, so larger ones go through R10.
+    for fun_arg in fun_args.iter().rev() {
+        match fun_arg {
+            Val::Imm(n) if i32::try_from(*n).is_err() => {
+                instrs.push(Instr::Mov(Val::Reg(Reg::R10), *fun_arg));
+                instrs.push(Instr::Push(Val::Reg(Reg::R10)));
+            }
+            _ => instrs.push(Instr::Push(*fun_arg)),
+        }
+    }
+
+    // Call function
+    instrs.push(Instr::Call(name.to_string()));
+    // Reset stack pointer
+    instrs.push(Instr::Add(
+        Val::Reg(Reg::RSP),
+        Val::Imm(WORD_SIZE * fun_args.len() as i64),
+    ));
+}
+
+// Calls the named function with the elements of the vector whose address is in RBX as its
+// arguments, given the number of elements in R10. Parameters with default values that the vector
+// leaves out get their default values.
+fn apply_call(
+    name: &str,
+    def: &FunDef,
+    ctxt: &Context,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    let num_params = def.params.len();
+    let first_default = num_params - def.defaults.len();
+
+    // Maintain RSP alignment if needed by pushing an extra value
+    let num_pushed = num_params + num_params % 2;
+    if num_params % 2 != 0 {
+        instrs.push(Instr::Push(Val::Imm(NIL_VAL)));
+    }
+
+    // Push the elements straight from the vector, from the last parameter to the first
+    for i in (0..num_params).rev() {
+        let elem = Val::RegOff(Reg::RBX, -WORD_SIZE * (i as i64 + 1));
+        if i < first_default {
+            instrs.push(Instr::Push(elem));
+            continue;
+        }
+        let given_label = get_new_label("apply_given", ctxt);
+        let pushed_label = get_new_label("apply_pushed", ctxt);
+        instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(i as i64 + 1)));
+        instrs.push(Instr::JumpGreaterEqual(given_label.clone()));
+        // Only 32-bit immediates can be pushed directly, so larger ones go through RAX
+        match default_arg(&def.params[i], &def.defaults[i - first_default])? {
+            Val::Imm(n) if i32::try_from(n).is_err() => {
+                instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(n)));
+                instrs.push(Instr::Push(Val::Reg(Reg::RAX)));
+            }
+            default => instrs.push(Instr::Push(default)),
+        }
+        instrs.push(Instr::Jump(pushed_label.clone()));
+        instrs.push(Instr::Label(given_label));
+        instrs.push(Instr::Push(elem));
+        instrs.push(Instr::Label(pushed_label));
+    }
+
+    instrs.push(Instr::Call(name.to_string()));
+    instrs.push(Instr::Add(
+        Val::Reg(Reg::RSP),
+        Val::Imm(WORD_SIZE * num_pushed as i64),
+    ));
+    return Ok(());
+}
+
+// Calls the named function on each element of the vector saved at src_stack_offset.
+// If dst_stack_offset is given, each result is stored at the same index of the vector saved there.
+fn vec_call_loop(
+    name: &str,
+    ctxt: &Context,
+    src_stack_offset: i64,
+    index_stack_offset: i64,
+    dst_stack_offset: Option<i64>,
+    instrs: &mut Vec<Instr>,
+) {
+    // The loop index lives on the stack since the function call clobbers registers
+    instrs.push(Instr::Mov(
+        Val::RegOff(Reg::RBP, index_stack_offset),
+        Val::Imm(0),
+    ));
+
+    let loop_start = get_new_label("vec_loop_start", ctxt);
+    let loop_end = get_new_label("vec_loop_end", ctxt);
+    instrs.push(Instr::Label(loop_start.clone()));
+    // Check the loop index against the size of the vector
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::R10),
+        Val::RegOff(Reg::RBP, index_stack_offset),
+    ));
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::RBX),
+        Val::RegOff(Reg::RBP, src_stack_offset),
+    ));
+    instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(1)));
+    instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+    instrs.push(Instr::JumpEqual(loop_end.clone()));
+
+    // Load the element and call the function on it
+    instrs.push(Instr::Add(Val::Reg(Reg::R10), Val::Imm(1)));
+    instrs.push(Instr::Shl(Val::Reg(Reg::R10), Val::Imm(WORD_SIZE_SHIFT)));
+    instrs.push(Instr::Add(Val::Reg(Reg::RBX), Val::Reg(Reg::R10)));
+    instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RBX, 0)));
+    fun_call(name, vec![Val::Reg(Reg::RAX)], instrs);
+
+    // Store the result in the destination vector
+    if let Some(dst_stack_offset) = dst_stack_offset {
+        instrs.push(Instr::Mov(
+            Val::Reg(Reg::R10),
+            Val::RegOff(Reg::RBP, index_stack_offset),
+        ));
+        instrs.push(Instr::Add(Val::Reg(Reg::R10), Val::Imm(1)));
+        instrs.push(Instr::Shl(Val::Reg(Reg::R10), Val::Imm(WORD_SIZE_SHIFT)));
+        instrs.push(Instr::Add(
+            Val::Reg(Reg::R10),
+            Val::RegOff(Reg::RBP, dst_stack_offset),
+        ));
+        instrs.push(Instr::Mov(Val::RegOff(Reg::R10, 0), Val::Reg(Reg::RAX)));
+    }
+
+    // Increment the loop index
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::R10),
+        Val::RegOff(Reg::RBP, index_stack_offset),
+    ));
+    instrs.push(Instr::Add(Val::Reg(Reg::R10), Val::Imm(1)));
+    instrs.push(Instr::Mov(
+        Val::RegOff(Reg::RBP, index_stack_offset),
+        Val::Reg(Reg::R10),
+    ));
+    instrs.push(Instr::Jump(loop_start));
+    instrs.push(Instr::Label(loop_end));
+}
+
+// Appends error labels and instructions
+fn compile_error_instrs(instrs: &mut Vec<Instr>) {
+    get_error_instrs(ErrCode::Overflow, instrs);
+    get_error_instrs(ErrCode::InvalidType, instrs);
+    get_error_instrs(ErrCode::IndexOutOfBounds, instrs);
+    get_error_instrs(ErrCode::InvalidVecSize, instrs);
+    get_error_instrs(ErrCode::MatchFailure, instrs);
+    get_error_instrs(ErrCode::AssertionFailed, instrs);
+    get_error_instrs(ErrCode::DivideByZero, instrs);
+    get_error_instrs(ErrCode::WrongArgCount, instrs);
+}
+
+// Helper for unary operators
+fn compile_unary_op(
+    op: Op1,
+    e: &Expr,
+    ctxt: &Context,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
+    match op {
+        Op1::Add1 => {
+            compile_expr(e, ctxt, instrs)?;
+            is_number_with_error(instrs);
+            instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Imm(1 << 1)));
+            get_num_overflow_instrs(instrs);
         }
         Op1::Sub1 => {
-            instrs.append(&mut compile_expr(e, ctxt));
-            instrs.append(&mut is_number_with_error());
+            compile_expr(e, ctxt, instrs)?;
+            is_number_with_error(instrs);
             instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1 << 1)));
-            instrs.append(&mut get_num_overflow_instrs());
+            get_num_overflow_instrs(instrs);
         }
         Op1::IsNum => {
-            instrs.append(&mut compile_expr(e, ctxt));
+            compile_expr(e, ctxt, instrs)?;
             // Set condition codes for whether e is a number
-            instrs.append(&mut is_number());
+            is_number(instrs);
             // Move false into RAX by default. Conditionally move true into RAX if e is a number
             instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
             instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(TRUE_VAL)));
             instrs.push(Instr::CMove(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
         }
         Op1::IsBool => {
-            instrs.append(&mut compile_expr(e, ctxt));
+            compile_expr(e, ctxt, instrs)?;
             // Set condition codes for whether e is a Boolean
-            instrs.append(&mut is_boolean());
+            is_boolean(instrs);
             // Move false into RAX by default. Conditionally move true into RAX if e is a Boolean
             instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
             instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(TRUE_VAL)));
             instrs.push(Instr::CMove(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
         }
         Op1::IsVec => {
-            instrs.append(&mut compile_expr(e, ctxt));
+            compile_expr(e, ctxt, instrs)?;
             // Set condition codes for whether e is a vector
-            instrs.append(&mut is_vector());
+            is_vector(instrs);
             // Move false into RAX by default. Conditionally move true into RAX if e is a vector
             instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
             instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(TRUE_VAL)));
             instrs.push(Instr::CMove(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
         }
         Op1::Print => {
-            instrs.append(&mut compile_expr(e, ctxt));
+            compile_expr(e, ctxt, instrs)?;
             instrs.push(Instr::Mov(Val::Reg(Reg::RDI), Val::Reg(Reg::RAX)));
             instrs.push(Instr::Call(String::from("snek_print")));
             // The return value of print function is carried over from evaluating the expression
         }
+        Op1::Random => {
+            compile_expr(e, ctxt, instrs)?;
+            // The range must be a number that is at least 1
+            is_number_with_error(instrs);
+            instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(1 << 1)));
+            instrs.push(Instr::JumpLess(INVALID_TYPE_LABEL.to_string()));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RDI), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Call(String::from("snek_random")));
+        }
+        // The tag bit of a number is 0, so counting the bits of the tagged value counts the bits
+        // of the 63-bit number, with one extra trailing zero
+        Op1::PopCount => {
+            compile_expr(e, ctxt, instrs)?;
+            is_number_with_error(instrs);
+            instrs.push(Instr::Popcnt(Val::Reg(Reg::RAX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Shl(Val::Reg(Reg::RAX), Val::Imm(1)));
+        }
+        Op1::CountTrailingZeros => {
+            compile_expr(e, ctxt, instrs)?;
+            is_number_with_error(instrs);
+            instrs.push(Instr::Tzcnt(Val::Reg(Reg::RAX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1)));
+            instrs.push(Instr::Shl(Val::Reg(Reg::RAX), Val::Imm(1)));
+        }
     }
-    return instrs;
+    return Ok(());
 }
 
 // Helper for binary operators
-fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr> {
-    let mut instrs = Vec::new();
+fn compile_binary_op(
+    op: Op2,
+    e1: &Expr,
+    e2: &Expr,
+    ctxt: &Context,
+    instrs: &mut Vec<Instr>,
+) -> Result<(), CompileError> {
     match op {
         // Arithmetic binary operations
-        Op2::Plus | Op2::Minus | Op2::Times => {
+        Op2::Plus
+        | Op2::Minus
+        | Op2::Times
+        | Op2::Expt
+        | Op2::Gcd
+        | Op2::Lcm
+        | Op2::Quotient
+        | Op2::Remainder
+        | Op2::Div
+        | Op2::Modulo
+        | Op2::Min
+        | Op2::Max => {
             let stack_offset: i64 = (ctxt.si + 1) * WORD_SIZE;
             let next_ctxt = &Context {
                 si: ctxt.si + 1,
                 ..*ctxt
             };
 
-            instrs.append(&mut compile_expr(e1, ctxt));
+            compile_expr(e1, ctxt, instrs)?;
             // If e1 didn't evaluate to a number (LSB is not 0), jump to error code
             instrs.push(Instr::Test(Val::Reg(Reg::RAX), Val::Imm(1)));
             instrs.push(Instr::JumpNotZero(INVALID_TYPE_LABEL.to_string()));
@@ -719,7 +2152,7 @@ fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr
             ));
 
             // e2 instructions
-            instrs.append(&mut compile_expr(e2, next_ctxt));
+            compile_expr(e2, next_ctxt, instrs)?;
 
             // If e2 didn't evaluate to a number (LSB is not 0), jump to error code
             instrs.push(Instr::Test(Val::Reg(Reg::RAX), Val::Imm(1)));
@@ -757,10 +2190,52 @@ fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr
                     ));
                     instrs.push(Instr::JumpOverflow(String::from(NUM_OVERFLOW_LABEL)));
                 }
+                Op2::Expt => get_expt_instrs(stack_offset, ctxt, instrs),
+                // The runtime computes these with Euclid's algorithm, checking for overflow itself
+                Op2::Gcd | Op2::Lcm => {
+                    instrs.push(Instr::Mov(
+                        Val::Reg(Reg::RDI),
+                        Val::RegOff(Reg::RBP, stack_offset),
+                    ));
+                    instrs.push(Instr::Mov(Val::Reg(Reg::RSI), Val::Reg(Reg::RAX)));
+                    let helper = if let Op2::Gcd = op {
+                        "snek_gcd"
+                    } else {
+                        "snek_lcm"
+                    };
+                    instrs.push(Instr::Call(helper.to_string()));
+                }
+                Op2::Quotient | Op2::Remainder | Op2::Div | Op2::Modulo => {
+                    get_division_instrs(op, stack_offset, ctxt, instrs)
+                }
+                // Keep e2 unless e1 is smaller (min) or larger (max)
+                Op2::Min | Op2::Max => {
+                    instrs.push(Instr::Cmp(
+                        Val::Reg(Reg::RAX),
+                        Val::RegOff(Reg::RBP, stack_offset),
+                    ));
+                    if let Op2::Min = op {
+                        instrs.push(Instr::CMovg(
+                            Val::Reg(Reg::RAX),
+                            Val::RegOff(Reg::RBP, stack_offset),
+                        ));
+                    } else {
+                        instrs.push(Instr::CMovl(
+                            Val::Reg(Reg::RAX),
+                            Val::RegOff(Reg::RBP, stack_offset),
+                        ));
+                    }
+                }
                 _ => panic!("Should not panic here: {op:?}"),
             }
-            // Check for overflow
-            instrs.append(&mut get_num_overflow_instrs());
+            // Check for overflow. The flags are not meaningful after a call or a comparison, and
+            // a remainder is always in range.
+            if !matches!(
+                op,
+                Op2::Gcd | Op2::Lcm | Op2::Remainder | Op2::Modulo | Op2::Min | Op2::Max
+            ) {
+                get_num_overflow_instrs(instrs);
+            }
         }
 
         // Logical binary operators
@@ -776,7 +2251,7 @@ fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr
                 ..*ctxt
             };
 
-            instrs.append(&mut compile_expr(e1, ctxt));
+            compile_expr(e1, ctxt, instrs)?;
 
             // Save result of e1_instrs on stack
             instrs.push(Instr::Mov(
@@ -784,12 +2259,29 @@ fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr
                 Val::Reg(Reg::RAX),
             ));
 
-            instrs.append(&mut compile_expr(e2, next_ctxt));
+            compile_expr(e2, next_ctxt, instrs)?;
 
             // Insert instructions based on the type of logical operator
             match op {
+                Op2::Equal if ctxt.numeric_equal => {
+                    // Both values are numbers exactly when neither has its LSB set
+                    instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+                    instrs.push(Instr::Or(
+                        Val::Reg(Reg::RBX),
+                        Val::RegOff(Reg::RBP, stack_offset),
+                    ));
+                    instrs.push(Instr::Test(Val::Reg(Reg::RBX), Val::Imm(1)));
+                    instrs.push(Instr::JumpNotZero(INVALID_TYPE_LABEL.to_string()));
+                    instrs.push(Instr::Cmp(
+                        Val::Reg(Reg::RAX),
+                        Val::RegOff(Reg::RBP, stack_offset),
+                    ));
+                    instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Imm(TRUE_VAL)));
+                    instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
+                    instrs.push(Instr::CMove(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
+                }
                 Op2::Equal => {
-                    instrs.append(&mut are_same_types(stack_offset));
+                    are_same_types(stack_offset, instrs);
                     // Compare the results of e1 and e2
                     instrs.push(Instr::Cmp(
                         Val::Reg(Reg::RAX),
@@ -804,23 +2296,23 @@ fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr
                     instrs.push(Instr::CMove(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
                 }
                 Op2::Greater => {
-                    instrs.append(&mut get_inequality_instrs(ctxt));
+                    get_inequality_instrs(ctxt, instrs);
                     instrs.push(Instr::CMovg(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)))
                 }
                 Op2::GreaterEqual => {
-                    instrs.append(&mut get_inequality_instrs(ctxt));
+                    get_inequality_instrs(ctxt, instrs);
                     instrs.push(Instr::CMovge(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)))
                 }
                 Op2::Less => {
-                    instrs.append(&mut get_inequality_instrs(ctxt));
+                    get_inequality_instrs(ctxt, instrs);
                     instrs.push(Instr::CMovl(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
                 }
                 Op2::LessEqual => {
-                    instrs.append(&mut get_inequality_instrs(ctxt));
+                    get_inequality_instrs(ctxt, instrs);
                     instrs.push(Instr::CMovle(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)));
                 }
                 Op2::StructEqual => {
-                    instrs.append(&mut are_same_types(stack_offset));
+                    are_same_types(stack_offset, instrs);
                     instrs.push(Instr::Mov(
                         Val::Reg(Reg::RDI),
                         Val::RegOff(Reg::RBP, stack_offset),
@@ -832,14 +2324,185 @@ fn compile_binary_op(op: Op2, e1: &Expr, e2: &Expr, ctxt: &Context) -> Vec<Instr
                 _ => panic!("Should never panic here: {op:?}"),
             }
         }
+
+        Op2::BoolXor => {
+            let stack_offset: i64 = (ctxt.si + 1) * WORD_SIZE;
+            let next_ctxt = &Context {
+                si: ctxt.si + 1,
+                ..*ctxt
+            };
+
+            compile_expr(e1, ctxt, instrs)?;
+            is_boolean(instrs);
+            instrs.push(Instr::JumpNotEqual(INVALID_TYPE_LABEL.to_string()));
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
+
+            compile_expr(e2, next_ctxt, instrs)?;
+            is_boolean(instrs);
+            instrs.push(Instr::JumpNotEqual(INVALID_TYPE_LABEL.to_string()));
+
+            // true and false differ only in the bit of 4, so xor leaves that bit set if they
+            // differ, and setting the bits of false gives the boolean
+            instrs.push(Instr::Xor(
+                Val::Reg(Reg::RAX),
+                Val::RegOff(Reg::RBP, stack_offset),
+            ));
+            instrs.push(Instr::Or(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
+        }
+
+        Op2::PrintTo => {
+            let stack_offset: i64 = (ctxt.si + 1) * WORD_SIZE;
+            let next_ctxt = &Context {
+                si: ctxt.si + 1,
+                ..*ctxt
+            };
+
+            // The file descriptor must be 1 or 2, tagged as 2 or 4, so subtracting 2 leaves at
+            // most the bit of 2 set
+            compile_expr(e1, ctxt, instrs)?;
+            instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Sub(Val::Reg(Reg::RBX), Val::Imm(2)));
+            instrs.push(Instr::Test(Val::Reg(Reg::RBX), Val::Imm(!2)));
+            instrs.push(Instr::JumpNotZero(INVALID_TYPE_LABEL.to_string()));
+            instrs.push(Instr::Mov(
+                Val::RegOff(Reg::RBP, stack_offset),
+                Val::Reg(Reg::RAX),
+            ));
+
+            compile_expr(e2, next_ctxt, instrs)?;
+            instrs.push(Instr::Mov(
+                Val::Reg(Reg::RDI),
+                Val::RegOff(Reg::RBP, stack_offset),
+            ));
+            instrs.push(Instr::Mov(Val::Reg(Reg::RSI), Val::Reg(Reg::RAX)));
+            instrs.push(Instr::Call(String::from("snek_print_fd")));
+            // Like print, the value printed is returned
+        }
     }
-    return instrs;
+    return Ok(());
 }
 
-// Get the instructions for the error handler for the given error code
-fn get_error_instrs(errcode: ErrCode) -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
+// Appends the instructions that divide the number at the stack offset by the number in RAX, leaving
+// the quotient or remainder in RAX. idiv truncates toward zero; div and modulo then correct the
+// results whose remainder has the opposite sign of the divisor, so that the quotient rounds down.
+fn get_division_instrs(op: Op2, dividend_offset: i64, ctxt: &Context, instrs: &mut Vec<Instr>) {
+    instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(0)));
+    instrs.push(Instr::JumpEqual(DIVIDE_BY_ZERO_LABEL.to_string()));
+
+    // Dividing the tagged values gives the untagged quotient in RAX and the tagged remainder in RDX
+    instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::RAX),
+        Val::RegOff(Reg::RBP, dividend_offset),
+    ));
+    instrs.push(Instr::Cqo());
+    instrs.push(Instr::IDiv(Val::Reg(Reg::RBX)));
+
+    if let Op2::Div | Op2::Modulo = op {
+        let done_label = get_new_label("floor_done", ctxt);
+        instrs.push(Instr::Test(Val::Reg(Reg::RDX), Val::Reg(Reg::RDX)));
+        instrs.push(Instr::JumpEqual(done_label.clone()));
+        // xor clears the overflow flag, so jge jumps when the sign bits are the same
+        instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::Reg(Reg::RDX)));
+        instrs.push(Instr::Xor(Val::Reg(Reg::R10), Val::Reg(Reg::RBX)));
+        instrs.push(Instr::JumpGreaterEqual(done_label.clone()));
+        if let Op2::Div = op {
+            instrs.push(Instr::Sub(Val::Reg(Reg::RAX), Val::Imm(1)));
+        } else {
+            instrs.push(Instr::Add(Val::Reg(Reg::RDX), Val::Reg(Reg::RBX)));
+        }
+        instrs.push(Instr::Label(done_label));
+    }
+
+    if let Op2::Quotient | Op2::Div = op {
+        // Tag the quotient, which overflows only when the smallest number is divided by -1
+        instrs.push(Instr::Add(Val::Reg(Reg::RAX), Val::Reg(Reg::RAX)));
+    } else {
+        instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Reg(Reg::RDX)));
+    }
+}
+
+// Appends the instructions that raise the base at the stack offset to the power of the exponent in
+// RAX, both of which are numbers, by repeated squaring. Squaring the base only when a higher bit of
+// the exponent remains means that any overflow is an overflow of the result.
+fn get_expt_instrs(base_offset: i64, ctxt: &Context, instrs: &mut Vec<Instr>) {
+    let loop_label = get_new_label("expt", ctxt);
+    let skip_label = get_new_label("exptskip", ctxt);
+    let end_label = get_new_label("exptend", ctxt);
+
+    // Negative exponents are invalid
+    instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(0)));
+    instrs.push(Instr::JumpLess(INVALID_TYPE_LABEL.to_string()));
+
+    // RBX holds the remaining exponent and R10 the base, both untagged.
+    // RAX holds the tagged result, so multiplying it by R10 keeps it tagged.
+    instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
+    instrs.push(Instr::Sar(Val::Reg(Reg::RBX), Val::Imm(1)));
+    instrs.push(Instr::Mov(
+        Val::Reg(Reg::R10),
+        Val::RegOff(Reg::RBP, base_offset),
+    ));
+    instrs.push(Instr::Sar(Val::Reg(Reg::R10), Val::Imm(1)));
+    instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(1 << 1)));
 
+    instrs.push(Instr::Label(loop_label.clone()));
+    // Multiply the result by the base if the lowest bit of the exponent is set
+    instrs.push(Instr::Test(Val::Reg(Reg::RBX), Val::Imm(1)));
+    instrs.push(Instr::JumpEqual(skip_label.clone()));
+    instrs.push(Instr::Mul(Val::Reg(Reg::RAX), Val::Reg(Reg::R10)));
+    instrs.push(Instr::JumpOverflow(NUM_OVERFLOW_LABEL.to_string()));
+    instrs.push(Instr::Label(skip_label));
+    // Move on to the next bit of the exponent, squaring the base if any bits remain
+    instrs.push(Instr::Sar(Val::Reg(Reg::RBX), Val::Imm(1)));
+    instrs.push(Instr::Cmp(Val::Reg(Reg::RBX), Val::Imm(0)));
+    instrs.push(Instr::JumpEqual(end_label.clone()));
+    instrs.push(Instr::Mul(Val::Reg(Reg::R10), Val::Reg(Reg::R10)));
+    instrs.push(Instr::JumpOverflow(NUM_OVERFLOW_LABEL.to_string()));
+    instrs.push(Instr::Jump(loop_label));
+    instrs.push(Instr::Label(end_label));
+}
+
+// Returns the greatest common divisor of the absolute values of two numbers, as the runtime
+// computes it. gcd(0, 0) is 0.
+fn gcd(a: i64, b: i64) -> u64 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    return a;
+}
+
+// Computes the least common multiple of the absolute values of two numbers, as the runtime
+// computes it, returning None if it overflows an i64
+fn checked_lcm(a: i64, b: i64) -> Option<i64> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    let lcm = (a.unsigned_abs() / gcd(a, b)).checked_mul(b.unsigned_abs())?;
+    return i64::try_from(lcm).ok();
+}
+
+// Computes base raised to the power of the non-negative exponent the same way the compiled code
+// does, returning None if any multiplication overflows
+fn checked_expt(mut base: i64, mut exponent: i64) -> Option<i64> {
+    let mut result: i64 = 1;
+    loop {
+        if exponent & 1 == 1 {
+            result = result.checked_mul(base)?;
+        }
+        exponent >>= 1;
+        if exponent == 0 {
+            return Some(result);
+        }
+        base = base.checked_mul(base)?;
+    }
+}
+
+// Get the instructions for the error handler for the given error code
+fn get_error_instrs(errcode: ErrCode, instrs: &mut Vec<Instr>) {
     match errcode {
         ErrCode::Overflow => instrs.push(Instr::Label(String::from(NUM_OVERFLOW_LABEL))),
         ErrCode::InvalidType => instrs.push(Instr::Label(String::from(INVALID_TYPE_LABEL))),
@@ -847,15 +2510,27 @@ fn get_error_instrs(errcode: ErrCode) -> Vec<Instr> {
             instrs.push(Instr::Label(String::from(INDEX_OUT_OF_BOUNDS_LABEL)))
         }
         ErrCode::InvalidVecSize => instrs.push(Instr::Label(String::from(INVALID_VEC_SIZE_LABEL))),
+        ErrCode::MatchFailure => instrs.push(Instr::Label(String::from(MATCH_FAILURE_LABEL))),
+        ErrCode::AssertionFailed => instrs.push(Instr::Label(String::from(ASSERTION_FAILED_LABEL))),
+        ErrCode::DivideByZero => instrs.push(Instr::Label(String::from(DIVIDE_BY_ZERO_LABEL))),
+        ErrCode::WrongArgCount => instrs.push(Instr::Label(String::from(WRONG_ARG_COUNT_LABEL))),
     }
 
-    // Pass error code as first function argument to snek_error
+    // Pass error code as first function argument to snek_error, and the heap pointer as the second
     instrs.push(Instr::Mov(Val::Reg(Reg::EDI), Val::Imm(errcode as i64)));
+    instrs.push(Instr::Mov(Val::Reg(Reg::RSI), Val::Reg(Reg::R15)));
 
     // Call snek_error
     instrs.push(Instr::Call("snek_error".to_string()));
+}
 
-    return instrs;
+// Divides the numbers, rounding the quotient down
+fn floor_div(a: i64, b: i64) -> i64 {
+    let quotient = a / b;
+    if a % b != 0 && (a % b < 0) != (b < 0) {
+        return quotient - 1;
+    }
+    return quotient;
 }
 
 // Returns true if the the given integer is outside the range of a 63-bit signed integer,
@@ -864,20 +2539,354 @@ fn int_overflow(i: i64) -> bool {
     return i < I63_MIN || i > I63_MAX;
 }
 
-// Get an incremented label. Increments the global variable LABEL_CTR each time it is called.
-fn get_new_label(s: &str) -> String {
-    unsafe {
-        let current = LABEL_CTR;
-        LABEL_CTR += 1;
-        return format!("{s}_{current}");
+// Finds the values of the function body or main expression that are known at compile time, given
+// the value of input0 if it is known
+fn find_constants(body: &Expr, input: Option<i64>) -> Result<Constants, CompileError> {
+    let mut assigned = HashSet::new();
+    collect_assigned(body, &mut assigned);
+    let mut constants = Constants::default();
+    fold_constants(body, &HashMap::new(), &assigned, input, &mut constants)?;
+    return Ok(constants);
+}
+
+// Adds the name of every variable that is assigned with set! within expr to assigned
+fn collect_assigned(expr: &Expr, assigned: &mut HashSet<String>) {
+    if let Expr::Set(name, _) | Expr::SetGet(name, _) = expr {
+        assigned.insert(name.to_string());
+    }
+    for e in sub_exprs(expr) {
+        collect_assigned(e, assigned);
+    }
+}
+
+// Computes the value of every arithmetic expression within expr that is made only of number
+// literals, ids bound to them and a known input0, recording it in constants keyed by the address
+// of the expression. A let binding whose value is constant is propagated to every use of the id,
+// unless the id is ever assigned with set!, since no assignment is tracked by scope. env maps the
+// ids in scope to their constant values, and input is the runtime value of input0 if it is known.
+// Each node is visited once, with its subexpressions folded first. Returns the value of expr
+// itself, if it is constant, or an error if any constant value or intermediate result overflows,
+// since the program would always fail at runtime.
+fn fold_constants(
+    expr: &Expr,
+    env: &HashMap<String, i64>,
+    assigned: &HashSet<String>,
+    input: Option<i64>,
+    constants: &mut Constants,
+) -> Result<Option<i64>, CompileError> {
+    match expr {
+        // Only numbers are folded; a known boolean input is still compiled into the program
+        Expr::Input(0) => return Ok(input.filter(|value| value & 1 == 0).map(|value| value >> 1)),
+        Expr::Id(name) => {
+            let value = env.get(name).copied();
+            if let Some(num) = value {
+                constants.values.insert(expr as *const Expr, num);
+            }
+            return Ok(value);
+        }
+        // A let is constant when all of its bindings are propagated and its body is constant
+        Expr::Let(bindings, body) => {
+            let mut new_env = env.clone();
+            let mut all_propagated = true;
+            for (id, e) in bindings.iter() {
+                match fold_constants(e, &new_env, assigned, input, constants)? {
+                    Some(num) if !assigned.contains(id) => {
+                        constants.propagated.insert(e as *const Expr);
+                        new_env.insert(id.to_string(), num);
+                    }
+                    _ => {
+                        all_propagated = false;
+                        new_env.remove(id);
+                    }
+                }
+            }
+            let value = fold_constants(body, &new_env, assigned, input, constants)?;
+            return Ok(value.filter(|_| all_propagated));
+        }
+        // Named let bindings are updated by tail calls, so they are never propagated
+        Expr::NamedLet(_, bindings, body) => {
+            let mut new_env = env.clone();
+            for (id, e) in bindings.iter() {
+                fold_constants(e, &new_env, assigned, input, constants)?;
+                new_env.remove(id);
+            }
+            fold_constants(body, &new_env, assigned, input, constants)?;
+            return Ok(None);
+        }
+        Expr::Match(e, arms) => {
+            fold_constants(e, env, assigned, input, constants)?;
+            for (pattern, body) in arms.iter() {
+                let arm_env: HashMap<String, i64> = env
+                    .clone()
+                    .into_iter()
+                    .filter(|(id, _)| !binds(pattern, id))
+                    .collect();
+                fold_constants(body, &arm_env, assigned, input, constants)?;
+            }
+            return Ok(None);
+        }
+        _ => (),
+    }
+
+    let mut values: Vec<Option<i64>> = Vec::new();
+    for e in sub_exprs(expr) {
+        values.push(fold_constants(e, env, assigned, input, constants)?);
+    }
+
+    let value = match (expr, &values[..]) {
+        (Expr::Number(num), _) => {
+            if int_overflow(*num) {
+                return Err(CompileError::NumberOverflow);
+            }
+            return Ok(Some(*num));
+        }
+        (Expr::UnOp(Op1::Add1, _), [Some(num)]) => num + 1,
+        (Expr::UnOp(Op1::Sub1, _), [Some(num)]) => num - 1,
+        (Expr::UnOp(Op1::PopCount, _), [Some(num)]) => (num << 1).count_ones() as i64,
+        (Expr::UnOp(Op1::CountTrailingZeros, _), [Some(num)]) => {
+            (num << 1).trailing_zeros() as i64 - 1
+        }
+        // A negative exponent or a division by zero is left to fail at runtime
+        (Expr::BinOp(Op2::Expt, _, _), [Some(_), Some(num2)]) if *num2 < 0 => return Ok(None),
+        (
+            Expr::BinOp(Op2::Quotient | Op2::Remainder | Op2::Div | Op2::Modulo, _, _),
+            [Some(_), Some(0)],
+        ) => return Ok(None),
+        (
+            Expr::BinOp(
+                op @ (Op2::Plus
+                | Op2::Minus
+                | Op2::Times
+                | Op2::Expt
+                | Op2::Gcd
+                | Op2::Lcm
+                | Op2::Quotient
+                | Op2::Remainder
+                | Op2::Div
+                | Op2::Modulo
+                | Op2::Min
+                | Op2::Max),
+                _,
+                _,
+            ),
+            [Some(num1), Some(num2)],
+        ) => {
+            let value = match op {
+                Op2::Plus => num1.checked_add(*num2),
+                Op2::Minus => num1.checked_sub(*num2),
+                Op2::Times => num1.checked_mul(*num2),
+                Op2::Expt => checked_expt(*num1, *num2),
+                Op2::Gcd => i64::try_from(gcd(*num1, *num2)).ok(),
+                Op2::Lcm => checked_lcm(*num1, *num2),
+                Op2::Quotient => Some(num1 / num2),
+                Op2::Remainder => Some(num1 % num2),
+                Op2::Div => Some(floor_div(*num1, *num2)),
+                Op2::Min => Some(*num1.min(num2)),
+                Op2::Max => Some(*num1.max(num2)),
+                _ => Some(num1 - floor_div(*num1, *num2) * num2),
+            };
+            match value {
+                Some(value) => value,
+                None => return Err(CompileError::ConstantOverflow),
+            }
+        }
+        _ => return Ok(None),
+    };
+    if int_overflow(value) {
+        return Err(CompileError::ConstantOverflow);
+    }
+    constants.values.insert(expr as *const Expr, value);
+    return Ok(Some(value));
+}
+
+// Returns the loop-invariant expressions of the loop body, in evaluation order. These are pure
+// expressions that read variables that are neither bound nor assigned within the body, so they can
+// be computed once before the loop. Structural equality also reads the elements of vectors, so it
+// is only invariant if the body cannot change them. Since they may still fail at runtime, they are only taken from
+// the start of the first iteration, before anything else with an effect or a possible runtime
+// error, so that a failure is reported exactly where the loop would have reported it.
+fn loop_invariants(body: &Expr) -> Vec<&Expr> {
+    let mut variant = HashSet::new();
+    collect_variant(body, &mut variant);
+    let heap_changes = mutates_vectors(body) || makes_calls(body);
+    let mut purity = HashMap::new();
+    collect_purity(body, &variant, heap_changes, &mut purity);
+    let mut invariants = Vec::new();
+    find_invariants(body, &purity, &mut invariants);
+    return invariants;
+}
+
+// Finds the loop-invariant expressions of every loop within expr, so that they are computed once
+// for both the stack depth and the code of the loop
+fn find_loop_invariants<'a>(expr: &'a Expr, loops: &mut LoopInvariants<'a>) {
+    if let Expr::Loop(_, body) = expr {
+        loops.insert(&**body as *const Expr, loop_invariants(body));
+    }
+    for e in sub_exprs(expr) {
+        find_loop_invariants(e, loops);
+    }
+}
+
+// Adds the name of every variable that is bound or assigned within expr to variant
+fn collect_variant(expr: &Expr, variant: &mut HashSet<String>) {
+    match expr {
+        Expr::Set(name, _) | Expr::SetGet(name, _) => {
+            variant.insert(name.to_string());
+        }
+        Expr::Let(bindings, _) | Expr::NamedLet(_, bindings, _) => {
+            for (id, _) in bindings.iter() {
+                variant.insert(id.to_string());
+            }
+        }
+        Expr::Match(_, arms) => {
+            for (pattern, _) in arms.iter() {
+                collect_pattern_ids(pattern, variant);
+            }
+        }
+        _ => (),
+    }
+    for e in sub_exprs(expr) {
+        collect_variant(e, variant);
     }
 }
 
+// Adds the name of every variable bound by the pattern to ids
+fn collect_pattern_ids(pattern: &Pattern, ids: &mut HashSet<String>) {
+    match pattern {
+        Pattern::Id(id) => {
+            ids.insert(id.to_string());
+        }
+        Pattern::Vec(patterns) => {
+            for p in patterns.iter() {
+                collect_pattern_ids(p, ids);
+            }
+        }
+        Pattern::Wildcard | Pattern::Number(_) | Pattern::Boolean(_) | Pattern::Nil => (),
+    }
+}
+
+// Records, for expr and every expression within it, whether it is pure and reads none of the
+// variant variables, and whether it reads any variable at all. Returns those of expr. Each
+// expression is visited once, from the operands up. heap_changes is true if the elements of vectors
+// may change while expr is computed repeatedly, so results that depend on them vary too.
+fn collect_purity(
+    expr: &Expr,
+    variant: &HashSet<String>,
+    heap_changes: bool,
+    purity: &mut HashMap<*const Expr, (bool, bool)>,
+) -> (bool, bool) {
+    let operands: Vec<(bool, bool)> = sub_exprs(expr)
+        .into_iter()
+        .map(|e| collect_purity(e, variant, heap_changes, purity))
+        .collect();
+    let result = match expr {
+        Expr::Number(_) | Expr::Boolean(_) | Expr::Nil => (true, false),
+        Expr::Input(_) => (true, true),
+        Expr::Id(name) => (!variant.contains(name), true),
+        Expr::UnOp(Op1::Print | Op1::Random, _) | Expr::BinOp(Op2::PrintTo, _, _) => (false, false),
+        Expr::BinOp(Op2::StructEqual, _, _) if heap_changes => (false, false),
+        Expr::UnOp(_, _) | Expr::VecLen(_) | Expr::BinOp(_, _, _) => (
+            operands.iter().all(|(pure, _)| *pure),
+            operands.iter().any(|(_, reads)| *reads),
+        ),
+        _ => (false, false),
+    };
+    purity.insert(expr as *const Expr, result);
+    return result;
+}
+
+// Returns true if expr is pure, reads at least one variable, and none of the variant ones
+fn is_invariant(expr: &Expr, purity: &HashMap<*const Expr, (bool, bool)>) -> bool {
+    return purity.get(&(expr as *const Expr)) == Some(&(true, true));
+}
+
+// Adds the largest invariant expressions within expr that are computed before anything else with
+// an effect or a possible runtime error to invariants. Returns true if nothing in expr besides
+// the invariant expressions has an effect or may fail, so the search can go on after expr.
+fn find_invariants<'a>(
+    expr: &'a Expr,
+    purity: &HashMap<*const Expr, (bool, bool)>,
+    invariants: &mut Vec<&'a Expr>,
+) -> bool {
+    match expr {
+        Expr::Number(_) | Expr::Boolean(_) | Expr::Nil | Expr::Input(_) | Expr::Id(_) => true,
+        Expr::UnOp(_, _) | Expr::BinOp(_, _, _) | Expr::VecLen(_) if is_invariant(expr, purity) => {
+            invariants.push(expr);
+            true
+        }
+        Expr::Block(es) => es.iter().all(|e| find_invariants(e, purity, invariants)),
+        Expr::Let(bindings, body) => {
+            bindings
+                .iter()
+                .all(|(_, e)| find_invariants(e, purity, invariants))
+                && find_invariants(body, purity, invariants)
+        }
+        // Only the condition is computed on every iteration
+        Expr::If(cond, _, _) => {
+            find_invariants(cond, purity, invariants);
+            false
+        }
+        // The operation itself may fail once its operands are computed
+        Expr::UnOp(_, e) | Expr::VecLen(e) => {
+            find_invariants(e, purity, invariants);
+            false
+        }
+        Expr::BinOp(_, e1, e2) => {
+            if find_invariants(e1, purity, invariants) {
+                find_invariants(e2, purity, invariants);
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+// Get an incremented label. Increments the label counter of the context each time it is called.
+// With stable labels, the label is named after the path of the expression being compiled instead,
+// with a suffix if the expression needs more than one label of the kind.
+fn get_new_label(s: &str, ctxt: &Context) -> String {
+    if let Some(labels) = ctxt.stable_labels {
+        let label = format!("{}{s}_{}", ctxt.label_prefix, ctxt.label_path);
+        let mut used = labels.used.borrow_mut();
+        let mut unique = label.clone();
+        let mut n = 1;
+        while used.contains(&unique) {
+            unique = format!("{label}_{n}");
+            n += 1;
+        }
+        used.insert(unique.clone());
+        return unique;
+    }
+    let current = ctxt.label_ctr.get();
+    ctxt.label_ctr.set(current + 1);
+    return format!("{}{s}_{current}", ctxt.label_prefix);
+}
+
+// Returns the path of each expression of the body if the options ask for stable labels
+fn stable_labels(body: &Expr, options: &CompileOptions) -> Option<StableLabels> {
+    if !options.stable_labels {
+        return None;
+    }
+    let mut paths = HashMap::new();
+    collect_paths(body, "0".to_string(), &mut paths);
+    return Some(StableLabels {
+        paths,
+        used: RefCell::new(HashSet::new()),
+    });
+}
+
+// Records the path of the expression and of each of its subexpressions
+fn collect_paths(expr: &Expr, path: String, paths: &mut HashMap<*const Expr, String>) {
+    for (i, e) in sub_exprs(expr).into_iter().enumerate() {
+        collect_paths(e, format!("{path}.{i}"), paths);
+    }
+    paths.insert(expr as *const Expr, path);
+}
+
 // Return instructions that are common to all implementations of inequality operators.
 // Sets condition codes with a CMP indicating the result of the inequality comparison.
 // RAX contains false and RBX contains true.
-fn get_inequality_instrs(ctxt: &Context) -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
+fn get_inequality_instrs(ctxt: &Context, instrs: &mut Vec<Instr>) {
     let stack_offset = (ctxt.si + 1) * WORD_SIZE;
 
     // Move the result of e2 into RBX for the type check
@@ -906,51 +2915,55 @@ fn get_inequality_instrs(ctxt: &Context) -> Vec<Instr> {
 
     // Move false into RAX
     instrs.push(Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(FALSE_VAL)));
-
-    return instrs;
 }
 
-// Returns a vector of instructions that jumps to the numerical overflow error label
-fn get_num_overflow_instrs() -> Vec<Instr> {
-    let mut instrs: Vec<Instr> = Vec::new();
+// Appends instructions that jumps to the numerical overflow error label
+fn get_num_overflow_instrs(instrs: &mut Vec<Instr>) {
     instrs.push(Instr::JumpOverflow(NUM_OVERFLOW_LABEL.to_string()));
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX is a number.
+// Appends instructions that checks whether the current value in RAX is a number.
 // Uses RBX for intermediate computation, and does a CMP that sets condition codes.
-fn is_number() -> Vec<Instr> {
-    let mut instrs = Vec::new();
+fn is_number(instrs: &mut Vec<Instr>) {
     instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
     instrs.push(Instr::Not(Val::Reg(Reg::RBX)));
     instrs.push(Instr::And(Val::Reg(Reg::RBX), Val::Imm(1)));
     instrs.push(Instr::Cmp(Val::Reg(Reg::RBX), Val::Imm(1)));
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX
+// Appends instructions that checks whether the current value in RAX
 // is a number. Throws an error if this value is not a number, otherwise continues.
-fn is_number_with_error() -> Vec<Instr> {
-    let mut instrs = Vec::new();
-    instrs.append(&mut is_number());
+fn is_number_with_error(instrs: &mut Vec<Instr>) {
+    is_number(instrs);
     instrs.push(Instr::JumpNotEqual(String::from(INVALID_TYPE_LABEL)));
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX
-// is a positive integer. Throws an error if not, otherwise continues.
-fn is_positive_int() -> Vec<Instr> {
-    let mut instrs = Vec::new();
-    instrs.append(&mut &mut is_number_with_error());
-    instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(1)));
+// Appends instructions that checks whether the current value in RAX
+// is a non-negative integer. Throws an error if not, otherwise continues.
+fn is_non_negative_int(instrs: &mut Vec<Instr>) {
+    is_number_with_error(instrs);
+    instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(0)));
     instrs.push(Instr::JumpLess(String::from(INVALID_VEC_SIZE_LABEL)));
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX is a Boolean.
+// Appends instructions that checks whether the index in RAX, computed by the index
+// expression, is within the bounds of the untagged vector address in RBX. Uses R10 for the vector
+// size. The check for a negative index is omitted when the index is a non-negative literal.
+fn index_in_bounds(index: &Expr, instrs: &mut Vec<Instr>) {
+    instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::RegOff(Reg::RBX, 0)));
+    instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Reg(Reg::R10)));
+    instrs.push(Instr::JumpGreaterEqual(
+        INDEX_OUT_OF_BOUNDS_LABEL.to_string(),
+    ));
+    if !matches!(index, Expr::Number(n) if *n >= 0) {
+        instrs.push(Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(0)));
+        instrs.push(Instr::JumpLess(INDEX_OUT_OF_BOUNDS_LABEL.to_string()));
+    }
+}
+
+// Appends instructions that checks whether the current value in RAX is a Boolean.
 // Uses RBX for intermediate computation, and does a CMP that sets condition codes.
-fn is_boolean() -> Vec<Instr> {
-    let mut instrs = Vec::new();
+fn is_boolean(instrs: &mut Vec<Instr>) {
     instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
 
     // Clear all bits except the lower two
@@ -959,24 +2972,19 @@ fn is_boolean() -> Vec<Instr> {
     instrs.push(Instr::And(Val::Reg(Reg::RBX), Val::Imm(BOOLEAN_LSB)));
 
     instrs.push(Instr::Cmp(Val::Reg(Reg::RBX), Val::Imm(BOOLEAN_LSB)));
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX is a non-nil vector.
+// Appends instructions that checks whether the current value in RAX is a non-nil vector.
 // Uses RBX for intermediate computation, and does a CMP that sets condition codes.
-fn is_vector() -> Vec<Instr> {
-    let mut instrs = Vec::new();
+fn is_vector(instrs: &mut Vec<Instr>) {
     instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
     instrs.push(Instr::And(Val::Reg(Reg::RBX), Val::Imm(0b11)));
     instrs.push(Instr::Cmp(Val::Reg(Reg::RBX), Val::Imm(0b1)));
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX
+// Appends instructions that checks whether the current value in RAX
 // is a non-nil vector. Throws an error if not, otherwise continues.
-fn is_non_nil_vector() -> Vec<Instr> {
-    let mut instrs = Vec::new();
-
+fn is_non_nil_vector(instrs: &mut Vec<Instr>) {
     // Check that value is not nil
     instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
     instrs.push(Instr::Cmp(Val::Reg(Reg::RBX), Val::Imm(NIL_VAL)));
@@ -986,22 +2994,19 @@ fn is_non_nil_vector() -> Vec<Instr> {
     instrs.push(Instr::And(Val::Reg(Reg::RBX), Val::Imm(1)));
     instrs.push(Instr::Cmp(Val::Reg(Reg::RBX), Val::Imm(1)));
     instrs.push(Instr::JumpNotEqual(String::from(INVALID_TYPE_LABEL)));
-
-    return instrs;
 }
 
-// Returns a vector of instructions that checks whether the current value in RAX
+// Appends instructions that checks whether the current value in RAX
 // and the next value on the stack at lower memory are the same type. If the types are different,
 // jumps to error code; otherwise, continues.
-fn are_same_types(stack_offset: i64) -> Vec<Instr> {
-    let mut instrs = Vec::new();
+fn are_same_types(stack_offset: i64, instrs: &mut Vec<Instr>) {
     // Move the contents of RAX into RBX
     instrs.push(Instr::Mov(Val::Reg(Reg::RBX), Val::Reg(Reg::RAX)));
 
     // These are the possible type comparisons in the current value representation:
     // 1. number XOR number -> LSB = 0
     // 2. boolean XOR boolean -> LSB = 0
-    // 3. pointer XOR pointer -> LSB = 0
+    // 3. pointer XOR pointer -> LSB = 0; nil is the pointer 1, so it has the type of vectors
     // 4. number XOR boolean -> LSB = 1
     // 5. number XOR pointer -> LSB = 1
     // 6. boolean XOR pointer -> LSB = 10
@@ -1022,7 +3027,7 @@ fn are_same_types(stack_offset: i64) -> Vec<Instr> {
     instrs.push(Instr::JumpNotZero(INVALID_TYPE_LABEL.to_string()));
 
     // If LSB(rax) XOR LSB(result) = 0b11, type error
-    // Get the LSB of RAX into R11
+    // Get the LSB of RAX into R10
     instrs.push(Instr::Mov(Val::Reg(Reg::R10), Val::Reg(Reg::RAX)));
     instrs.push(Instr::And(Val::Reg(Reg::R10), Val::Imm(1)));
     // Clear all but the lower two bits of RBX
@@ -1030,38 +3035,475 @@ fn are_same_types(stack_offset: i64) -> Vec<Instr> {
     instrs.push(Instr::Xor(Val::Reg(Reg::R10), Val::Reg(Reg::RBX)));
     instrs.push(Instr::Cmp(Val::Reg(Reg::R10), Val::Imm(0b11)));
     instrs.push(Instr::JumpEqual(INVALID_TYPE_LABEL.to_string()));
+}
+
+// Finds the vector literals whose elements are all literals or such vectors, which are laid out in
+// the data section so that evaluating them allocates nothing. A literal that is evaluated more than
+// once then returns the same vector each time, so this is only done if the program never mutates a
+// vector; otherwise every literal is allocated on the heap, giving each evaluation its own copy.
+fn find_static_vectors(prog: &Program) -> StaticVectors {
+    let mut vecs = StaticVectors::default();
+    let bodies: Vec<&Expr> = prog
+        .defs
+        .iter()
+        .map(|def| &*def.body)
+        .chain(std::iter::once(&*prog.main))
+        .collect();
+    if bodies.iter().any(|body| mutates_vectors(body)) {
+        return vecs;
+    }
+    for body in bodies {
+        collect_static_vectors(body, &mut vecs);
+    }
+    return vecs;
+}
+
+// Returns true if expr contains a vec-set! or set-field!
+fn mutates_vectors(expr: &Expr) -> bool {
+    if let Expr::VecSet(..) | Expr::SetField(..) = expr {
+        return true;
+    }
+    return sub_exprs(expr).into_iter().any(mutates_vectors);
+}
+
+// Returns true if expr calls a function, which may mutate vectors
+fn makes_calls(expr: &Expr) -> bool {
+    if let Expr::Call(..) | Expr::Apply(..) | Expr::VecMap(..) | Expr::VecForEach(..) = expr {
+        return true;
+    }
+    return sub_exprs(expr).into_iter().any(makes_calls);
+}
 
-    return instrs;
+// Lays out every constant vector literal within expr in the data section
+fn collect_static_vectors(expr: &Expr, vecs: &mut StaticVectors) {
+    if static_vector(expr, vecs).is_some() {
+        return;
+    }
+    for e in sub_exprs(expr) {
+        collect_static_vectors(e, vecs);
+    }
+}
+
+// Lays out expr in the data section if it is a constant vector literal, returning its label.
+// Nested constant vectors are laid out first, and the outer vector holds their tagged addresses.
+// A literal with the same words as one already laid out shares its label; since nested vectors are
+// shared the same way, literals that are structurally equal always end up with the same label.
+fn static_vector(expr: &Expr, vecs: &mut StaticVectors) -> Option<String> {
+    let Expr::Vec(elems) = expr else {
+        return None;
+    };
+    let key = expr as *const Expr as usize;
+    if let Some(label) = vecs.labels.get(&key) {
+        return Some(label.to_string());
+    }
+
+    let mut words = vec![StaticWord::Value(elems.len() as i64)];
+    for elem in elems.iter() {
+        match elem {
+            Expr::Number(num) if !int_overflow(*num) => words.push(StaticWord::Value(num << 1)),
+            Expr::Boolean(true) => words.push(StaticWord::Value(TRUE_VAL)),
+            Expr::Boolean(false) => words.push(StaticWord::Value(FALSE_VAL)),
+            Expr::Nil => words.push(StaticWord::Value(NIL_VAL)),
+            _ => words.push(StaticWord::Vector(static_vector(elem, vecs)?)),
+        }
+    }
+
+    if let Some(label) = vecs.interned.get(&words) {
+        let label = label.to_string();
+        vecs.labels.insert(key, label.to_string());
+        return Some(label);
+    }
+    let label = format!("const_vec_{}", vecs.interned.len());
+    vecs.labels.insert(key, label.to_string());
+    vecs.data.push(Instr::Align(WORD_SIZE));
+    vecs.data.push(Instr::Label(label.to_string()));
+    for word in words.iter() {
+        match word {
+            StaticWord::Value(value) => vecs.data.push(Instr::Quad(*value)),
+            StaticWord::Vector(label) => vecs.data.push(Instr::QuadLabel(label.to_string(), 1)),
+        }
+    }
+    vecs.interned.insert(words, label.to_string());
+    return Some(label);
+}
+
+// Returns the number of expressions in the tree
+fn count_exprs(expr: &Expr) -> usize {
+    return 1 + sub_exprs(expr).into_iter().map(count_exprs).sum::<usize>();
 }
 
 // Calculates the number of stack words that must be allocated for the given expression.
-fn depth(expr: &Expr) -> u32 {
+fn depth(expr: &Expr, loops: &LoopInvariants) -> u32 {
     match expr {
-        Expr::Number(_) | Expr::Boolean(_) | Expr::Input | Expr::Nil | Expr::Id(_) => 0,
-        Expr::UnOp(_, e) | Expr::Loop(e) | Expr::Break(e) | Expr::Set(_, e) => depth(e),
-        Expr::BinOp(_, e1, e2) => depth(e1).max(depth(e2) + 1),
-        Expr::If(e1, e2, e3) => depth(e1).max(depth(e2)).max(depth(e3)),
-        Expr::Block(es) => es.iter().map(depth).max().unwrap_or(0),
-        Expr::Let(bindings, body) => bindings
+        Expr::Number(_)
+        | Expr::Boolean(_)
+        | Expr::Input(_)
+        | Expr::Read
+        | Expr::Nil
+        | Expr::Continue(_)
+        | Expr::Id(_) => 0,
+        // Loop-invariant expressions are stored before the slots of the body
+        Expr::Loop(_, e) => {
+            depth(e, loops) + loops.get(&(&**e as *const Expr)).map_or(0, Vec::len) as u32
+        }
+        Expr::UnOp(_, e)
+        | Expr::Break(_, e)
+        | Expr::Set(_, e)
+        | Expr::SetGet(_, e)
+        | Expr::Assert(e)
+        | Expr::UserError(e) => depth(e, loops),
+        Expr::BinOp(_, e1, e2) => depth(e1, loops).max(depth(e2, loops) + 1),
+        Expr::If(e1, e2, e3) => depth(e1, loops).max(depth(e2, loops)).max(depth(e3, loops)),
+        Expr::Block(es) => es.iter().map(|e| depth(e, loops)).max().unwrap_or(0),
+        Expr::Let(bindings, body) | Expr::NamedLet(_, bindings, body) => bindings
             .iter()
             .enumerate()
-            .map(|(i, (_, e))| depth(e) + (i as u32))
+            .map(|(i, (_, e))| depth(e, loops) + (i as u32))
             .max()
             .unwrap_or(0)
-            .max(depth(body) + bindings.len() as u32),
-        Expr::Call(_, args) | Expr::Vec(args) => args
+            .max(depth(body, loops) + bindings.len() as u32),
+        Expr::Call(_, args) => args
             .iter()
             .enumerate()
-            .map(|(i, e)| depth(e) + (i as u32))
+            .map(|(i, e)| depth(e, loops) + (i as u32))
             .max()
             .unwrap_or(0)
             .max(args.len() as u32),
-        Expr::VecLen(e) => depth(e),
-        Expr::VecGet(vec, offset) => depth(vec).max(depth(offset) + 1),
-        Expr::VecSet(vec, index, value) => depth(vec)
-            .max(depth(index) + 1)
-            .max(depth(value) + 2)
+        // Elements are stored on the heap as soon as they are computed, so they all reuse the
+        // slots after the one holding the vector address
+        Expr::Vec(args) | Expr::MakeStruct(_, args) => args
+            .iter()
+            .map(|e| depth(e, loops) + 1)
+            .max()
+            .unwrap_or(0)
+            .max(1),
+        Expr::VecLen(e)
+        | Expr::VecReverse(e)
+        | Expr::VecSort(e)
+        | Expr::VecSum(e)
+        | Expr::VecProduct(e)
+        | Expr::GetField(e, _) => depth(e, loops),
+        Expr::SetField(e, _, value) => depth(e, loops).max(depth(value, loops) + 2).max(2),
+        Expr::VecGet(vec, offset) => depth(vec, loops).max(depth(offset, loops) + 1),
+        Expr::VecContains(vec, e) | Expr::VecIndexOf(vec, e) => {
+            depth(vec, loops).max(depth(e, loops) + 1)
+        }
+        Expr::VecSet(vec, index, value) => depth(vec, loops)
+            .max(depth(index, loops) + 1)
+            .max(depth(value, loops) + 2)
             .max(2),
-        Expr::MakeVec(size, elem) => depth(size).max(depth(elem) + 1).max(2),
+        Expr::MakeVec(size, elem) => depth(size, loops).max(depth(elem, loops) + 1).max(2),
+        Expr::VecMap(_, vec) => depth(vec, loops).max(3),
+        Expr::VecForEach(_, vec) => depth(vec, loops).max(2),
+        Expr::Apply(_, args) => depth(args, loops),
+        Expr::Case(e, clauses, else_clause) => clauses
+            .iter()
+            .map(|(_, body)| depth(body, loops))
+            .chain(else_clause.iter().map(|body| depth(body, loops)))
+            .max()
+            .unwrap_or(0)
+            .max(depth(e, loops)),
+        Expr::Match(e, arms) => arms
+            .iter()
+            .map(|(pattern, body)| 1 + pattern_slots(pattern) + depth(body, loops))
+            .max()
+            .unwrap_or(0)
+            .max(depth(e, loops)),
+    }
+}
+
+// Calculates the number of stack words used to hold the vector elements a pattern destructures.
+fn pattern_slots(pattern: &Pattern) -> u32 {
+    match pattern {
+        Pattern::Vec(elems) => elems.iter().map(|elem| 1 + pattern_slots(elem)).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{quickcheck, Arbitrary, Gen};
+
+    // Returns the change in RSP caused by the instructions, which only adjust RSP directly
+    fn rsp_change(instrs: &[Instr]) -> i64 {
+        let mut rsp = 0;
+        for instr in instrs {
+            match instr {
+                Instr::Push(_) => rsp -= WORD_SIZE,
+                Instr::Pop(_) | Instr::Ret() => rsp += WORD_SIZE,
+                Instr::Sub(Val::Reg(Reg::RSP), Val::Imm(n)) => rsp -= n,
+                Instr::Add(Val::Reg(Reg::RSP), Val::Imm(n)) => rsp += n,
+                _ => (),
+            }
+        }
+        return rsp;
+    }
+
+    // Returns the frame size of the main expression of the program
+    fn main_frame_size(source: &str) -> u32 {
+        let program = crate::parser::parse_source(source).unwrap();
+        let mut loops = HashMap::new();
+        find_loop_invariants(&program.main, &mut loops);
+        return frame_size(depth(&program.main, &loops), &[Val::Reg(Reg::RBP)]);
+    }
+
+    #[test]
+    fn disjoint_subexpressions_reuse_slots() {
+        let stmt = "(let ((x 1) (y (vec 2 3))) (vec-get y x))";
+        let one = main_frame_size(&format!("(block {stmt})"));
+        let many = main_frame_size(&format!("(block {})", [stmt; 50].join(" ")));
+        assert_eq!(one, many);
+
+        let elems: Vec<String> = (0..50).map(|i| format!("(+ {i} input)")).collect();
+        let small_vec = main_frame_size(&format!("(vec {})", elems[..2].join(" ")));
+        let large_vec = main_frame_size(&format!("(vec {})", elems.join(" ")));
+        assert_eq!(small_vec, large_vec);
+    }
+
+    #[test]
+    fn constant_let_bindings_are_propagated() {
+        let program = crate::parser::parse_source("(let ((x 3)) (+ x x))").unwrap();
+        let instrs = compile_program(&program, "our_code_starts_here".to_string()).unwrap();
+        let asm: Vec<String> = instrs.iter().map(instr_to_str).collect();
+        assert!(asm.iter().any(|s| s.ends_with("rax, 12")));
+        // Neither the binding nor the addition is computed at runtime
+        assert!(!asm.iter().any(|s| s.ends_with("rax, 6")));
+        assert!(!asm.iter().any(|s| s.trim().starts_with("add rax")));
+    }
+
+    #[test]
+    fn loop_invariants_are_hoisted() {
+        let source = "(let ((v (vec 1 2 3)) (i 0)) (loop (if (< i (vec-len v)) (set! i (add1 i)) (break i))))";
+        let program = crate::parser::parse_source(source).unwrap();
+        let instrs = compile_program(&program, "our_code_starts_here".to_string()).unwrap();
+        let asm: Vec<String> = instrs
+            .iter()
+            .map(|i| instr_to_str(i).trim().to_string())
+            .collect();
+        let loop_start = asm.iter().position(|s| s.starts_with("loop_")).unwrap();
+        // The length of v is loaded once, before the loop
+        let vec_len: Vec<usize> = (0..asm.len())
+            .filter(|i| asm[*i] == "mov rax, [rax]")
+            .collect();
+        assert_eq!(vec_len.len(), 1);
+        assert!(vec_len[0] < loop_start);
+    }
+
+    #[test]
+    fn frame_keeps_call_sites_aligned() {
+        let regs = [Reg::RBP, Reg::R11, Reg::R12, Reg::R13, Reg::R14];
+        for num_saved in 0..=regs.len() {
+            let callee_saved: Vec<Val> = regs[..num_saved].iter().map(|r| Val::Reg(*r)).collect();
+            for locals in 0..8 {
+                // RSP is 16-byte aligned at the caller's call, which then pushes the return address
+                let mut entry = Vec::new();
+                fun_entry(locals, &callee_saved, &mut entry);
+                let rsp = -WORD_SIZE + rsp_change(&entry);
+                assert_eq!(
+                    rsp % 16,
+                    0,
+                    "misaligned with {locals} locals and {num_saved} saved registers"
+                );
+
+                // Every local has a slot, with at most one word of padding
+                let size = frame_size(locals, &callee_saved);
+                assert!(size == locals || size == locals + 1);
+
+                // Calls push an even number of arguments, so they stay aligned
+                for num_args in 0..4 {
+                    let args = vec![Val::Imm(0); num_args];
+                    let mut call = Vec::new();
+                    fun_call("f", args, &mut call);
+                    let pushed = rsp_change(&call[..num_args + num_args % 2]);
+                    assert_eq!((rsp + pushed) % 16, 0);
+                }
+
+                // The exit restores RSP to where it was before the call
+                let mut exit = Vec::new();
+                fun_exit(locals, &callee_saved, &mut exit);
+                let exit = rsp_change(&exit);
+                assert_eq!(rsp + exit, 0);
+            }
+        }
+    }
+
+    // Definitions the generated main expressions can use
+    const PRELUDE: &str =
+        "(struct point x y) (fun (f a b) a) (fun (g a) a) (fun (h a (b 2) . rest) a)";
+
+    // Source of a random main expression that compiles, for checking its stack usage
+    #[derive(Debug, Clone)]
+    struct Source(String);
+
+    impl Arbitrary for Source {
+        fn arbitrary(g: &mut Gen) -> Source {
+            let size = g.size();
+            return Source(arbitrary_expr(g, size, &[], false));
+        }
+
+        // Shrinks to the subexpressions, which may not compile if they use ids bound outside them
+        fn shrink(&self) -> Box<dyn Iterator<Item = Source>> {
+            let source = self.0.to_string();
+            let mut subexprs = Vec::new();
+            for (start, _) in source.match_indices('(').skip(1) {
+                let mut open = 0;
+                for (i, c) in source[start..].char_indices() {
+                    match c {
+                        '(' => open += 1,
+                        ')' if open == 1 => {
+                            subexprs.push(Source(source[start..start + i + 1].to_string()));
+                            break;
+                        }
+                        ')' => open -= 1,
+                        _ => (),
+                    }
+                }
+            }
+            return Box::new(subexprs.into_iter());
+        }
+    }
+
+    // Generates an expression with about size nodes that only refers to the ids in scope, and
+    // only breaks within a loop
+    fn arbitrary_expr(g: &mut Gen, size: usize, scope: &[String], in_loop: bool) -> String {
+        if size == 0 || u8::arbitrary(g) % 8 == 0 {
+            let mut leaves = vec!["1".to_string(), "true".to_string(), "nil".to_string()];
+            leaves.push("input".to_string());
+            leaves.extend(scope.iter().cloned());
+            return g.choose(&leaves).unwrap().to_string();
+        }
+        let sub = |g: &mut Gen| arbitrary_expr(g, size / 2, scope, in_loop);
+        match u8::arbitrary(g) % 24 {
+            0 => return format!("(add1 {})", sub(g)),
+            1 => return format!("(+ {} {})", sub(g), sub(g)),
+            2 => return format!("(< {} {})", sub(g), sub(g)),
+            3 => return format!("(== {} {})", sub(g), sub(g)),
+            4 => return format!("(if {} {} {})", sub(g), sub(g), sub(g)),
+            5 => return format!("(block {} {})", sub(g), sub(g)),
+            6 => {
+                let count = 1 + usize::arbitrary(g) % 3;
+                let names: Vec<String> = (0..count)
+                    .map(|i| format!("x{}_{i}", scope.len()))
+                    .collect();
+                let bindings: Vec<String> = names
+                    .iter()
+                    .map(|name| format!("({name} {})", sub(g)))
+                    .collect();
+                let inner = [scope, &names[..]].concat();
+                let body = arbitrary_expr(g, size / 2, &inner, in_loop);
+                return format!("(let ({}) {body})", bindings.join(" "));
+            }
+            7 => return format!("(loop {})", arbitrary_expr(g, size / 2, scope, true)),
+            8 if in_loop => return format!("(break {})", sub(g)),
+            9 if !scope.is_empty() => {
+                return format!("(set! {} {})", g.choose(scope).unwrap(), sub(g));
+            }
+            10 if bool::arbitrary(g) => return format!("(f {} {})", sub(g), sub(g)),
+            10 => {
+                let args: Vec<String> = (0..1 + usize::arbitrary(g) % 4).map(|_| sub(g)).collect();
+                return format!("(h {})", args.join(" "));
+            }
+            11 => return format!("(vec {} {} {})", sub(g), sub(g), sub(g)),
+            12 => return format!("(vec-get {} {})", sub(g), sub(g)),
+            13 => return format!("(vec-set! {} {} {})", sub(g), sub(g), sub(g)),
+            14 => return format!("(vec-len {})", sub(g)),
+            15 => return format!("(make-vec {} {})", sub(g), sub(g)),
+            16 => return format!("(vec-map g {})", sub(g)),
+            17 => return format!("(vec-for-each g {})", sub(g)),
+            18 => {
+                let (a, b) = (format!("m{}_0", scope.len()), format!("m{}_1", scope.len()));
+                let inner = [scope, &[a.to_string(), b.to_string()]].concat();
+                let body = arbitrary_expr(g, size / 2, &inner, in_loop);
+                return format!(
+                    "(match {} ((vec {a} (vec _ {b})) {body}) (1 {}) (_ {}))",
+                    sub(g),
+                    sub(g),
+                    sub(g)
+                );
+            }
+            19 => return format!("(case {} (1 {}) (else {}))", sub(g), sub(g), sub(g)),
+            20 => return format!("(make point {} {})", sub(g), sub(g)),
+            21 => return format!("(get-field {} x)", sub(g)),
+            22 => return format!("(set-field! {} y {})", sub(g), sub(g)),
+            _ => {
+                // A named let that calls itself in tail position
+                let name = format!("l{}", scope.len());
+                let param = format!("{name}_0");
+                let inner = [scope, &[param.to_string()]].concat();
+                let sub_inner = |g: &mut Gen| arbitrary_expr(g, size / 2, &inner, in_loop);
+                return format!(
+                    "(let {name} (({param} {})) (if {} ({name} {}) {}))",
+                    sub(g),
+                    sub_inner(g),
+                    sub_inner(g),
+                    sub_inner(g)
+                );
+            }
+        }
+    }
+
+    // Compiles the main expression of the source, returning the number of stack slots it reserves
+    // and the largest slot its instructions use, or None if it does not compile
+    fn slots_reserved_and_used(source: &str) -> Option<(i64, i64)> {
+        let program = crate::parser::parse_source(&format!("{PRELUDE} {source}")).unwrap();
+        let fun_map: HashMap<String, &FunDef> = program
+            .defs
+            .iter()
+            .map(|def| (def.name.to_string(), def))
+            .collect();
+        let structs = HashMap::unit(
+            "point".to_string(),
+            StructInfo {
+                id: 0,
+                fields: vec!["x".to_string(), "y".to_string()],
+            },
+        );
+        let constants = find_constants(&program.main, None).ok()?;
+        let mut loops = HashMap::new();
+        find_loop_invariants(&program.main, &mut loops);
+        let ctxt = Context {
+            si: 0,
+            env: &HashMap::default(),
+            loops: &HashMap::new(),
+            fun_map: &fun_map,
+            compiling_main: true,
+            label_ctr: &Cell::new(0),
+            label_prefix: "",
+            stable_labels: None,
+            label_path: "",
+            loop_targets: &HashMap::new(),
+            structs: &structs,
+            unchecked: false,
+            numeric_equal: false,
+            input: None,
+            constants: &constants,
+            hoisted: &HashMap::new(),
+            loop_invariants: &loops,
+            static_vecs: &HashMap::new(),
+        };
+        let mut instrs = Vec::new();
+        compile_expr(&program.main, &ctxt, &mut instrs).ok()?;
+
+        // Locals are the only values addressed below RBP
+        let mut used = 0;
+        for instr in instrs.iter() {
+            let s = instr_to_str(instr);
+            for (start, _) in s.match_indices("[rbp - ") {
+                let rest = &s[start + "[rbp - ".len()..];
+                let offset: i64 = rest[..rest.find(']').unwrap()].parse().unwrap();
+                used = used.max(offset / WORD_SIZE);
+            }
+        }
+        return Some((depth(&program.main, &loops) as i64, used));
+    }
+
+    quickcheck! {
+        fn depth_covers_every_slot_used(source: Source) -> bool {
+            match slots_reserved_and_used(&source.0) {
+                Some((reserved, used)) => used <= reserved,
+                None => true,
+            }
+        }
     }
 }
@@ -14,6 +14,13 @@ pub const OFFSET_TO_NUMBER_SHIFT: i64 = 3;
 pub const I63_MIN: i64 = -4611686018427387904;
 pub const I63_MAX: i64 = 4611686018427387903;
 
+// Stack size of compiler threads. Parsing and compiling recurse on the nesting depth of the
+// program, so deeply nested expressions need much more than the default thread stack.
+pub const COMPILER_STACK_SIZE: usize = 1 << 30;
+
+// Number of runtime inputs (input0 .. input15) the runtime passes to the compiled code
+pub const MAX_INPUTS: usize = 16;
+
 pub const NIL_VAL: i64 = 1;
 pub const FALSE_VAL: i64 = 3;
 pub const TRUE_VAL: i64 = 7;
@@ -26,9 +33,17 @@ pub enum ErrCode {
     InvalidType = 2,
     IndexOutOfBounds = 3,
     InvalidVecSize = 4,
+    MatchFailure = 5,
+    AssertionFailed = 6,
+    DivideByZero = 7,
+    WrongArgCount = 8,
 }
 
 pub const NUM_OVERFLOW_LABEL: &str = "error_numeric_overflow";
 pub const INVALID_TYPE_LABEL: &str = "error_invalid_type";
 pub const INDEX_OUT_OF_BOUNDS_LABEL: &str = "error_index_out_of_bounds";
 pub const INVALID_VEC_SIZE_LABEL: &str = "error_invalid_vec_size";
+pub const MATCH_FAILURE_LABEL: &str = "error_match_failure";
+pub const ASSERTION_FAILED_LABEL: &str = "error_assertion_failed";
+pub const DIVIDE_BY_ZERO_LABEL: &str = "error_divide_by_zero";
+pub const WRONG_ARG_COUNT_LABEL: &str = "error_wrong_arg_count";
@@ -0,0 +1,393 @@
+/**
+ * Graphviz rendering of the syntax tree, for visualizing how programs parse.
+ */
+use std::fmt::Write;
+
+use crate::syntax::{Expr, Op1, Op2, Pattern, Program, Ty};
+
+// Returns the program as a Graphviz digraph: each expression is a labeled box, with edges
+// to its subexpressions labeled by the role or binding name of the subexpression
+pub fn program_to_dot(prog: &Program) -> String {
+    let mut graph = Graph {
+        out: String::new(),
+        next_id: 0,
+    };
+    graph.out.push_str("digraph program {\n");
+    graph.out.push_str("  node [shape=box];\n");
+    for def in prog.structs.iter() {
+        graph.node(&format!("struct {} ({})", def.name, def.fields.join(" ")));
+    }
+    for def in prog.defs.iter() {
+        let first_default = def.params.len() - def.defaults.len();
+        let mut params: Vec<String> = Vec::new();
+        for (i, param) in def.params.iter().enumerate() {
+            let param = match def.param_types.get(i).copied().flatten() {
+                Some(ty) => format!("({param} : {})", type_name(ty)),
+                None => param.to_string(),
+            };
+            if i < first_default {
+                params.push(param);
+            } else {
+                let default = &def.defaults[i - first_default];
+                params.push(format!("({param} {})", literal_to_string(default)));
+            }
+        }
+        if let Some(rest) = &def.rest {
+            params.push(format!(". {rest}"));
+        }
+        let return_type = match def.return_type {
+            Some(ty) => format!(" : {}", type_name(ty)),
+            None => String::new(),
+        };
+        let fun = graph.node(&format!(
+            "fun {} ({}){return_type}",
+            def.name,
+            params.join(" ")
+        ));
+        let body = graph.expr(&def.body);
+        graph.edge(fun, body, "body");
+    }
+    let main = graph.node("main");
+    let body = graph.expr(&prog.main);
+    graph.edge(main, body, "body");
+    graph.out.push_str("}\n");
+    return graph.out;
+}
+
+// The digraph being written, and the id of the next node. Every node gets a fresh id, so
+// subexpressions that look the same are still drawn separately.
+struct Graph {
+    out: String,
+    next_id: usize,
+}
+
+impl Graph {
+    // Adds a node with the given label and returns its id
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        writeln!(self.out, "  n{id} [label=\"{}\"];", escape(label)).unwrap();
+        return id;
+    }
+
+    fn edge(&mut self, from: usize, to: usize, label: &str) {
+        if label.is_empty() {
+            writeln!(self.out, "  n{from} -> n{to};").unwrap();
+        } else {
+            writeln!(
+                self.out,
+                "  n{from} -> n{to} [label=\"{}\"];",
+                escape(label)
+            )
+            .unwrap();
+        }
+    }
+
+    // Adds a subexpression as a child of the given node
+    fn child(&mut self, parent: usize, e: &Expr, label: &str) {
+        let id = self.expr(e);
+        self.edge(parent, id, label);
+    }
+
+    // Adds the nodes for the expression and its subexpressions, returning the id of its root
+    fn expr(&mut self, e: &Expr) -> usize {
+        match e {
+            Expr::Number(n) => return self.node(&n.to_string()),
+            Expr::Boolean(b) => return self.node(&b.to_string()),
+            Expr::Input(index) => return self.node(&format!("input{index}")),
+            Expr::Read => return self.node("read"),
+            Expr::Nil => return self.node("nil"),
+            Expr::Id(name) => return self.node(name),
+            Expr::Let(bindings, body) => {
+                let id = self.node("let");
+                for (name, e) in bindings.iter() {
+                    self.child(id, e, name);
+                }
+                self.child(id, body, "body");
+                return id;
+            }
+            Expr::NamedLet(name, bindings, body) => {
+                let id = self.node(&format!("let {name}"));
+                for (name, e) in bindings.iter() {
+                    self.child(id, e, name);
+                }
+                self.child(id, body, "body");
+                return id;
+            }
+            Expr::UnOp(op, e) => {
+                let id = self.node(op1_name(*op));
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::BinOp(op, e1, e2) => {
+                let id = self.node(op2_name(*op));
+                self.child(id, e1, "");
+                self.child(id, e2, "");
+                return id;
+            }
+            Expr::If(cond, thn, els) => {
+                let id = self.node("if");
+                self.child(id, cond, "cond");
+                self.child(id, thn, "then");
+                self.child(id, els, "else");
+                return id;
+            }
+            Expr::Loop(name, body) => {
+                let id = self.node(&with_name("loop", name));
+                self.child(id, body, "");
+                return id;
+            }
+            Expr::Break(name, e) => {
+                let id = self.node(&with_name("break", name));
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::Continue(name) => return self.node(&with_name("continue", name)),
+            Expr::Set(name, e) => {
+                let id = self.node(&format!("set! {name}"));
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::SetGet(name, e) => {
+                let id = self.node(&format!("set-get! {name}"));
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::Block(es) => {
+                let id = self.node("block");
+                for e in es.iter() {
+                    self.child(id, e, "");
+                }
+                return id;
+            }
+            Expr::Call(name, args) => {
+                let id = self.node(&format!("call {name}"));
+                for e in args.iter() {
+                    self.child(id, e, "");
+                }
+                return id;
+            }
+            Expr::Vec(es) => {
+                let id = self.node("vec");
+                for e in es.iter() {
+                    self.child(id, e, "");
+                }
+                return id;
+            }
+            Expr::VecGet(vec, index) => {
+                let id = self.node("vec-get");
+                self.child(id, vec, "vec");
+                self.child(id, index, "index");
+                return id;
+            }
+            Expr::VecSet(vec, index, e) => {
+                let id = self.node("vec-set!");
+                self.child(id, vec, "vec");
+                self.child(id, index, "index");
+                self.child(id, e, "value");
+                return id;
+            }
+            Expr::VecLen(vec) => {
+                let id = self.node("vec-len");
+                self.child(id, vec, "");
+                return id;
+            }
+            Expr::VecContains(vec, e) => {
+                let id = self.node("vec-contains?");
+                self.child(id, vec, "vec");
+                self.child(id, e, "value");
+                return id;
+            }
+            Expr::VecIndexOf(vec, e) => {
+                let id = self.node("vec-index-of");
+                self.child(id, vec, "vec");
+                self.child(id, e, "value");
+                return id;
+            }
+            Expr::VecReverse(vec) => {
+                let id = self.node("vec-reverse");
+                self.child(id, vec, "");
+                return id;
+            }
+            Expr::VecSort(vec) => {
+                let id = self.node("vec-sort");
+                self.child(id, vec, "");
+                return id;
+            }
+            Expr::VecSum(vec) => {
+                let id = self.node("vec-sum");
+                self.child(id, vec, "");
+                return id;
+            }
+            Expr::VecProduct(vec) => {
+                let id = self.node("vec-product");
+                self.child(id, vec, "");
+                return id;
+            }
+            Expr::MakeVec(size, e) => {
+                let id = self.node("make-vec");
+                self.child(id, size, "size");
+                self.child(id, e, "value");
+                return id;
+            }
+            Expr::VecMap(f, vec) => {
+                let id = self.node("vec-map");
+                self.child(id, f, "fun");
+                self.child(id, vec, "vec");
+                return id;
+            }
+            Expr::VecForEach(f, vec) => {
+                let id = self.node("vec-for-each");
+                self.child(id, f, "fun");
+                self.child(id, vec, "vec");
+                return id;
+            }
+            Expr::Apply(f, args) => {
+                let id = self.node("apply");
+                self.child(id, f, "fun");
+                self.child(id, args, "args");
+                return id;
+            }
+            Expr::Match(e, arms) => {
+                let id = self.node("match");
+                self.child(id, e, "");
+                for (pattern, body) in arms.iter() {
+                    self.child(id, body, &pattern_to_string(pattern));
+                }
+                return id;
+            }
+            Expr::Assert(e) => {
+                let id = self.node("assert");
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::UserError(e) => {
+                let id = self.node("error");
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::Case(e, arms, default) => {
+                let id = self.node("case");
+                self.child(id, e, "");
+                for (n, body) in arms.iter() {
+                    self.child(id, body, &n.to_string());
+                }
+                if let Some(default) = default {
+                    self.child(id, default, "else");
+                }
+                return id;
+            }
+            Expr::MakeStruct(name, fields) => {
+                let id = self.node(&format!("make {name}"));
+                for e in fields.iter() {
+                    self.child(id, e, "");
+                }
+                return id;
+            }
+            Expr::GetField(e, field) => {
+                let id = self.node(&format!("get-field {field}"));
+                self.child(id, e, "");
+                return id;
+            }
+            Expr::SetField(e, field, value) => {
+                let id = self.node(&format!("set-field! {field}"));
+                self.child(id, e, "");
+                self.child(id, value, "value");
+                return id;
+            }
+        }
+    }
+}
+
+fn with_name(keyword: &str, name: &Option<String>) -> String {
+    match name {
+        Some(name) => return format!("{keyword} {name}"),
+        None => return keyword.to_string(),
+    }
+}
+
+// The keyword an operator is written with in the source
+pub(crate) fn op1_name(op: Op1) -> &'static str {
+    match op {
+        Op1::Add1 => "add1",
+        Op1::Sub1 => "sub1",
+        Op1::IsNum => "isnum",
+        Op1::IsBool => "isbool",
+        Op1::IsVec => "isvec",
+        Op1::Print => "print",
+        Op1::Random => "random",
+        Op1::PopCount => "popcount",
+        Op1::CountTrailingZeros => "ctz",
+    }
+}
+
+pub(crate) fn op2_name(op: Op2) -> &'static str {
+    match op {
+        Op2::Plus => "+",
+        Op2::Minus => "-",
+        Op2::Times => "*",
+        Op2::Expt => "expt",
+        Op2::Gcd => "gcd",
+        Op2::Lcm => "lcm",
+        Op2::Quotient => "/",
+        Op2::Remainder => "%",
+        Op2::Div => "div",
+        Op2::Modulo => "modulo",
+        Op2::Min => "min",
+        Op2::Max => "max",
+        Op2::Equal => "=",
+        Op2::Greater => ">",
+        Op2::GreaterEqual => ">=",
+        Op2::Less => "<",
+        Op2::LessEqual => "<=",
+        Op2::StructEqual => "==",
+        Op2::BoolXor => "xor",
+        Op2::PrintTo => "print-to",
+    }
+}
+
+// The name of a type as it is written in annotations
+fn type_name(ty: Ty) -> &'static str {
+    match ty {
+        Ty::Num => "Num",
+        Ty::Bool => "Bool",
+        Ty::Vec => "Vec",
+        Ty::Nil => "Nil",
+        Ty::Any => "Any",
+    }
+}
+
+// A default parameter value as it is written in the source
+fn literal_to_string(e: &Expr) -> String {
+    match e {
+        Expr::Number(n) => return n.to_string(),
+        Expr::Boolean(b) => return b.to_string(),
+        Expr::Nil => return "nil".to_string(),
+        _ => return "...".to_string(),
+    }
+}
+
+// The pattern as it is written in the source
+fn pattern_to_string(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => return "_".to_string(),
+        Pattern::Number(n) => return n.to_string(),
+        Pattern::Boolean(b) => return b.to_string(),
+        Pattern::Nil => return "nil".to_string(),
+        Pattern::Id(name) => return name.to_string(),
+        Pattern::Vec(elems) => {
+            let mut s = "(vec".to_string();
+            for elem in elems.iter() {
+                s.push(' ');
+                s.push_str(&pattern_to_string(elem));
+            }
+            s.push(')');
+            return s;
+        }
+    }
+}
+
+// Escapes the characters that are special inside a quoted DOT string
+fn escape(s: &str) -> String {
+    return s.replace('\\', "\\\\").replace('"', "\\\"");
+}
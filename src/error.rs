@@ -0,0 +1,178 @@
+/**
+ * Errors reported while parsing and compiling a program.
+ */
+use std::fmt;
+
+use crate::syntax::Ty;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    EmptyProgram,
+    InvalidSexp,
+    DuplicateFunction {
+        name: String,
+        first: usize, // 1-based ordinal of the original definition
+        first_params: Vec<String>,
+        redefinition: usize, // 1-based ordinal of the duplicate definition
+        params: Vec<String>,
+    },
+    DuplicateParameter(String), // parameter name
+    DuplicateStruct(String),    // struct name
+    DuplicateField(String),     // field name
+    DuplicateBinding,
+    UnboundId(String), // identifier name
+    NumberOverflow,
+    ConstantOverflow,
+    InputOutsideMain,
+    InvalidInput(String), // input value given at compile time
+    InputOverflow,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    UnknownLoop(String),       // loop name
+    UndefinedFunction(String), // function name
+    WrongArgCount {
+        name: String,
+        found: usize,
+        expected: usize,
+    },
+    ExpectedFunctionName(String), // operator expecting the function name
+    InvalidDefault(String),       // parameter name
+    VariadicFunctionArg {
+        op: String,   // higher-order operator
+        name: String, // variadic function passed to it
+    },
+    NonTailCall(String),     // named let name
+    UndefinedStruct(String), // struct name
+    UndefinedField(String),  // field name
+    WrongFieldCount {
+        name: String,
+        found: usize,
+        expected: usize,
+    },
+    TypeError {
+        op: String, // operator or keyword
+        arg: usize, // 1-based position of the operand
+        expected: Ty,
+        found: Ty,
+    },
+    ReturnTypeError {
+        name: String, // function name
+        expected: Ty,
+        found: Ty,
+    },
+    IncludeCycle(String), // path of the file that includes itself, as written
+    UnreadableInclude {
+        path: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::EmptyProgram => write!(f, "Invalid: empty program"),
+            CompileError::InvalidSexp => write!(f, "Invalid S-expression format"),
+            CompileError::DuplicateFunction {
+                name,
+                first,
+                first_params,
+                redefinition,
+                params,
+            } => write!(
+                f,
+                "Function {name} already defined: definition {redefinition} ({}) redefines definition {first} ({})",
+                params.join(" "),
+                first_params.join(" ")
+            ),
+            CompileError::DuplicateParameter(param) => write!(f, "Duplicate parameter {param}"),
+            CompileError::DuplicateStruct(name) => write!(f, "Struct {name} already defined"),
+            CompileError::DuplicateField(field) => write!(f, "Duplicate field {field}"),
+            CompileError::DuplicateBinding => write!(f, "Duplicate binding"),
+            CompileError::UnboundId(name) => write!(f, "Unbound variable identifier {name}"),
+            CompileError::NumberOverflow => write!(
+                f,
+                "Invalid: number must be in the range of a 63-bit signed integer"
+            ),
+            CompileError::ConstantOverflow => write!(
+                f,
+                "Invalid: constant expression overflows a 63-bit signed integer"
+            ),
+            CompileError::InputOutsideMain => {
+                write!(f, "Invalid: input can only be used in the main expression")
+            }
+            CompileError::InvalidInput(input) => {
+                write!(f, "Invalid: error occurred parsing input {input}")
+            }
+            CompileError::InputOverflow => {
+                write!(f, "Invalid: input overflows a 63-bit signed integer")
+            }
+            CompileError::BreakOutsideLoop => write!(f, "Error: break without surrounding loop"),
+            CompileError::ContinueOutsideLoop => {
+                write!(f, "Error: continue without surrounding loop")
+            }
+            CompileError::UnknownLoop(name) => write!(f, "Error: no surrounding loop named {name}"),
+            CompileError::UndefinedFunction(name) => {
+                write!(f, "Invalid: undefined function {name}")
+            }
+            CompileError::WrongArgCount {
+                name,
+                found,
+                expected,
+            } => write!(
+                f,
+                "Invalid: function {name} called with {found} args, expected {expected}"
+            ),
+            CompileError::ExpectedFunctionName(op) => {
+                write!(f, "Invalid: {op} expects the name of a function")
+            }
+            CompileError::InvalidDefault(name) => write!(
+                f,
+                "Invalid: default value of parameter {name} must be a number, boolean or nil"
+            ),
+            CompileError::VariadicFunctionArg { op, name } => {
+                write!(f, "Invalid: {op} cannot call variadic function {name}")
+            }
+            CompileError::NonTailCall(name) => {
+                write!(
+                    f,
+                    "Invalid: named let {name} can only be called in tail position"
+                )
+            }
+            CompileError::UndefinedStruct(name) => write!(f, "Invalid: undefined struct {name}"),
+            CompileError::UndefinedField(field) => write!(f, "Invalid: undefined field {field}"),
+            CompileError::WrongFieldCount {
+                name,
+                found,
+                expected,
+            } => write!(
+                f,
+                "Invalid: struct {name} made with {found} values, expected {expected}"
+            ),
+            CompileError::TypeError {
+                op,
+                arg,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Type error: argument {arg} of {op} must be {expected}, found {found}"
+            ),
+            CompileError::ReturnTypeError {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Type error: function {name} must return {expected}, found {found}"
+            ),
+            CompileError::IncludeCycle(path) => {
+                write!(f, "Invalid: {path} is included by a file it includes")
+            }
+            CompileError::UnreadableInclude { path, reason } => {
+                write!(f, "Invalid: cannot read included file {path}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
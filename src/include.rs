@@ -0,0 +1,96 @@
+/**
+ * Resolution of include directives, which add the definitions of other files to a program.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::CompileError;
+use crate::parser::parse_included_source;
+use crate::syntax::{FunDef, Program, StructDef};
+
+// A file included by a program, with the path it was read from
+pub struct IncludedFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+// Adds the definitions of the files the program includes, and of the files they include, after
+// its own. The program was read from the given path, and the paths of included files are relative
+// to the file that includes them. Each file is included once, however many files include it.
+// Also returns the included files, in the order their definitions were added.
+pub fn resolve_includes(
+    prog: Program,
+    path: &Path,
+) -> Result<(Program, Vec<IncludedFile>), CompileError> {
+    let mut resolver = Resolver {
+        including: vec![canonical(path)?],
+        included: Vec::new(),
+        files: Vec::new(),
+        structs: Vec::new(),
+        defs: Vec::new(),
+    };
+    resolver.include_all(&prog.includes, path)?;
+
+    let mut structs = prog.structs;
+    structs.extend(resolver.structs);
+    let mut defs = prog.defs;
+    defs.extend(resolver.defs);
+    let prog = Program {
+        structs,
+        defs,
+        includes: Vec::new(),
+        main: prog.main,
+    };
+    return Ok((prog, resolver.files));
+}
+
+// The files being included, from the program down to the current one, which must not be included
+// again while they are, the files already included, their contents in the order their definitions
+// were found, and the definitions found so far
+struct Resolver {
+    including: Vec<PathBuf>,
+    included: Vec<PathBuf>,
+    files: Vec<IncludedFile>,
+    structs: Vec<StructDef>,
+    defs: Vec<FunDef>,
+}
+
+impl Resolver {
+    // Includes each of the paths, written in the file at from
+    fn include_all(&mut self, includes: &[String], from: &Path) -> Result<(), CompileError> {
+        let dir = from.parent().unwrap_or_else(|| Path::new(""));
+        for include in includes.iter() {
+            let path = dir.join(include);
+            let canonical = canonical(&path)?;
+            if self.including.contains(&canonical) {
+                return Err(CompileError::IncludeCycle(include.to_string()));
+            }
+            if self.included.contains(&canonical) {
+                continue;
+            }
+            self.included.push(canonical.clone());
+
+            let contents =
+                fs::read_to_string(&path).map_err(|err| CompileError::UnreadableInclude {
+                    path: path.display().to_string(),
+                    reason: err.to_string(),
+                })?;
+            let prog = parse_included_source(&contents)?;
+            self.including.push(canonical);
+            self.include_all(&prog.includes, &path)?;
+            self.including.pop();
+            self.structs.extend(prog.structs);
+            self.defs.extend(prog.defs);
+            self.files.push(IncludedFile { path, contents });
+        }
+        return Ok(());
+    }
+}
+
+// Returns the canonical form of the path, so that a file is recognized however it is reached
+fn canonical(path: &Path) -> Result<PathBuf, CompileError> {
+    return fs::canonicalize(path).map_err(|err| CompileError::UnreadableInclude {
+        path: path.display().to_string(),
+        reason: err.to_string(),
+    });
+}
@@ -0,0 +1,105 @@
+/**
+ * The Snek compiler as a library: parsing, compilation, and assembly generation.
+ */
+use std::io::{self, Write};
+
+// Modules
+pub mod analysis;
+pub mod assembly;
+pub mod compiler;
+pub mod constants;
+pub mod dot;
+pub mod error;
+pub mod include;
+pub mod linemap;
+pub mod link;
+pub mod parser;
+mod start;
+pub mod syntax;
+pub mod typecheck;
+
+pub use analysis::{find_warnings, Warning};
+pub use assembly::{
+    instructions_to_string, write_instructions, write_instructions_in, Instr, Syntax,
+};
+pub use compiler::{compile_program, compile_program_with_options, CompileOptions};
+pub use dot::program_to_dot;
+pub use error::CompileError;
+pub use include::{resolve_includes, IncludedFile};
+pub use linemap::line_map;
+pub use parser::{parse_program, parse_source};
+pub use syntax::{Expr, Program};
+pub use typecheck::typecheck;
+
+// Label of the entry point called by the runtime
+pub const START_LABEL: &str = "our_code_starts_here";
+
+// Serializes the parsed program to JSON, for tools that consume the syntax tree
+#[cfg(feature = "serde")]
+pub fn program_to_json(prog: &Program) -> String {
+    return serde_json::to_string(prog).expect("programs are always serializable");
+}
+
+// Reads a program serialized by program_to_json
+#[cfg(feature = "serde")]
+pub fn program_from_json(json: &str) -> Result<Program, serde_json::Error> {
+    return serde_json::from_str(json);
+}
+
+// Compiles the source code of a Snek program into a complete assembly file
+pub fn compile_source(contents: &str) -> Result<String, CompileError> {
+    let program = parse_source(contents)?;
+    let compiled_instrs = compile_program(&program, START_LABEL.to_string())?;
+
+    let mut asm_program: Vec<u8> = Vec::new();
+    write_program(&mut asm_program, &compiled_instrs).expect("writing to a Vec cannot fail");
+    return Ok(String::from_utf8(asm_program).expect("assembly is valid UTF-8"));
+}
+
+// Writes a complete assembly file for the compiled instructions: the section and symbol
+// declarations followed by the instructions themselves
+pub fn write_program<W: Write>(w: &mut W, instrs: &[Instr]) -> io::Result<()> {
+    return write_program_in(w, instrs, Syntax::Intel);
+}
+
+// Writes a complete assembly file for the compiled instructions in the given syntax
+pub fn write_program_in<W: Write>(w: &mut W, instrs: &[Instr], syntax: Syntax) -> io::Result<()> {
+    match syntax {
+        Syntax::Intel => write!(
+            w,
+            "
+    section .text
+    global {START_LABEL}
+    extern snek_error
+    extern snek_print
+    extern snek_print_fd
+    extern snek_equals
+    extern snek_gcd
+    extern snek_lcm
+    extern snek_vec_index_of
+    extern snek_sort
+    extern snek_random
+    extern snek_read
+"
+        )?,
+        Syntax::Att => write!(
+            w,
+            "
+    .text
+    .globl {START_LABEL}
+    .extern snek_error
+    .extern snek_print
+    .extern snek_print_fd
+    .extern snek_equals
+    .extern snek_gcd
+    .extern snek_lcm
+    .extern snek_vec_index_of
+    .extern snek_sort
+    .extern snek_random
+    .extern snek_read
+"
+        )?,
+    }
+    write_instructions_in(w, instrs, syntax)?;
+    return Ok(());
+}
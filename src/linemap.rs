@@ -0,0 +1,100 @@
+/**
+ * Line maps from the labels of the compiled code to the source lines they come from, for
+ * debugging compiled programs.
+ */
+use std::path::Path;
+
+use crate::include::IncludedFile;
+use crate::parser::{normalize_source, strip_comments};
+use crate::syntax::Program;
+
+// Returns the label of each function, then the label of the main expression, with the line where
+// its definition starts and the path of the included file it is defined in, if it is not defined
+// in the source of the program. The program must be the one parsed from the source, with the
+// definitions of the included files added after its own.
+pub fn line_map<'a>(
+    contents: &str,
+    prog: &Program,
+    included: &'a [IncludedFile],
+    start_label: &str,
+) -> Vec<(String, usize, Option<&'a Path>)> {
+    let source = strip_comments(&normalize_source(contents));
+    let source_forms = forms(&source);
+
+    // The functions of each file are in the order they are defined in it, including those defined
+    // in blocks, and their names are their labels
+    let mut labels: Vec<(String, usize, Option<&Path>)> = Vec::new();
+    let mut defs = prog.defs.iter();
+    for (line, head, _) in source_forms.iter() {
+        if *head == "fun" {
+            if let Some(def) = defs.next() {
+                labels.push((def.name.to_string(), *line, None));
+            }
+        }
+    }
+    for file in included.iter() {
+        let source = strip_comments(&normalize_source(&file.contents));
+        for (line, head, _) in forms(&source).iter() {
+            if *head == "fun" {
+                if let Some(def) = defs.next() {
+                    labels.push((def.name.to_string(), *line, Some(file.path.as_path())));
+                }
+            }
+        }
+    }
+    // The main expression is the last top-level form
+    if let Some((line, _, _)) = source_forms
+        .iter()
+        .rev()
+        .find(|(_, _, top_level)| *top_level)
+    {
+        labels.push((start_label.to_string(), *line, None));
+    }
+    return labels;
+}
+
+// Returns the line where each top-level form and each function definition of the source starts,
+// with the first atom of the form if it is a list, and whether it is a top-level form. The source
+// must not contain comments.
+fn forms(source: &str) -> Vec<(usize, &str, bool)> {
+    let mut forms: Vec<(usize, &str, bool)> = Vec::new();
+    let mut line = 1;
+    let mut depth = 0;
+    let mut in_atom = false;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in source.char_indices() {
+        if c == '\n' {
+            line += 1;
+        }
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '(' => {
+                let head = source[i + 1..]
+                    .trim_start()
+                    .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .next()
+                    .unwrap_or("");
+                if depth == 0 || head == "fun" {
+                    forms.push((line, head, depth == 0));
+                }
+                depth += 1;
+            }
+            ')' => depth -= 1,
+            '"' => in_string = true,
+            _ if depth == 0 && !in_atom && !c.is_whitespace() => forms.push((line, "", true)),
+            _ => (),
+        }
+        in_atom = depth == 0 && !c.is_whitespace() && c != '(' && c != ')';
+    }
+    return forms;
+}
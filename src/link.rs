@@ -0,0 +1,86 @@
+/**
+ * Assembles and links generated assembly with the runtime into an executable.
+ */
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::assembly::Syntax;
+
+// The runtime that compiled programs are linked against
+const RUNTIME_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/runtime/start.rs");
+
+// Assembles the file at asm_path with nasm and links it with the runtime.
+// Returns the path of the executable, which is asm_path with the extension replaced by "run".
+pub fn link(asm_path: &Path) -> Result<PathBuf, String> {
+    return link_in(asm_path, Syntax::Intel);
+}
+
+// Assembles the file at asm_path, written in the given syntax, and links it with the runtime.
+// Intel syntax is assembled with nasm and AT&T syntax with as.
+pub fn link_in(asm_path: &Path, syntax: Syntax) -> Result<PathBuf, String> {
+    let dir = match asm_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let stem = match asm_path.file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => return Err(format!("invalid output path {}", asm_path.display())),
+    };
+    let obj_path = asm_path.with_extension("o");
+    let lib_path = dir.join(format!("lib{stem}.a"));
+    let exe_path = asm_path.with_extension("run");
+
+    match syntax {
+        Syntax::Intel => {
+            let arch = if cfg!(target_os = "macos") {
+                "macho64"
+            } else {
+                "elf64"
+            };
+            run_tool(
+                "nasm",
+                Command::new("nasm")
+                    .arg("-f")
+                    .arg(arch)
+                    .arg(asm_path)
+                    .arg("-o")
+                    .arg(&obj_path),
+            )?;
+        }
+        Syntax::Att => {
+            run_tool(
+                "as",
+                Command::new("as").arg(asm_path).arg("-o").arg(&obj_path),
+            )?;
+        }
+    }
+    run_tool(
+        "ar",
+        Command::new("ar").arg("rcs").arg(&lib_path).arg(&obj_path),
+    )?;
+    run_tool(
+        "rustc",
+        Command::new("rustc")
+            .arg("-L")
+            .arg(&dir)
+            .arg(format!("-lour_code:{stem}"))
+            .arg(RUNTIME_PATH)
+            .arg("-o")
+            .arg(&exe_path),
+    )?;
+
+    return Ok(exe_path);
+}
+
+// Runs an external tool, returning its stderr as the error if it fails
+fn run_tool(name: &str, cmd: &mut Command) -> Result<(), String> {
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(err) => return Err(format!("could not run {name}: {err}")),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{name} failed:\n{}", stderr.trim()));
+    }
+    return Ok(());
+}
@@ -1,67 +1,272 @@
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::{BufWriter, IsTerminal};
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
 
-use sexp::*;
+use green_egg_eater::constants::COMPILER_STACK_SIZE;
+use green_egg_eater::link;
+use green_egg_eater::parser::parse_input;
+use green_egg_eater::{
+    compile_program_with_options, find_warnings, line_map, parse_source, program_to_dot,
+    resolve_includes, typecheck, write_program_in, CompileOptions, Program, Syntax, Warning,
+    START_LABEL,
+};
 
-// Modules
-mod assembly;
-mod compiler;
-mod constants;
-mod parser;
-mod start;
-mod syntax;
-
-use assembly::instructions_to_string;
-use compiler::compile_program;
-use parser::parse_program;
+// Flags accepted before or after the file arguments
+const FLAGS: [&str; 13] = [
+    "--link",
+    "--deny-unused",
+    "--deny-type-warnings",
+    "--deny-shadow",
+    "--unchecked",
+    "--numeric-equal",
+    "--stable-labels",
+    "--att",
+    "--emit-json",
+    "--emit-dot",
+    "--verbose",
+    "--typecheck",
+    "--emit-linemap",
+];
+// Prefix of the flag giving the value of the first input at compile time
+const INPUT_FLAG: &str = "--input=";
 
 fn main() -> std::io::Result<()> {
+    let compiler = thread::Builder::new()
+        .stack_size(COMPILER_STACK_SIZE)
+        .spawn(run)?;
+    match compiler.join() {
+        Ok(result) => result,
+        // The compiler thread panicked and already reported the error
+        Err(_) => std::process::exit(1),
+    }
+}
+
+// Compiles the input file given on the command line
+fn run() -> std::io::Result<()> {
     let args: Vec<String> = env::args().collect();
 
+    // With --link, also assemble and link the output into an executable
+    let should_link = args.iter().any(|arg| arg == "--link");
+    // With --deny-unused, unused functions are errors rather than warnings
+    let deny_unused = args.iter().any(|arg| arg == "--deny-unused");
+    // With --deny-type-warnings, comparisons of mismatched types are errors rather than warnings
+    let deny_type_warnings = args.iter().any(|arg| arg == "--deny-type-warnings");
+    // With --deny-shadow, let bindings that shadow parameters are errors rather than warnings
+    let deny_shadow = args.iter().any(|arg| arg == "--deny-shadow");
+    // With --emit-json, the parsed program is written as JSON instead of being compiled
+    let emit_json = args.iter().any(|arg| arg == "--emit-json");
+    // With --emit-dot, the parsed program is written as a Graphviz graph instead of being compiled
+    let emit_dot = args.iter().any(|arg| arg == "--emit-dot");
+    // With --typecheck, programs with type errors that can be found statically are rejected
+    let should_typecheck = args.iter().any(|arg| arg == "--typecheck");
+    // With --emit-linemap, the source line of each function label is also written to a .linemap
+    // file next to the output
+    let emit_linemap = args.iter().any(|arg| arg == "--emit-linemap");
+    // With --verbose, the time each compilation phase takes is logged to stderr
+    let mut timer = PhaseTimer::new(args.iter().any(|arg| arg == "--verbose"));
+    // With --input=VALUE, the first input is compiled into the program instead of read at runtime
+    let input = match args.iter().find_map(|arg| arg.strip_prefix(INPUT_FLAG)) {
+        Some(value) => match parse_input(value) {
+            Ok(input) => Some(input),
+            Err(err) => {
+                report_error(&err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    // With --unchecked, vector accesses are not bounds checked
+    // With --numeric-equal, = only compares numbers
+    // With --stable-labels, labels are named after the position of their expression
+    let options = CompileOptions {
+        unchecked: args.iter().any(|arg| arg == "--unchecked"),
+        numeric_equal: args.iter().any(|arg| arg == "--numeric-equal"),
+        stable_labels: args.iter().any(|arg| arg == "--stable-labels"),
+        input,
+        ..CompileOptions::default()
+    };
+    // With --att, the output is written in AT&T syntax instead of NASM syntax
+    let syntax = if args.iter().any(|arg| arg == "--att") {
+        Syntax::Att
+    } else {
+        Syntax::Intel
+    };
+    let args: Vec<&String> = args
+        .iter()
+        .filter(|arg| !FLAGS.contains(&arg.as_str()) && !arg.starts_with(INPUT_FLAG))
+        .collect();
+    if args.len() != 3 {
+        eprintln!(
+            "Usage: {} [--link] [--deny-unused] [--deny-type-warnings] [--deny-shadow] [--unchecked] [--numeric-equal] [--stable-labels] [--att] [--input=VALUE] [--emit-json] [--emit-dot] [--verbose] [--typecheck] [--emit-linemap] <input.snek> <output.s>",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+
     // Input file path
-    let in_name = &args[1];
+    let in_name = args[1];
 
     // Output file path
-    let out_name = &args[2];
+    let out_name = args[2];
 
     let mut in_file = File::open(in_name)?;
     let mut in_contents = String::new();
     in_file.read_to_string(&mut in_contents)?;
 
-    // First try to parse the file contents as a single main expression.
-    // If that fails, try to parse the file contents as a Program
-    let parsed_sexpr = parse(&in_contents);
-    let sexpr = match parsed_sexpr {
-        Ok(sexpr) => sexpr,
-        Err(_) => {
-            // Try to parse the file as a program
-            let parsed_sexpr = parse(&format!("({in_contents})"));
-            match parsed_sexpr {
-                Ok(sexpr) => sexpr,
-                Err(_) => panic!("Invalid S-expression format"),
-            }
+    let program = match parse_source(&in_contents) {
+        Ok(program) => program,
+        Err(err) => {
+            report_error(&err);
+            std::process::exit(1);
         }
     };
+    let (program, included) = match resolve_includes(program, Path::new(in_name)) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            report_error(&err);
+            std::process::exit(1);
+        }
+    };
+    timer.finish("parse", "");
+    if emit_json {
+        return write_json(&program, out_name);
+    }
+    if emit_dot {
+        let mut out_file = File::create(out_name)?;
+        out_file.write_all(program_to_dot(&program).as_bytes())?;
+        return Ok(());
+    }
+    if should_typecheck {
+        if let Err(err) = typecheck(&program) {
+            report_error(&err);
+            std::process::exit(1);
+        }
+        timer.finish("typecheck", "");
+    }
 
-    let program = parse_program(&sexpr);
-    let compiled_instrs = compile_program(&program, "our_code_starts_here".to_string());
-    let code = instructions_to_string(compiled_instrs);
-
-    let asm_program = format!(
-        "
-    section .text
-    global our_code_starts_here
-    extern snek_error
-    extern snek_print
-    extern snek_equals
-{code}
-    "
+    let compiled_instrs =
+        match compile_program_with_options(&program, START_LABEL.to_string(), &options) {
+            Ok(compiled_instrs) => compiled_instrs,
+            Err(err) => {
+                report_error(&err);
+                std::process::exit(1);
+            }
+        };
+    timer.finish(
+        "codegen",
+        &format!(", {} instructions", compiled_instrs.len()),
     );
 
-    // Write the generated assembly into the output file
-    let mut out_file = File::create(out_name)?;
-    out_file.write_all(asm_program.as_bytes())?;
+    let warnings = find_warnings(&program);
+    timer.finish("analysis", "");
+    for warning in warnings.iter() {
+        eprintln!("{warning}");
+    }
+    if deny_unused
+        && warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::UnusedFunction(_)))
+    {
+        std::process::exit(1);
+    }
+    if deny_type_warnings
+        && warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::TypeMismatch { .. }))
+    {
+        std::process::exit(1);
+    }
+    if deny_shadow
+        && warnings
+            .iter()
+            .any(|warning| matches!(warning, Warning::ShadowedParameter { .. }))
+    {
+        std::process::exit(1);
+    }
+
+    // Stream the generated assembly into the output file
+    let mut out_file = BufWriter::new(File::create(out_name)?);
+    write_program_in(&mut out_file, &compiled_instrs, syntax)?;
+    out_file.flush()?;
+    if emit_linemap {
+        let mut map_file =
+            BufWriter::new(File::create(Path::new(out_name).with_extension("linemap"))?);
+        // Labels defined in included files are followed by the path of the file
+        for (label, line, file) in line_map(&in_contents, &program, &included, START_LABEL) {
+            match file {
+                Some(file) => writeln!(map_file, "{label} {line} {}", file.display())?,
+                None => writeln!(map_file, "{label} {line}")?,
+            }
+        }
+        map_file.flush()?;
+    }
+    timer.finish("write", "");
+
+    if should_link {
+        match link::link_in(Path::new(out_name), syntax) {
+            Ok(exe_path) => {
+                timer.finish("link", "");
+                println!("{}", exe_path.display());
+            }
+            Err(err) => {
+                report_error(&err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Logs each phase of the compilation with the time since the previous one, if enabled
+struct PhaseTimer {
+    enabled: bool,
+    start: Instant,
+}
+
+impl PhaseTimer {
+    fn new(enabled: bool) -> PhaseTimer {
+        PhaseTimer {
+            enabled,
+            start: Instant::now(),
+        }
+    }
 
+    // Logs the phase that just finished, followed by the detail, and starts timing the next one
+    fn finish(&mut self, phase: &str, detail: &str) {
+        if self.enabled {
+            let millis = self.start.elapsed().as_secs_f64() * 1000.0;
+            eprintln!("{phase}: {millis:.3}ms{detail}");
+        }
+        self.start = Instant::now();
+    }
+}
+
+// Prints an error message to stderr. It is red when stderr is a terminal, unless NO_COLOR is set
+// to a non-empty value (see https://no-color.org).
+fn report_error(err: &dyn std::fmt::Display) {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    if !no_color && std::io::stderr().is_terminal() {
+        eprintln!("\x1b[31m{err}\x1b[0m");
+    } else {
+        eprintln!("{err}");
+    }
+}
+
+// Writes the parsed program as JSON into the output file
+#[cfg(feature = "serde")]
+fn write_json(program: &Program, out_name: &str) -> std::io::Result<()> {
+    let mut out_file = File::create(out_name)?;
+    writeln!(out_file, "{}", green_egg_eater::program_to_json(program))?;
     Ok(())
 }
+
+#[cfg(not(feature = "serde"))]
+fn write_json(_program: &Program, _out_name: &str) -> std::io::Result<()> {
+    eprintln!("--emit-json requires the compiler to be built with the serde feature");
+    std::process::exit(1);
+}
@@ -1,34 +1,148 @@
 /**
  * The parser for the compiler.
  */
+use im::{HashMap, HashSet};
 use sexp::Atom::*;
 use sexp::*;
 
+use crate::constants::*;
+use crate::error::CompileError;
 use crate::syntax::*;
 
+// Parses the source code of a Snek program
+pub fn parse_source(contents: &str) -> Result<Program, CompileError> {
+    let contents = &strip_comments(&normalize_source(contents));
+    if contents.trim().is_empty() {
+        return Err(CompileError::EmptyProgram);
+    }
+
+    // First try to parse the file contents as a single main expression.
+    // If that fails, try to parse the file contents as a Program
+    let parsed_sexpr = parse(contents);
+    let sexpr = match parsed_sexpr {
+        Ok(sexpr) => sexpr,
+        Err(_) => {
+            // Try to parse the file as a program
+            let parsed_sexpr = parse(&format!("({contents})"));
+            match parsed_sexpr {
+                Ok(sexpr) => sexpr,
+                Err(_) => return Err(CompileError::InvalidSexp),
+            }
+        }
+    };
+    return Ok(parse_program(&sexpr));
+}
+
+// Removes a leading byte order mark and converts Windows line endings to \n, so files saved by
+// any editor parse the same
+pub(crate) fn normalize_source(contents: &str) -> String {
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    return contents.replace("\r\n", "\n");
+}
+
+// Removes line comments, which start with ; and run to the end of the line. A ; within a string
+// literal does not start a comment.
+pub(crate) fn strip_comments(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut in_comment = false;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in contents.chars() {
+        if in_comment {
+            // Keep the newline so the lines of the program stay the same
+            if c == '\n' {
+                in_comment = false;
+                stripped.push(c);
+            }
+            continue;
+        }
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ';' {
+            in_comment = true;
+            continue;
+        }
+        stripped.push(c);
+    }
+    return stripped;
+}
+
+// Parses an input value given at compile time exactly as the runtime parses the program inputs,
+// returning the value as it is represented at runtime
+pub fn parse_input(input: &str) -> Result<i64, CompileError> {
+    return match input {
+        "false" => Ok(FALSE_VAL),
+        "true" => Ok(TRUE_VAL),
+        _ => match input.parse::<i64>() {
+            Ok(num) if !(I63_MIN..=I63_MAX).contains(&num) => Err(CompileError::InputOverflow),
+            Ok(num) => Ok(num << 1),
+            Err(_) => Err(CompileError::InvalidInput(input.to_string())),
+        },
+    };
+}
+
 // Parses an S-expression into a Snek program
 pub fn parse_program(sexpr: &Sexp) -> Program {
     match sexpr {
         // Program or S-expression surrounded by parentheses
         Sexp::List(vec) => {
             let mut parsing_main_only = true;
+            let mut structs: Vec<StructDef> = Vec::new();
             let mut defs: Vec<FunDef> = Vec::new();
+            // Functions defined in blocks are lifted out after the function they are defined in,
+            // with names that differ from those of the top-level functions
+            let mut taken: HashSet<String> = vec.iter().filter_map(fun_name).collect();
+            let mut lifted: Vec<Sexp> = Vec::new();
+            let mut includes: Vec<String> = Vec::new();
             for def_or_expr in vec {
                 // println!("def_or_expr: {def_or_expr}");
-                if is_fundef(def_or_expr) {
+                if let Some(path) = include_path(def_or_expr) {
+                    parsing_main_only = false;
+                    includes.push(path);
+                } else if is_structdef(def_or_expr) {
+                    parsing_main_only = false;
+                    structs.push(parse_struct_definition(def_or_expr));
+                } else if is_fundef(def_or_expr) {
                     // println!("Parsing definition: {def_or_expr:?}");
                     parsing_main_only = false;
-                    defs.push(parse_definition(def_or_expr));
+                    let name = fun_name(def_or_expr).unwrap_or_default();
+                    let def = lift_definition(
+                        def_or_expr,
+                        &name,
+                        &HashMap::new(),
+                        &mut taken,
+                        &mut lifted,
+                    );
+                    defs.push(parse_definition(&def));
+                    defs.extend(lifted.drain(..).map(|def| parse_definition(&def)));
                 } else {
                     // println!("Parsing expr: {def_or_expr:?}");
-                    let main;
-                    if parsing_main_only {
-                        main = Box::new(parse_sexpr(sexpr))
+                    let main_sexpr = if parsing_main_only {
+                        sexpr
                     } else {
-                        main = Box::new(parse_sexpr(def_or_expr))
-                    }
+                        def_or_expr
+                    };
+                    let main_sexpr = lift_local_functions(
+                        main_sexpr,
+                        "main",
+                        &HashMap::new(),
+                        &mut taken,
+                        &mut lifted,
+                    );
+                    defs.extend(lifted.drain(..).map(|def| parse_definition(&def)));
+                    let main = Box::new(parse_sexpr(&main_sexpr));
                     return Program {
+                        structs,
                         defs,
+                        includes,
                         main,
                     };
                 }
@@ -39,13 +153,17 @@ pub fn parse_program(sexpr: &Sexp) -> Program {
         // S-expression without parentheses
         Sexp::Atom(I(_)) => {
             return Program {
+                structs: vec![],
                 defs: vec![],
+                includes: vec![],
                 main: Box::new(parse_sexpr(sexpr)),
             };
         }
         Sexp::Atom(S(_)) => {
             return Program {
+                structs: vec![],
                 defs: vec![],
+                includes: vec![],
                 main: Box::new(parse_sexpr(sexpr)),
             };
         }
@@ -67,10 +185,22 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
             });
         }
 
+        // Hexadecimal and binary number literals
+        Sexp::Atom(S(name)) if parse_radix_literal(name).is_some() => {
+            Expr::Number(parse_radix_literal(name).unwrap())
+        }
+
         // Literals
         Sexp::Atom(S(name)) if name == "false" => Expr::Boolean(false),
         Sexp::Atom(S(name)) if name == "true" => Expr::Boolean(true),
-        Sexp::Atom(S(name)) if name == "input" => Expr::Input,
+        Sexp::Atom(S(name)) if name == "input" => Expr::Input(0),
+        Sexp::Atom(S(name)) if parse_input_index(name).is_some() => {
+            let index = parse_input_index(name).unwrap();
+            if index >= MAX_INPUTS {
+                panic!("Invalid: at most {MAX_INPUTS} inputs are supported, found {name}");
+            }
+            Expr::Input(index)
+        }
         Sexp::Atom(S(name)) if name == "nil" => Expr::Nil,
 
         // Identifier
@@ -105,6 +235,55 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
             [Sexp::Atom(S(op)), e] if op == "print" => {
                 Expr::UnOp(Op1::Print, Box::new(parse_sexpr(e)))
             }
+            // (max a b c) is (max (max a b) c). A single operand is compared with the number that
+            // leaves it unchanged, so that it is still checked to be a number.
+            [Sexp::Atom(S(op)), first, rest @ ..] if op == "min" || op == "max" => {
+                let (op, identity) = if op == "min" {
+                    (Op2::Min, I63_MAX)
+                } else {
+                    (Op2::Max, I63_MIN)
+                };
+                let first = parse_sexpr(first);
+                if rest.is_empty() {
+                    Expr::BinOp(op, Box::new(first), Box::new(Expr::Number(identity)))
+                } else {
+                    rest.iter().fold(first, |acc, e| {
+                        Expr::BinOp(op, Box::new(acc), Box::new(parse_sexpr(e)))
+                    })
+                }
+            }
+            [Sexp::Atom(S(op))] if op == "min" || op == "max" => {
+                panic!("Invalid: {op} needs at least one argument")
+            }
+            // Exclusive or of booleans
+            [Sexp::Atom(S(op)), e1, e2] if op == "xor" => Expr::BinOp(
+                Op2::BoolXor,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            // Print to stdout (1) or stderr (2)
+            [Sexp::Atom(S(op)), fd, e] if op == "print-to" => Expr::BinOp(
+                Op2::PrintTo,
+                Box::new(parse_sexpr(fd)),
+                Box::new(parse_sexpr(e)),
+            ),
+            [Sexp::Atom(S(op)), e] if op == "random" => {
+                Expr::UnOp(Op1::Random, Box::new(parse_sexpr(e)))
+            }
+            [Sexp::Atom(S(op)), e] if op == "popcount" => {
+                Expr::UnOp(Op1::PopCount, Box::new(parse_sexpr(e)))
+            }
+            [Sexp::Atom(S(op)), e] if op == "ctz" => {
+                Expr::UnOp(Op1::CountTrailingZeros, Box::new(parse_sexpr(e)))
+            }
+
+            // Assert
+            [Sexp::Atom(S(op)), e] if op == "assert" => Expr::Assert(Box::new(parse_sexpr(e))),
+            // User error
+            [Sexp::Atom(S(op)), e] if op == "error" => Expr::UserError(Box::new(parse_sexpr(e))),
+
+            // Read a value from stdin
+            [Sexp::Atom(S(op))] if op == "read" => Expr::Read,
 
             // If
             [Sexp::Atom(S(keyword)), cond, thn, els] if keyword == "if" => Expr::If(
@@ -112,6 +291,12 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                 Box::new(parse_sexpr(thn)),
                 Box::new(parse_sexpr(els)),
             ),
+            // If without an else branch evaluates to nil when the condition is false
+            [Sexp::Atom(S(keyword)), cond, thn] if keyword == "if" => Expr::If(
+                Box::new(parse_sexpr(cond)),
+                Box::new(parse_sexpr(thn)),
+                Box::new(Expr::Nil),
+            ),
 
             // Arithmetic
             [Sexp::Atom(S(op)), e1, e2] if op == "+" => Expr::BinOp(
@@ -129,7 +314,47 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                 Box::new(parse_sexpr(e1)),
                 Box::new(parse_sexpr(e2)),
             ),
-            // Comparison
+            [Sexp::Atom(S(op)), e1, e2] if op == "expt" => Expr::BinOp(
+                Op2::Expt,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            [Sexp::Atom(S(op)), e1, e2] if op == "gcd" => Expr::BinOp(
+                Op2::Gcd,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            [Sexp::Atom(S(op)), e1, e2] if op == "lcm" => Expr::BinOp(
+                Op2::Lcm,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            // Division. / and % truncate toward zero, and are also written quotient and remainder.
+            // div and modulo round the quotient down instead.
+            [Sexp::Atom(S(op)), e1, e2] if op == "/" || op == "quotient" => Expr::BinOp(
+                Op2::Quotient,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            [Sexp::Atom(S(op)), e1, e2] if op == "%" || op == "remainder" => Expr::BinOp(
+                Op2::Remainder,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            [Sexp::Atom(S(op)), e1, e2] if op == "div" => Expr::BinOp(
+                Op2::Div,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            [Sexp::Atom(S(op)), e1, e2] if op == "modulo" => Expr::BinOp(
+                Op2::Modulo,
+                Box::new(parse_sexpr(e1)),
+                Box::new(parse_sexpr(e2)),
+            ),
+            // Comparison.
+            // = compares two values of the same type, vectors by identity; with the numeric_equal
+            // option it compares only numbers. == and its alias equal? compare vectors by their
+            // contents.
             [Sexp::Atom(S(op)), e1, e2] if op == "=" => Expr::BinOp(
                 Op2::Equal,
                 Box::new(parse_sexpr(e1)),
@@ -155,7 +380,7 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                 Box::new(parse_sexpr(e1)),
                 Box::new(parse_sexpr(e2)),
             ),
-            [Sexp::Atom(S(op)), e1, e2] if op == "==" => Expr::BinOp(
+            [Sexp::Atom(S(op)), e1, e2] if op == "==" || op == "equal?" => Expr::BinOp(
                 Op2::StructEqual,
                 Box::new(parse_sexpr(e1)),
                 Box::new(parse_sexpr(e2)),
@@ -169,6 +394,16 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                 }
                 Expr::Let(bindings, Box::new(parse_sexpr(body)))
             }
+            [Sexp::Atom(S(keyword)), Sexp::Atom(S(name)), bindings, body] if keyword == "let" => {
+                if is_keyword(name) {
+                    panic!("Invalid: {name} cannot be used as a named let");
+                }
+                Expr::NamedLet(
+                    name.to_string(),
+                    parse_bindings(bindings),
+                    Box::new(parse_sexpr(body)),
+                )
+            }
 
             // Set!
             [Sexp::Atom(S(op)), Sexp::Atom(S(name)), e] if op == "set!" => {
@@ -178,9 +413,17 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                     Expr::Set(name.to_string(), Box::new(parse_sexpr(e)))
                 }
             }
+            [Sexp::Atom(S(op)), Sexp::Atom(S(name)), e] if op == "set-get!" => {
+                if is_keyword(name) {
+                    panic!("Invalid: {name} cannot be used as a variable identifier");
+                } else {
+                    Expr::SetGet(name.to_string(), Box::new(parse_sexpr(e)))
+                }
+            }
 
             // Block
-            [Sexp::Atom(S(op)), exprs @ ..] if op == "block" => {
+            // begin is an alias for block
+            [Sexp::Atom(S(op)), exprs @ ..] if op == "block" || op == "begin" => {
                 let parsed_exprs: Vec<Expr> = exprs.iter().map(parse_sexpr).collect();
                 if parsed_exprs.is_empty() {
                     panic!("Invalid: no expressions for block");
@@ -189,9 +432,23 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
             }
 
             // Loop
-            [Sexp::Atom(S(op)), e] if op == "loop" => Expr::Loop(Box::new(parse_sexpr(e))),
+            [Sexp::Atom(S(op)), e] if op == "loop" => Expr::Loop(None, Box::new(parse_sexpr(e))),
+            [Sexp::Atom(S(op)), Sexp::Atom(S(name)), e] if op == "loop" => {
+                if is_keyword(name) {
+                    panic!("Invalid: {name} cannot be used as a loop name");
+                }
+                Expr::Loop(Some(name.to_string()), Box::new(parse_sexpr(e)))
+            }
             // Break
-            [Sexp::Atom(S(op)), e] if op == "break" => Expr::Break(Box::new(parse_sexpr(e))),
+            [Sexp::Atom(S(op)), e] if op == "break" => Expr::Break(None, Box::new(parse_sexpr(e))),
+            [Sexp::Atom(S(op)), Sexp::Atom(S(name)), e] if op == "break" => {
+                Expr::Break(Some(name.to_string()), Box::new(parse_sexpr(e)))
+            }
+            // Continue
+            [Sexp::Atom(S(op))] if op == "continue" => Expr::Continue(None),
+            [Sexp::Atom(S(op)), Sexp::Atom(S(name))] if op == "continue" => {
+                Expr::Continue(Some(name.to_string()))
+            }
 
             // Vector construction with N expressions
             [Sexp::Atom(S(keyword)), arg1, remaining_args @ ..] if keyword == "vec" => {
@@ -208,6 +465,30 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                 Expr::VecGet(Box::new(parse_sexpr(e1)), Box::new(parse_sexpr(e2)))
             }
 
+            // Vector search
+            [Sexp::Atom(S(keyword)), e1, e2] if keyword == "vec-contains?" => {
+                Expr::VecContains(Box::new(parse_sexpr(e1)), Box::new(parse_sexpr(e2)))
+            }
+            [Sexp::Atom(S(keyword)), e1, e2] if keyword == "vec-index-of" => {
+                Expr::VecIndexOf(Box::new(parse_sexpr(e1)), Box::new(parse_sexpr(e2)))
+            }
+
+            // Pairs are 2-element vectors
+            [Sexp::Atom(S(keyword)), e1, e2] if keyword == "cons" => {
+                Expr::Vec(vec![parse_sexpr(e1), parse_sexpr(e2)])
+            }
+            [Sexp::Atom(S(keyword)), e] if keyword == "car" => {
+                Expr::VecGet(Box::new(parse_sexpr(e)), Box::new(Expr::Number(0)))
+            }
+            [Sexp::Atom(S(keyword)), e] if keyword == "cdr" => {
+                Expr::VecGet(Box::new(parse_sexpr(e)), Box::new(Expr::Number(1)))
+            }
+            // (list a b c) is (cons a (cons b (cons c nil)))
+            [Sexp::Atom(S(keyword)), exprs @ ..] if keyword == "list" => exprs
+                .iter()
+                .rev()
+                .fold(Expr::Nil, |rest, e| Expr::Vec(vec![parse_sexpr(e), rest])),
+
             // Vector mutability
             [Sexp::Atom(S(keyword)), e1, e2, e3] if keyword == "vec-set!" => Expr::VecSet(
                 Box::new(parse_sexpr(e1)),
@@ -215,16 +496,96 @@ fn parse_sexpr(sexpr: &Sexp) -> Expr {
                 Box::new(parse_sexpr(e3)),
             ),
 
+            // Structs
+            [Sexp::Atom(S(keyword)), Sexp::Atom(S(name)), args @ ..] if keyword == "make" => {
+                Expr::MakeStruct(name.to_string(), args.iter().map(parse_sexpr).collect())
+            }
+            [Sexp::Atom(S(keyword)), e, Sexp::Atom(S(field))] if keyword == "get-field" => {
+                Expr::GetField(Box::new(parse_sexpr(e)), field.to_string())
+            }
+            [Sexp::Atom(S(keyword)), e1, Sexp::Atom(S(field)), e2] if keyword == "set-field!" => {
+                Expr::SetField(
+                    Box::new(parse_sexpr(e1)),
+                    field.to_string(),
+                    Box::new(parse_sexpr(e2)),
+                )
+            }
+
             // Vector length
             [Sexp::Atom(S(keyword)), e] if keyword == "vec-len" => {
                 Expr::VecLen(Box::new(parse_sexpr(e)))
             }
 
+            // Vector reversal
+            [Sexp::Atom(S(keyword)), e] if keyword == "vec-reverse" => {
+                Expr::VecReverse(Box::new(parse_sexpr(e)))
+            }
+
+            // Vector sorting
+            [Sexp::Atom(S(keyword)), e] if keyword == "vec-sort" => {
+                Expr::VecSort(Box::new(parse_sexpr(e)))
+            }
+
+            // Vector sum and product
+            [Sexp::Atom(S(keyword)), e] if keyword == "vec-sum" => {
+                Expr::VecSum(Box::new(parse_sexpr(e)))
+            }
+            [Sexp::Atom(S(keyword)), e] if keyword == "vec-product" => {
+                Expr::VecProduct(Box::new(parse_sexpr(e)))
+            }
+
             // Vector length
             [Sexp::Atom(S(keyword)), e1, e2] if keyword == "make-vec" => {
                 Expr::MakeVec(Box::new(parse_sexpr(e1)), Box::new(parse_sexpr(e2)))
             }
 
+            // Vector map
+            [Sexp::Atom(S(keyword)), f, e] if keyword == "vec-map" => {
+                Expr::VecMap(Box::new(parse_sexpr(f)), Box::new(parse_sexpr(e)))
+            }
+            [Sexp::Atom(S(keyword)), f, e] if keyword == "vec-for-each" => {
+                Expr::VecForEach(Box::new(parse_sexpr(f)), Box::new(parse_sexpr(e)))
+            }
+            [Sexp::Atom(S(keyword)), f, e] if keyword == "apply" => {
+                Expr::Apply(Box::new(parse_sexpr(f)), Box::new(parse_sexpr(e)))
+            }
+
+            // Match
+            [Sexp::Atom(S(keyword)), e, arms @ ..] if keyword == "match" => {
+                if arms.is_empty() {
+                    panic!("Invalid: no arms for match");
+                }
+                Expr::Match(
+                    Box::new(parse_sexpr(e)),
+                    arms.iter().map(parse_match_arm).collect(),
+                )
+            }
+
+            // Case
+            [Sexp::Atom(S(keyword)), e, clauses @ ..] if keyword == "case" => {
+                let mut keyed_clauses: Vec<(i64, Expr)> = Vec::new();
+                let mut else_clause = None;
+                for (i, clause) in clauses.iter().enumerate() {
+                    match clause {
+                        Sexp::List(vec) => match &vec[..] {
+                            [Sexp::Atom(S(keyword)), body] if keyword == "else" => {
+                                if i != clauses.len() - 1 {
+                                    panic!("Invalid: else must be the last case clause");
+                                }
+                                else_clause = Some(Box::new(parse_sexpr(body)));
+                            }
+                            [key, body] => match parse_sexpr(key) {
+                                Expr::Number(num) => keyed_clauses.push((num, parse_sexpr(body))),
+                                _ => panic!("Invalid: case keys must be numbers"),
+                            },
+                            _ => panic!("Invalid: case clause should be a key and a body"),
+                        },
+                        _ => panic!("Invalid: case clause should be a key and a body"),
+                    }
+                }
+                Expr::Case(Box::new(parse_sexpr(e)), keyed_clauses, else_clause)
+            }
+
             // Function call
             [Sexp::Atom(S(funname)), args @ ..] => {
                 if is_keyword(funname) {
@@ -279,17 +640,295 @@ fn parse_bind(sexpr: &Sexp) -> (String, Expr) {
     }
 }
 
+// Parses a single match arm of the form (pattern body)
+fn parse_match_arm(sexpr: &Sexp) -> (Pattern, Expr) {
+    match sexpr {
+        Sexp::List(vec) => match &vec[..] {
+            [pattern, body] => (parse_pattern(pattern), parse_sexpr(body)),
+            _ => panic!("Invalid: match arm should be a pattern and a body"),
+        },
+        _ => panic!("Invalid: {sexpr:?}"),
+    }
+}
+
+// Parses a match pattern
+fn parse_pattern(sexpr: &Sexp) -> Pattern {
+    match sexpr {
+        Sexp::Atom(I(num)) => Pattern::Number(*num),
+        Sexp::Atom(S(name)) if name == "_" => Pattern::Wildcard,
+        Sexp::Atom(S(name)) if name == "false" => Pattern::Boolean(false),
+        Sexp::Atom(S(name)) if name == "true" => Pattern::Boolean(true),
+        Sexp::Atom(S(name)) if name == "nil" => Pattern::Nil,
+        Sexp::Atom(S(name)) => {
+            if is_keyword(name) {
+                panic!("Invalid: pattern variable {name} is a reserved keyword");
+            }
+            Pattern::Id(name.to_string())
+        }
+        Sexp::List(vec) => match &vec[..] {
+            [Sexp::Atom(S(keyword)), elems @ ..] if keyword == "vec" => {
+                Pattern::Vec(elems.iter().map(parse_pattern).collect())
+            }
+            _ => panic!("Invalid pattern: {sexpr:?}"),
+        },
+        _ => panic!("Invalid pattern: {sexpr:?}"),
+    }
+}
+
+// Parses the source code of an included file, which may only contain struct and function
+// definitions and includes. They are returned as a program whose main expression is nil.
+pub fn parse_included_source(contents: &str) -> Result<Program, CompileError> {
+    let contents = &strip_comments(&normalize_source(contents));
+    let mut forms = match parse(&format!("({contents})")) {
+        Ok(Sexp::List(forms)) => forms,
+        _ => return Err(CompileError::InvalidSexp),
+    };
+    if let Some(form) = forms
+        .iter()
+        .find(|form| include_path(form).is_none() && !is_structdef(form) && !is_fundef(form))
+    {
+        panic!("Invalid: an included file can only contain definitions, found {form}");
+    }
+    if forms.is_empty() {
+        return Ok(Program {
+            structs: vec![],
+            defs: vec![],
+            includes: vec![],
+            main: Box::new(Expr::Nil),
+        });
+    }
+    forms.push(Sexp::Atom(S("nil".to_string())));
+    return Ok(parse_program(&Sexp::List(forms)));
+}
+
+// Returns the path of the file the S-expression includes, if it is an include directive
+fn include_path(sexpr: &Sexp) -> Option<String> {
+    match sexpr {
+        Sexp::List(vec) => match &vec[..] {
+            [Sexp::Atom(S(keyword)), Sexp::Atom(S(path))] if keyword == "include" => {
+                Some(path.to_string())
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Returns the name of the function the S-expression defines, if it is a function definition
+fn fun_name(sexpr: &Sexp) -> Option<String> {
+    match sexpr {
+        Sexp::List(vec) if is_fundef(sexpr) => match &vec[1] {
+            Sexp::List(name_and_params) => match name_and_params.first() {
+                Some(Sexp::Atom(S(name))) => Some(name.to_string()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Returns the function definition with the given name, and with the functions defined in blocks
+// within its body lifted out to lifted
+fn lift_definition(
+    def: &Sexp,
+    name: &str,
+    renames: &HashMap<String, String>,
+    taken: &mut HashSet<String>,
+    lifted: &mut Vec<Sexp>,
+) -> Sexp {
+    let mut items = match def {
+        Sexp::List(items) => items.clone(),
+        _ => return def.clone(),
+    };
+    if let Sexp::List(name_and_params) = &mut items[1] {
+        name_and_params[0] = Sexp::Atom(S(name.to_string()));
+    }
+    if let Some(body) = items.pop() {
+        items.push(lift_local_functions(&body, name, renames, taken, lifted));
+    }
+    return Sexp::List(items);
+}
+
+// Returns the expression without the functions defined in its blocks, which are added to lifted
+// in source order. A function defined in a block can only be called within the block, so it is
+// renamed after the function it is defined in, such as outer.helper, and so are the calls to it.
+// The scope is the name of the enclosing function, and renames maps the names of the local
+// functions in scope to their new names. Local functions cannot use the enclosing variables.
+fn lift_local_functions(
+    sexpr: &Sexp,
+    scope: &str,
+    renames: &HashMap<String, String>,
+    taken: &mut HashSet<String>,
+    lifted: &mut Vec<Sexp>,
+) -> Sexp {
+    let items = match sexpr {
+        Sexp::List(items) => items,
+        _ => return sexpr.clone(),
+    };
+    let lift = |e: &Sexp, taken: &mut HashSet<String>, lifted: &mut Vec<Sexp>| {
+        lift_local_functions(e, scope, renames, taken, lifted)
+    };
+    match &items[..] {
+        [Sexp::Atom(S(op)), exprs @ ..]
+            if (op == "block" || op == "begin") && exprs.iter().any(is_fundef) =>
+        {
+            // The functions of a block can call each other
+            let mut renames = renames.clone();
+            let mut local_names: HashSet<String> = HashSet::new();
+            for name in exprs.iter().filter_map(fun_name) {
+                if local_names.contains(&name) {
+                    panic!("Invalid: function {name} defined twice in the same block");
+                }
+                local_names.insert(name.to_string());
+                let mut new_name = format!("{scope}.{name}");
+                let mut n = 1;
+                while taken.contains(&new_name) {
+                    n += 1;
+                    new_name = format!("{scope}.{name}.{n}");
+                }
+                taken.insert(new_name.to_string());
+                renames = renames.update(name, new_name);
+            }
+
+            let mut block = vec![items[0].clone()];
+            for e in exprs.iter() {
+                match fun_name(e) {
+                    Some(name) => {
+                        // Reserve the place of the function, so it comes before the functions
+                        // defined in its body
+                        let index = lifted.len();
+                        lifted.push(e.clone());
+                        lifted[index] =
+                            lift_definition(e, &renames[&name], &renames, taken, lifted);
+                    }
+                    None => {
+                        block.push(lift_local_functions(e, scope, &renames, taken, lifted));
+                    }
+                }
+            }
+            return Sexp::List(block);
+        }
+        // Only the expressions of bindings, match arms and definitions are renamed, since the
+        // lists around them are not calls
+        [Sexp::Atom(S(op)), Sexp::List(bindings), body] if op == "let" => {
+            let bindings = lift_pairs(bindings, scope, renames, taken, lifted);
+            return Sexp::List(vec![items[0].clone(), bindings, lift(body, taken, lifted)]);
+        }
+        [Sexp::Atom(S(op)), name @ Sexp::Atom(_), Sexp::List(bindings), body] if op == "let" => {
+            let bindings = lift_pairs(bindings, scope, renames, taken, lifted);
+            return Sexp::List(vec![
+                items[0].clone(),
+                name.clone(),
+                bindings,
+                lift(body, taken, lifted),
+            ]);
+        }
+        [Sexp::Atom(S(op)), e, arms @ ..] if op == "match" => {
+            let mut result = vec![items[0].clone(), lift(e, taken, lifted)];
+            if let Sexp::List(arms) = lift_pairs(arms, scope, renames, taken, lifted) {
+                result.extend(arms);
+            }
+            return Sexp::List(result);
+        }
+        [Sexp::Atom(S(op)), ..] if op == "fun" => {
+            let mut def = items[..items.len() - 1].to_vec();
+            def.push(lift(&items[items.len() - 1], taken, lifted));
+            return Sexp::List(def);
+        }
+        // A call of a local function
+        [Sexp::Atom(S(name)), args @ ..] if renames.contains_key(name) => {
+            let mut call = vec![Sexp::Atom(S(renames[name].to_string()))];
+            call.extend(args.iter().map(|e| lift(e, taken, lifted)));
+            return Sexp::List(call);
+        }
+        // A local function passed by name
+        [Sexp::Atom(S(op)), Sexp::Atom(S(name)), args @ ..]
+            if (op == "vec-map" || op == "vec-for-each" || op == "apply")
+                && renames.contains_key(name) =>
+        {
+            let mut call = vec![items[0].clone(), Sexp::Atom(S(renames[name].to_string()))];
+            call.extend(args.iter().map(|e| lift(e, taken, lifted)));
+            return Sexp::List(call);
+        }
+        _ => return Sexp::List(items.iter().map(|e| lift(e, taken, lifted)).collect()),
+    }
+}
+
+// Returns the list of let bindings or match arms with the local functions lifted out of the
+// expression of each pair, leaving the name or pattern before it as it is
+fn lift_pairs(
+    pairs: &[Sexp],
+    scope: &str,
+    renames: &HashMap<String, String>,
+    taken: &mut HashSet<String>,
+    lifted: &mut Vec<Sexp>,
+) -> Sexp {
+    let mut result = Vec::new();
+    for pair in pairs.iter() {
+        match pair {
+            Sexp::List(items) if items.len() == 2 => result.push(Sexp::List(vec![
+                items[0].clone(),
+                lift_local_functions(&items[1], scope, renames, taken, lifted),
+            ])),
+            _ => result.push(pair.clone()),
+        }
+    }
+    return Sexp::List(result);
+}
+
 // Returns true if the S-expression is a function definition; false otherwise
 fn is_fundef(sexpr: &Sexp) -> bool {
     match sexpr {
         Sexp::List(vec) => match &vec[..] {
             [Sexp::Atom(S(keyword)), Sexp::List(_), _] if keyword == "fun" => true,
+            [Sexp::Atom(S(keyword)), Sexp::List(_), Sexp::Atom(S(colon)), _, _]
+                if keyword == "fun" && colon == ":" =>
+            {
+                true
+            }
             _ => false,
         },
         _ => false,
     }
 }
 
+fn is_structdef(sexpr: &Sexp) -> bool {
+    match sexpr {
+        Sexp::List(vec) => match &vec[..] {
+            [Sexp::Atom(S(keyword)), Sexp::Atom(S(_)), _, ..] if keyword == "struct" => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Parses the struct definition.
+fn parse_struct_definition(s: &Sexp) -> StructDef {
+    match s {
+        Sexp::List(def_vec) => match &def_vec[..] {
+            [Sexp::Atom(S(keyword)), Sexp::Atom(S(name)), fields @ ..] if keyword == "struct" => {
+                if is_keyword(name) {
+                    panic!("Invalid: struct {name} is a reserved keyword")
+                }
+                let fields = fields
+                    .iter()
+                    .map(|field| match field {
+                        Sexp::Atom(S(field)) if !is_keyword(field) => field.to_string(),
+                        _ => panic!("Invalid struct field"),
+                    })
+                    .collect();
+                return StructDef {
+                    name: name.to_string(),
+                    fields,
+                };
+            }
+            _ => panic!("Invalid struct definition syntax"),
+        },
+        _ => panic!("Definition is a not a List"),
+    }
+}
+
 // Parses the parameter.
 fn parse_param(sexpr: &Sexp) -> String {
     match sexpr {
@@ -297,31 +936,55 @@ fn parse_param(sexpr: &Sexp) -> String {
             if is_keyword(name) {
                 panic!("Invalid: parameter {name} is a reserved keyword");
             }
+            if name == "." {
+                panic!("Invalid: . must be followed by exactly one rest parameter");
+            }
             name.to_string()
         }
         _ => panic!("Invalid function parameter"),
     }
 }
 
+// Parses a parameter with an optional type annotation, written (name : type).
+fn parse_annotated_param(sexpr: &Sexp) -> (String, Option<Ty>) {
+    match sexpr {
+        Sexp::List(vec) => match &vec[..] {
+            [name, Sexp::Atom(S(colon)), ty] if colon == ":" => {
+                return (parse_param(name), Some(parse_type(ty)));
+            }
+            _ => panic!("Invalid function parameter"),
+        },
+        _ => return (parse_param(sexpr), None),
+    }
+}
+
+// Parses the name of a type in an annotation.
+fn parse_type(sexpr: &Sexp) -> Ty {
+    match sexpr {
+        Sexp::Atom(S(name)) => match name.as_str() {
+            "Num" => return Ty::Num,
+            "Bool" => return Ty::Bool,
+            "Vec" => return Ty::Vec,
+            "Nil" => return Ty::Nil,
+            "Any" => return Ty::Any,
+            _ => panic!("Invalid: unknown type {name}"),
+        },
+        _ => panic!("Invalid type annotation"),
+    }
+}
+
 // Parses the function definition.
 fn parse_definition(s: &Sexp) -> FunDef {
     match s {
         Sexp::List(def_vec) => match &def_vec[..] {
             [Sexp::Atom(S(keyword)), Sexp::List(name_and_params), body] if keyword == "fun" => {
-                match &name_and_params[..] {
-                    [Sexp::Atom(S(funname)), params @ ..] => {
-                        if is_keyword(funname) {
-                            panic!("Invalid: function {funname} is a reserved keyword")
-                        }
-                        let parsed_params = params.iter().map(parse_param).collect();
-                        return FunDef {
-                            name: funname.to_string(),
-                            params: parsed_params,
-                            body: Box::new(parse_sexpr(body)),
-                        };
-                    }
-                    _ => panic!("Invalid function definition syntax"),
-                }
+                return parse_function(name_and_params, None, body);
+            }
+            // The result type is annotated after the name and parameters: (fun (f x) : Num body)
+            [Sexp::Atom(S(keyword)), Sexp::List(name_and_params), Sexp::Atom(S(colon)), ty, body]
+                if keyword == "fun" && colon == ":" =>
+            {
+                return parse_function(name_and_params, Some(parse_type(ty)), body);
             }
             _ => panic!("fun keyword not found"),
         },
@@ -329,17 +992,110 @@ fn parse_definition(s: &Sexp) -> FunDef {
     }
 }
 
+// Parses the name, parameters, and body of a function definition.
+fn parse_function(name_and_params: &[Sexp], return_type: Option<Ty>, body: &Sexp) -> FunDef {
+    match name_and_params {
+        [Sexp::Atom(S(funname)), params @ ..] => {
+            if is_keyword(funname) {
+                panic!("Invalid: function {funname} is a reserved keyword")
+            }
+            // A rest parameter is written after a . at the end of the parameters
+            let (params, rest) = match params {
+                [fixed @ .., Sexp::Atom(S(dot)), rest] if dot == "." => {
+                    (fixed, Some(parse_param(rest)))
+                }
+                _ => (params, None),
+            };
+            let mut parsed_params = Vec::new();
+            let mut param_types = Vec::new();
+            let mut defaults = Vec::new();
+            for param in params {
+                match param {
+                    // A parameter with a default value is written (name default), or
+                    // ((name : type) default) with an annotation
+                    Sexp::List(pair) if pair.len() == 2 => {
+                        let (name, ty) = parse_annotated_param(&pair[0]);
+                        parsed_params.push(name);
+                        param_types.push(ty);
+                        defaults.push(parse_sexpr(&pair[1]));
+                    }
+                    _ => {
+                        let (name, ty) = parse_annotated_param(param);
+                        if !defaults.is_empty() {
+                            panic!("Invalid: parameter {name} without a default value follows one with a default value");
+                        }
+                        parsed_params.push(name);
+                        param_types.push(ty);
+                    }
+                }
+            }
+            return FunDef {
+                name: funname.to_string(),
+                params: parsed_params,
+                defaults,
+                rest,
+                param_types,
+                return_type,
+                body: Box::new(parse_sexpr(body)),
+            };
+        }
+        _ => panic!("Invalid function definition syntax"),
+    }
+}
+
+// Parses the index of a numbered input such as input0 or input1.
+// Returns None if the string is not a numbered input.
+fn parse_input_index(s: &str) -> Option<usize> {
+    let digits = s.strip_prefix("input")?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    return Some(digits.parse::<usize>().unwrap_or(usize::MAX));
+}
+
+// Parses a number written as 0x... (hexadecimal) or 0b... (binary), with an optional leading minus.
+// Returns None if the string is not in either form.
+fn parse_radix_literal(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (radix, digits) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        (2, bin)
+    } else {
+        return None;
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    let num = match i64::from_str_radix(digits, radix) {
+        Ok(n) if negative => -n,
+        Ok(n) => n,
+        Err(_) => I63_MAX + 1,
+    };
+    if num < I63_MIN || num > I63_MAX {
+        panic!("Invalid: number must be in the range of a 63-bit signed integer");
+    }
+    return Some(num);
+}
+
 // Returns true if the given string is a language keyword, false otherwise
 fn is_keyword(s: &str) -> bool {
     match s {
         "true" | "false" | "input" | "nil"  // literals
-        | "add1" | "sub1" | "isnum" | "isbool" | "print" // unary operators
-        | "let" | "set!" // variable identifiers
-        | "if" | "block" | "loop" | "break" // control flow
-        | "fun" // functions
-        |  "vec" | "vec-get" | "vec-set!" // vectors
-        |  "+" | "-" | "*" | "<" | "=" | "<=" | ">=" | "==" // binary operators
+        | "add1" | "sub1" | "isnum" | "isbool" | "print" | "read" | "random" | "popcount" | "ctz" // unary operators
+        | "let" | "set!" | "set-get!" // variable identifiers
+        | "if" | "block" | "begin" | "loop" | "break" | "continue" | "match" | "case" | "else" | "assert" | "error" // control flow
+        | "fun" | ":" | "include" // functions
+        |  "vec" | "vec-get" | "vec-set!" | "vec-map" | "vec-for-each" | "vec-reverse" | "vec-sort" | "vec-sum" | "vec-product" | "vec-contains?" | "vec-index-of" | "apply" // vectors
+        | "cons" | "car" | "cdr" | "list" // pairs
+        | "struct" | "make" | "get-field" | "set-field!" // structs
+        |  "+" | "-" | "*" | "expt" | "gcd" | "lcm" | "/" | "quotient" | "%" | "remainder" | "div" | "modulo" | "min" | "max" | "<" | "=" | "<=" | ">=" | "==" | "equal?" | "xor" | "print-to" // binary operators
         => true,
+        _ if parse_input_index(s).is_some() => true, // input0, input1, ...
         _ => false,
     }
 }
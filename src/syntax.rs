@@ -4,6 +4,7 @@
 
 // Unary operators
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Op1 {
     Add1,
     Sub1,
@@ -11,56 +12,141 @@ pub enum Op1 {
     IsBool,
     IsVec,
     Print,
+    Random,             // pseudo-random number from 0 up to but excluding a positive number
+    PopCount,           // number of bits set in the 63-bit two's complement number
+    CountTrailingZeros, // number of trailing zero bits of the number; 63 for 0
 }
 
 // Binary operators
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Op2 {
     Plus,
     Minus,
     Times,
+    Expt,      // raises the first operand to the power of the second, which must be non-negative
+    Gcd,       // greatest common divisor, which is never negative
+    Lcm,       // least common multiple, which is never negative
+    Quotient,  // division rounding toward zero, like idiv
+    Remainder, // remainder of Quotient, with the sign of the dividend
+    Div,       // division rounding down
+    Modulo,    // remainder of Div, with the sign of the divisor
+    Min,
+    Max,
     Equal,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
     StructEqual,
+    BoolXor, // exclusive or of two booleans
+    PrintTo, // prints the second operand to the file descriptor given by the first, 1 or 2
 }
 
 // Expressions
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Number(i64),
     Boolean(bool),
-    Input,
+    Input(usize), // input0, input1, ...; the bare input is input0
+    Read,         // read a value from stdin
     Nil,
     Id(String),
     Let(Vec<(String, Expr)>, Box<Expr>),
+    NamedLet(String, Vec<(String, Expr)>, Box<Expr>), // let with a name that loops when called in tail position
     UnOp(Op1, Box<Expr>),
     BinOp(Op2, Box<Expr>, Box<Expr>),
     If(Box<Expr>, Box<Expr>, Box<Expr>),
-    Loop(Box<Expr>),
-    Break(Box<Expr>),
+    Loop(Option<String>, Box<Expr>),  // loop with an optional name
+    Break(Option<String>, Box<Expr>), // break out of the innermost loop, or the named loop
+    Continue(Option<String>), // jump back to the start of the innermost loop, or the named loop
     Set(String, Box<Expr>),
+    SetGet(String, Box<Expr>), // set! that returns the previous value of the variable
     Block(Vec<Expr>),
     Call(String, Vec<Expr>), // function call
     Vec(Vec<Expr>),          // vector of heap-allocated values
     VecGet(Box<Expr>, Box<Expr>),
     VecSet(Box<Expr>, Box<Expr>, Box<Expr>),
     VecLen(Box<Expr>),
+    VecReverse(Box<Expr>), // new vector with the elements in reverse order
+    VecSort(Box<Expr>), // new vector with the elements, which must be numbers, in ascending order
+    VecSum(Box<Expr>),  // sum of the elements, which must be numbers
+    VecProduct(Box<Expr>), // product of the elements, which must be numbers
+    VecContains(Box<Expr>, Box<Expr>), // whether an element is structurally equal to the value
+    VecIndexOf(Box<Expr>, Box<Expr>), // index of the first element structurally equal to the value, or -1
     MakeVec(Box<Expr>, Box<Expr>),
+    VecMap(Box<Expr>, Box<Expr>), // apply a one-argument function to each element of a vector
+    VecForEach(Box<Expr>, Box<Expr>), // call a one-argument function on each element for its side effects
+    Apply(Box<Expr>, Box<Expr>), // call a function with the elements of a vector as its arguments
+    Match(Box<Expr>, Vec<(Pattern, Expr)>), // match a value against patterns, in order
+    Assert(Box<Expr>),           // runtime error if the value is false, otherwise the value
+    UserError(Box<Expr>),        // runtime error with the given code
+    Case(Box<Expr>, Vec<(i64, Expr)>, Option<Box<Expr>>), // dispatch on a number, with an optional else
+    MakeStruct(String, Vec<Expr>), // struct name and field values, in definition order
+    GetField(Box<Expr>, String),
+    SetField(Box<Expr>, String, Box<Expr>),
 }
 
-// A function consists of a name, 0 or more named parameters (arguments), and a body
+// Types that parameters and function results can be annotated with, as written in the source.
+// Any is the type of every expression whose type is not certain, so the type checker only rejects
+// expressions that always fail when they are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ty {
+    Num,
+    Bool,
+    Vec, // a non-nil vector or struct
+    Nil,
+    Any,
+}
+
+// Patterns used by match expressions
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    Wildcard, // _
+    Number(i64),
+    Boolean(bool),
+    Nil,
+    Id(String),        // binds the matched value
+    Vec(Vec<Pattern>), // vector with exactly this many elements
+}
+
+// A function consists of a name, 0 or more named parameters (arguments), and a body.
+// The last parameters may have default values, used when a call leaves them out.
+// A variadic function also has a rest parameter, bound to a vector of the remaining arguments.
+// The parameters and the result may be annotated with types, which only --typecheck verifies.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunDef {
     pub name: String,
     pub params: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub defaults: Vec<Expr>, // default values of the last defaults.len() parameters
+    pub rest: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub param_types: Vec<Option<Ty>>, // annotated types of the parameters, in order
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub return_type: Option<Ty>,
     pub body: Box<Expr>,
 }
-// A program consits of a list of function definitions and a main expression
+// A struct consists of a name and 1 or more named fields
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+// A program consits of a list of struct and function definitions and a main expression.
+// It may also include the definitions of other files, which are added once it is resolved.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program {
+    pub structs: Vec<StructDef>,
     pub defs: Vec<FunDef>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub includes: Vec<String>, // paths of the included files, as written
     pub main: Box<Expr>,
 }
@@ -0,0 +1,346 @@
+/**
+ * An optional static type checker, which rejects programs with type errors that can be proven
+ * before they run.
+ */
+use std::fmt;
+
+use im::HashMap;
+
+use crate::analysis::sub_exprs;
+use crate::dot::{op1_name, op2_name};
+use crate::error::CompileError;
+use crate::syntax::{Expr, FunDef, Op1, Op2, Pattern, Program, Ty};
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Num => write!(f, "a number"),
+            Ty::Bool => write!(f, "a boolean"),
+            Ty::Vec => write!(f, "a vector"),
+            Ty::Nil => write!(f, "nil"),
+            Ty::Any => write!(f, "any value"),
+        }
+    }
+}
+
+impl Ty {
+    // Returns true if a value of this type may be used where the expected type is required
+    fn fits(self, expected: Ty) -> bool {
+        return self == Ty::Any || expected == Ty::Any || self == expected;
+    }
+
+    // The type of a value that comes from one of two expressions
+    fn join(self, other: Ty) -> Ty {
+        if self == other {
+            return self;
+        }
+        return Ty::Any;
+    }
+
+    // The type as far as equality is concerned: nil is a vector at runtime, so it can be compared
+    // with vectors
+    fn tag(self) -> Ty {
+        if self == Ty::Nil {
+            return Ty::Vec;
+        }
+        return self;
+    }
+}
+
+// Checks every function body and the main expression, returning the first type error found.
+// Inputs and unannotated parameters and function results have unknown types.
+pub fn typecheck(prog: &Program) -> Result<(), CompileError> {
+    let mut funs: HashMap<String, &FunDef> = HashMap::new();
+    for def in prog.defs.iter() {
+        funs = funs.update(def.name.to_string(), def);
+    }
+
+    for def in prog.defs.iter() {
+        // An assigned parameter may hold a value of another type than its annotation
+        let mut env: HashMap<String, Ty> = HashMap::new();
+        for (i, param) in def.params.iter().enumerate() {
+            if let Some(Some(ty)) = def.param_types.get(i) {
+                if !assigns(param, &def.body) {
+                    env = env.update(param.to_string(), *ty);
+                }
+            }
+        }
+        let found = infer(&def.body, &env, &funs)?;
+        if let Some(expected) = def.return_type {
+            if !found.fits(expected) {
+                return Err(CompileError::ReturnTypeError {
+                    name: def.name.to_string(),
+                    expected,
+                    found,
+                });
+            }
+        }
+    }
+    infer(&prog.main, &HashMap::new(), &funs)?;
+    return Ok(());
+}
+
+// Returns the type of the expression, after checking that its operands have the types its
+// operator requires, and that the arguments of calls have the annotated types of the parameters.
+// The environment holds the types of the variables in scope.
+fn infer(
+    expr: &Expr,
+    env: &HashMap<String, Ty>,
+    funs: &HashMap<String, &FunDef>,
+) -> Result<Ty, CompileError> {
+    match expr {
+        Expr::Number(_) => return Ok(Ty::Num),
+        Expr::Boolean(_) => return Ok(Ty::Bool),
+        Expr::Nil => return Ok(Ty::Nil),
+        Expr::Input(_) | Expr::Read | Expr::Continue(_) => return Ok(Ty::Any),
+        Expr::Id(name) => return Ok(env.get(name).copied().unwrap_or(Ty::Any)),
+        Expr::Let(bindings, body) => {
+            let mut env = env.clone();
+            for (i, (name, e)) in bindings.iter().enumerate() {
+                let ty = infer(e, &env, funs)?;
+                // An assigned variable may hold a value of another type later on
+                let assigned =
+                    bindings[i + 1..].iter().any(|(_, e)| assigns(name, e)) || assigns(name, body);
+                env = env.update(name.to_string(), if assigned { Ty::Any } else { ty });
+            }
+            return infer(body, &env, funs);
+        }
+        Expr::NamedLet(_, bindings, body) => {
+            // Each call of the named let rebinds the variables
+            let mut env = env.clone();
+            for (name, e) in bindings.iter() {
+                infer(e, &env, funs)?;
+                env = env.update(name.to_string(), Ty::Any);
+            }
+            infer(body, &env, funs)?;
+            return Ok(Ty::Any);
+        }
+        Expr::UnOp(op, e) => match op {
+            Op1::IsNum | Op1::IsBool | Op1::IsVec => {
+                infer(e, env, funs)?;
+                return Ok(Ty::Bool);
+            }
+            Op1::Print => return infer(e, env, funs),
+            Op1::Add1 | Op1::Sub1 | Op1::Random | Op1::PopCount | Op1::CountTrailingZeros => {
+                check(e, Ty::Num, op1_name(*op), 1, env, funs)?;
+                return Ok(Ty::Num);
+            }
+        },
+        Expr::BinOp(op, e1, e2) => match op {
+            Op2::Equal | Op2::StructEqual => {
+                let left = infer(e1, env, funs)?;
+                let right = infer(e2, env, funs)?;
+                if !right.tag().fits(left.tag()) {
+                    return Err(CompileError::TypeError {
+                        op: op2_name(*op).to_string(),
+                        arg: 2,
+                        expected: left,
+                        found: right,
+                    });
+                }
+                return Ok(Ty::Bool);
+            }
+            Op2::BoolXor => {
+                check(e1, Ty::Bool, op2_name(*op), 1, env, funs)?;
+                check(e2, Ty::Bool, op2_name(*op), 2, env, funs)?;
+                return Ok(Ty::Bool);
+            }
+            Op2::PrintTo => {
+                check(e1, Ty::Num, op2_name(*op), 1, env, funs)?;
+                return infer(e2, env, funs);
+            }
+            Op2::Greater | Op2::GreaterEqual | Op2::Less | Op2::LessEqual => {
+                check(e1, Ty::Num, op2_name(*op), 1, env, funs)?;
+                check(e2, Ty::Num, op2_name(*op), 2, env, funs)?;
+                return Ok(Ty::Bool);
+            }
+            _ => {
+                check(e1, Ty::Num, op2_name(*op), 1, env, funs)?;
+                check(e2, Ty::Num, op2_name(*op), 2, env, funs)?;
+                return Ok(Ty::Num);
+            }
+        },
+        Expr::If(cond, thn, els) => {
+            // Every value other than false is true, so the condition can have any type
+            infer(cond, env, funs)?;
+            return Ok(infer(thn, env, funs)?.join(infer(els, env, funs)?));
+        }
+        Expr::Set(_, e) => return infer(e, env, funs),
+        Expr::SetGet(name, e) => {
+            infer(e, env, funs)?;
+            return Ok(env.get(name).copied().unwrap_or(Ty::Any));
+        }
+        Expr::Block(es) => {
+            let mut ty = Ty::Any;
+            for e in es.iter() {
+                ty = infer(e, env, funs)?;
+            }
+            return Ok(ty);
+        }
+        Expr::Vec(es) | Expr::MakeStruct(_, es) => {
+            for e in es.iter() {
+                infer(e, env, funs)?;
+            }
+            return Ok(Ty::Vec);
+        }
+        Expr::VecGet(vec, index) => {
+            check(vec, Ty::Vec, "vec-get", 1, env, funs)?;
+            check(index, Ty::Num, "vec-get", 2, env, funs)?;
+            return Ok(Ty::Any);
+        }
+        Expr::VecSet(vec, index, e) => {
+            check(vec, Ty::Vec, "vec-set!", 1, env, funs)?;
+            check(index, Ty::Num, "vec-set!", 2, env, funs)?;
+            infer(e, env, funs)?;
+            return Ok(Ty::Vec);
+        }
+        Expr::VecLen(vec) => {
+            check(vec, Ty::Vec, "vec-len", 1, env, funs)?;
+            return Ok(Ty::Num);
+        }
+        Expr::VecReverse(vec) => {
+            check(vec, Ty::Vec, "vec-reverse", 1, env, funs)?;
+            return Ok(Ty::Vec);
+        }
+        Expr::VecSort(vec) => {
+            check(vec, Ty::Vec, "vec-sort", 1, env, funs)?;
+            return Ok(Ty::Vec);
+        }
+        Expr::VecSum(vec) => {
+            check(vec, Ty::Vec, "vec-sum", 1, env, funs)?;
+            return Ok(Ty::Num);
+        }
+        Expr::VecProduct(vec) => {
+            check(vec, Ty::Vec, "vec-product", 1, env, funs)?;
+            return Ok(Ty::Num);
+        }
+        Expr::VecContains(vec, e) => {
+            check(vec, Ty::Vec, "vec-contains?", 1, env, funs)?;
+            infer(e, env, funs)?;
+            return Ok(Ty::Bool);
+        }
+        Expr::VecIndexOf(vec, e) => {
+            check(vec, Ty::Vec, "vec-index-of", 1, env, funs)?;
+            infer(e, env, funs)?;
+            return Ok(Ty::Num);
+        }
+        Expr::MakeVec(size, e) => {
+            check(size, Ty::Num, "make-vec", 1, env, funs)?;
+            infer(e, env, funs)?;
+            return Ok(Ty::Vec);
+        }
+        Expr::VecMap(_, vec) => {
+            check(vec, Ty::Vec, "vec-map", 2, env, funs)?;
+            return Ok(Ty::Vec);
+        }
+        Expr::VecForEach(_, vec) => {
+            check(vec, Ty::Vec, "vec-for-each", 2, env, funs)?;
+            return Ok(Ty::Nil);
+        }
+        Expr::Apply(_, args) => {
+            check(args, Ty::Vec, "apply", 2, env, funs)?;
+            return Ok(Ty::Any);
+        }
+        Expr::Match(e, arms) => {
+            infer(e, env, funs)?;
+            let mut ty: Option<Ty> = None;
+            for (pattern, body) in arms.iter() {
+                let arm_ty = infer(body, &bind_pattern(pattern, env), funs)?;
+                ty = Some(ty.map_or(arm_ty, |ty| ty.join(arm_ty)));
+            }
+            return Ok(ty.unwrap_or(Ty::Any));
+        }
+        Expr::Assert(e) => return infer(e, env, funs),
+        Expr::UserError(e) => {
+            check(e, Ty::Num, "error", 1, env, funs)?;
+            return Ok(Ty::Any);
+        }
+        Expr::Case(e, clauses, else_clause) => {
+            check(e, Ty::Num, "case", 1, env, funs)?;
+            // Without an else clause, the value is nil
+            let mut ty = match else_clause {
+                Some(body) => infer(body, env, funs)?,
+                None => Ty::Nil,
+            };
+            for (_, body) in clauses.iter() {
+                ty = ty.join(infer(body, env, funs)?);
+            }
+            return Ok(ty);
+        }
+        Expr::GetField(e, _) => {
+            check(e, Ty::Vec, "get-field", 1, env, funs)?;
+            return Ok(Ty::Any);
+        }
+        Expr::SetField(e, _, value) => {
+            check(e, Ty::Vec, "set-field!", 1, env, funs)?;
+            infer(value, env, funs)?;
+            return Ok(Ty::Vec);
+        }
+        Expr::Call(name, args) => {
+            let def = funs.get(name);
+            for (i, arg) in args.iter().enumerate() {
+                match def.and_then(|def| def.param_types.get(i).copied().flatten()) {
+                    Some(ty) => check(arg, ty, name, i + 1, env, funs)?,
+                    None => infer(arg, env, funs)?,
+                };
+            }
+            return Ok(def.and_then(|def| def.return_type).unwrap_or(Ty::Any));
+        }
+        Expr::Loop(_, _) | Expr::Break(_, _) => {
+            for e in sub_exprs(expr) {
+                infer(e, env, funs)?;
+            }
+            return Ok(Ty::Any);
+        }
+    }
+}
+
+// Infers the type of an operand, failing if it is known to differ from the type the operator
+// requires. arg is the 1-based position of the operand.
+fn check(
+    e: &Expr,
+    expected: Ty,
+    op: &str,
+    arg: usize,
+    env: &HashMap<String, Ty>,
+    funs: &HashMap<String, &FunDef>,
+) -> Result<Ty, CompileError> {
+    let found = infer(e, env, funs)?;
+    if !found.fits(expected) {
+        return Err(CompileError::TypeError {
+            op: op.to_string(),
+            arg,
+            expected,
+            found,
+        });
+    }
+    return Ok(found);
+}
+
+// Returns the environment with the variables bound by the pattern, whose types are unknown
+fn bind_pattern(pattern: &Pattern, env: &HashMap<String, Ty>) -> HashMap<String, Ty> {
+    match pattern {
+        Pattern::Id(name) => return env.update(name.to_string(), Ty::Any),
+        Pattern::Vec(patterns) => {
+            let mut env = env.clone();
+            for p in patterns.iter() {
+                env = bind_pattern(p, &env);
+            }
+            return env;
+        }
+        Pattern::Wildcard | Pattern::Number(_) | Pattern::Boolean(_) | Pattern::Nil => {
+            return env.clone()
+        }
+    }
+}
+
+// Returns true if the variable may be assigned within the expression. Inner bindings that shadow
+// it are not taken into account, which only makes the checker more conservative.
+fn assigns(name: &str, expr: &Expr) -> bool {
+    if let Expr::Set(id, _) | Expr::SetGet(id, _) = expr {
+        if id == name {
+            return true;
+        }
+    }
+    return sub_exprs(expr).into_iter().any(|e| assigns(name, e));
+}
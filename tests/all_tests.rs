@@ -5,6 +5,11 @@ success_tests! {
         file: "simple_examples_1.snek",
         expected: "10\n20\n[10, 20]\n30\n40\nnil\n[30, 40, nil]"
     },
+    {
+        name: begin,
+        file: "begin.snek",
+        expected: "10\n20\n[10, 20]\n30\n40\nnil\n[30, 40, nil]"
+    },
     {
         name: simple_examples_2,
         file: "simple_examples_2.snek",
@@ -15,6 +20,11 @@ success_tests! {
         file: "points.snek",
         expected: "[5, 10]\n[30, 60]\n[35, 70]"
     },
+    {
+        name: comments,
+        file: "comments.snek",
+        expected: "[5, 10]\n[30, 60]\n[35, 70]"
+    },
     {
         name: bst,
         file: "bst.snek",
@@ -30,6 +40,110 @@ success_tests! {
         file: "equal.snek",
         expected: "true\nfalse\ntrue\nfalse\ntrue\nfalse\ntrue\ntrue\nfalse"
     },
+    {
+        name: equal_deep,
+        file: "equal-deep.snek",
+        expected: "true\nfalse"
+    },
+    {
+        name: equal_alias,
+        file: "equal-alias.snek",
+        expected: "false\ntrue\ntrue\ntrue\ntrue"
+    },
+    {
+        name: numeric_equal_default,
+        file: "numeric-equal.snek",
+        expected: "true\ntrue\ntrue"
+    },
+    {
+        name: named_let,
+        file: "named-let.snek",
+        expected: "120\n2432902008176640000\n[0, 1, 4]"
+    },
+    {
+        name: pairs,
+        file: "pairs.snek",
+        expected: "1\n2\n[1, [2, [3, nil]]]\n6"
+    },
+    {
+        name: list,
+        file: "list.snek",
+        expected: "[1, [2, [3, nil]]]\n1\n2\n3\nnil\nnil"
+    },
+    {
+        name: assert,
+        file: "assert.snek",
+        expected: "true\n0\n[1, 2]"
+    },
+    {
+        name: if_no_else,
+        file: "if-no-else.snek",
+        expected: "nil\n5\n10"
+    },
+    {
+        name: case,
+        file: "case.snek",
+        expected: "[1, 1]\n[2, 2]\n255\n-1\nnil"
+    },
+    {
+        name: labeled_loops,
+        file: "labeled-loops.snek",
+        expected: "[2, 3]\n9"
+    },
+    {
+        name: continue_loop,
+        file: "continue.snek",
+        expected: "1\n3\n5\n7\n9\n25"
+    },
+    {
+        name: local_fun,
+        file: "local-fun.snek",
+        input: "3",
+        expected: "[2, 4, 6]\n[3, 3]\n14"
+    },
+    {
+        name: min_max,
+        file: "min-max.snek",
+        input: "5",
+        expected: "7\n-2\n4\n5\n5"
+    },
+    {
+        name: xor,
+        file: "xor.snek",
+        expected: "false\ntrue\ntrue\nfalse"
+    },
+    {
+        name: unreachable_break,
+        file: "unreachable-break.snek",
+        expected: "1\n[3, -1, 2]"
+    },
+    {
+        name: local_fun_binding,
+        file: "local-fun-binding.snek",
+        expected: "9"
+    },
+    {
+        name: include,
+        file: "include.snek",
+        input: "3",
+        expected: "9\n45"
+    },
+    {
+        name: set_get,
+        file: "set-get.snek",
+        input: "3",
+        expected: "10\n[10, 3]\n0\n1\n2"
+    },
+    {
+        name: many_locals,
+        file: "many-locals.snek",
+        expected: "[21, 45, 204]"
+    },
+    {
+        name: struct_fields,
+        file: "struct.snek",
+        expected: "3\n40\n8\n130"
+    },
     {
         name: cyclic_print1,
         file: "cyclic-print1.snek",
@@ -66,6 +180,45 @@ success_tests! {
         file: "vec-len.snek",
         expected: "4"
     },
+    {
+        name: vec_reverse,
+        file: "vec-reverse.snek",
+        expected: "[3, 2, 1]"
+    },
+    {
+        name: vec_reverse_nested,
+        file: "vec-reverse-nested.snek",
+        input: "3",
+        expected: "[0, 0, [true, nil]]\n[[true, nil], 0, 0]\n[]"
+    },
+    {
+        name: vec_sort,
+        file: "vec-sort.snek",
+        expected: "[1, 2, 3]"
+    },
+    {
+        name: vec_sort_shared,
+        file: "vec-sort-shared.snek",
+        input: "5",
+        expected: "[-7, -4, 0, 5, 5, 9]\n[5, -4, 0, 5, 9, -7]"
+    },
+    {
+        name: vec_sum,
+        file: "vec-sum.snek",
+        expected: "6"
+    },
+    {
+        name: vec_fold,
+        file: "vec-fold.snek",
+        input: "5",
+        expected: "0\n1\n6\n-30"
+    },
+    {
+        name: vec_search,
+        file: "vec-search.snek",
+        input: "5",
+        expected: "true\nfalse\n3\n-1\n1"
+    },
     {
         name: vec_get_1,
         file: "vec-get.snek",
@@ -115,6 +268,161 @@ success_tests! {
         file: "make_vec_1.snek",
         expected: "5\n231\n231\n231\n231\n231\n[231, 231, 231, 231, 231]"
     },
+    {
+        name: make_vec_empty,
+        file: "make-vec-empty.snek",
+        expected: "[]\n[[1, 1], [true, true]]\n0"
+    },
+    {
+        name: vec_literal_index,
+        file: "vec-literal-index.snek",
+        expected: "20\n[11, 20, 60]"
+    },
+    {
+        name: apply,
+        file: "apply.snek",
+        input: "2",
+        expected: "[4, 3]\n6"
+    },
+    {
+        name: apply_default,
+        file: "apply-default.snek",
+        expected: "5000000011\n5000000003\n6"
+    },
+    {
+        name: variadic,
+        file: "variadic.snek",
+        input: "5",
+        expected: "[1, 3, [2, 3, 4]]\n[5, 0, []]"
+    },
+    {
+        name: default_params,
+        file: "default-params.snek",
+        input: "4",
+        expected: "40\n8\n4000000000\n9"
+    },
+    {
+        name: static_vec,
+        file: "static-vec.snek",
+        expected: "[1, -2, true, nil, [false, [3]]]\n[3]\ntrue\n5"
+    },
+    {
+        name: static_vec_shared,
+        file: "static-vec-shared.snek",
+        expected: "true\ntrue\nfalse"
+    },
+    {
+        name: vec_access,
+        file: "vec-access.snek",
+        expected: "16\n9\n[0, 1, 4, 9, 16]"
+    },
+    {
+        name: constant_fold,
+        file: "constant-fold.snek",
+        expected: "24"
+    },
+    {
+        name: constant_propagation,
+        file: "constant-propagation.snek",
+        input: "10",
+        expected: "[13, 12, 11]"
+    },
+    {
+        name: loop_invariant,
+        file: "loop-invariant.snek",
+        input: "2",
+        expected: "30"
+    },
+    {
+        name: loop_invariant_equal,
+        file: "loop-invariant-equal.snek",
+        expected: "1"
+    },
+    {
+        name: expt,
+        file: "expt.snek",
+        input: "5",
+        expected: "[1024, 125, -243, 1, 1]"
+    },
+    {
+        name: gcd_lcm,
+        file: "gcd-lcm.snek",
+        input: "4",
+        expected: "[6, 12, 2, 12, 0, 0, 4]"
+    },
+    {
+        name: div_mod,
+        file: "div-mod.snek",
+        input: "7",
+        expected: "[2, -1, 2, -1, -4, -3, 1, -2, -3, -2, 6]"
+    },
+    {
+        name: popcount_ctz,
+        file: "popcount-ctz.snek",
+        input: "12",
+        expected: "[3, 2, 60, 0, 3, 2, 63, 12]"
+    },
+    {
+        name: match_vec2,
+        file: "match-vec2.snek",
+        expected: "[2, 1]\nnil\n2\n30"
+    },
+    {
+        name: match_vec3,
+        file: "match-vec3.snek",
+        expected: "6\ntrue\n[7, 1]"
+    },
+    {
+        name: vec_map,
+        file: "vec-map.snek",
+        expected: "[2, 4, 6]\n[true, false, false]\n[1, 2, 3]"
+    },
+    {
+        name: vec_for_each,
+        file: "vec-for-each.snek",
+        expected: "1\ntrue\n[2, 3]\nnil"
+    },
+    {
+        name: hex_binary,
+        file: "hex-binary.snek",
+        expected: "255\n10\n-16\n4611686018427387903"
+    },
+    {
+        name: inputs,
+        file: "inputs.snek",
+        input: "3 4",
+        expected: "3\n4\n7"
+    },
+    {
+        name: inputs_default,
+        file: "inputs-default.snek",
+        input: "true 5",
+        expected: "[true, 5, false]"
+    },
+    {
+        name: read,
+        file: "read.snek",
+        stdin: "41\ntrue\n",
+        expected: "true\n42"
+    },
+    {
+        name: typecheck_set,
+        file: "typecheck-set.snek",
+        input: "5",
+        expected: "2"
+    },
+    {
+        name: typed_fun,
+        file: "typed-fun.snek",
+        input: "4",
+        expected: "12\n2\n4"
+    },
+    {
+        name: typed_fun_return,
+        file: "typed-fun-return.snek",
+        input: "5",
+        expected: "true"
+    },
 
 }
 
@@ -136,6 +444,29 @@ runtime_error_tests! {
         file: "error-tag.snek",
         expected: "invalid argument"
     },
+    {
+        name: min_max_bool,
+        file: "min-max-bool.snek",
+        input: "true",
+        expected: "invalid argument"
+    },
+    {
+        name: min_max_one,
+        file: "min-max-one.snek",
+        input: "true",
+        expected: "invalid argument"
+    },
+    {
+        name: xor_num,
+        file: "xor-num.snek",
+        input: "1",
+        expected: "invalid argument"
+    },
+    {
+        name: print_to_fd,
+        file: "print-to-fd.snek",
+        expected: "invalid argument"
+    },
     {
         name: error3,
         file: "error3.snek",
@@ -182,12 +513,191 @@ runtime_error_tests! {
         file: "error-vec-len.snek",
         expected: "invalid"
     },
+    {
+        name: vec_reverse_number,
+        file: "vec-reverse-number.snek",
+        input: "5",
+        expected: "invalid argument"
+    },
+    {
+        name: vec_sort_bool,
+        file: "vec-sort-bool.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: vec_product_overflow,
+        file: "vec-product-overflow.snek",
+        input: "2",
+        expected: "numeric overflow"
+    },
+    {
+        name: vec_sum_bool,
+        file: "vec-sum-bool.snek",
+        input: "true",
+        expected: "invalid argument"
+    },
+    {
+        name: vec_search_number,
+        file: "vec-search-number.snek",
+        input: "5",
+        expected: "invalid argument"
+    },
 
     {
         name: error_make_vec,
         file: "error-make-vec.snek",
         expected: "invalid vector size"
     },
+    {
+        name: match_fail,
+        file: "match-fail.snek",
+        expected: "no matching pattern"
+    },
+    {
+        name: apply_arity,
+        file: "apply-arity.snek",
+        expected: "wrong number of arguments"
+    },
+    {
+        name: apply_default_arity,
+        file: "apply-default-arity.snek",
+        expected: "wrong number of arguments"
+    },
+    {
+        name: vec_map_nil,
+        file: "vec-map-nil.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: read_eof,
+        file: "read-eof.snek",
+        stdin: "",
+        expected: "no more input to read"
+    },
+    {
+        name: read_invalid,
+        file: "read-eof.snek",
+        stdin: "abc\n",
+        expected: "error occurred parsing input"
+    },
+    {
+        name: car_nil,
+        file: "car-nil.snek",
+        expected: "invalid"
+    },
+    {
+        name: cdr_short,
+        file: "cdr-short.snek",
+        expected: "index out of bounds"
+    },
+    {
+        name: assert_false,
+        file: "assert-false.snek",
+        expected: "assertion failed"
+    },
+    {
+        name: user_error,
+        file: "user-error.snek",
+        expected: "user error 42"
+    },
+    {
+        name: user_error_bool,
+        file: "user-error-bool.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: case_bool,
+        file: "case-bool.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: struct_wrong_type,
+        file: "struct-wrong-type.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: vec_negative_literal_index,
+        file: "vec-negative-literal-index.snek",
+        expected: "index out of bounds"
+    },
+    {
+        name: vec_literal_index_oob,
+        file: "vec-literal-index-oob.snek",
+        expected: "index out of bounds"
+    },
+    {
+        name: loop_invariant_error,
+        file: "loop-invariant-error.snek",
+        input: "5",
+        expected: "invalid argument"
+    },
+    {
+        name: expt_overflow,
+        file: "expt-overflow.snek",
+        input: "2",
+        expected: "numeric overflow"
+    },
+    {
+        name: expt_negative,
+        file: "expt-negative.snek",
+        input: "1",
+        expected: "invalid argument"
+    },
+    {
+        name: lcm_overflow,
+        file: "lcm-overflow.snek",
+        input: "2",
+        expected: "numeric overflow"
+    },
+    {
+        name: divide_by_zero,
+        file: "divide-by-zero.snek",
+        input: "3",
+        expected: "division by zero"
+    },
+    {
+        name: div_overflow,
+        file: "div-overflow.snek",
+        input: "1",
+        expected: "numeric overflow"
+    },
+    {
+        name: random_range,
+        file: "random-range.snek",
+        input: "0",
+        expected: "invalid argument"
+    },
+    {
+        name: typecheck_plus_bool,
+        file: "typecheck-plus-bool.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: typecheck_let,
+        file: "typecheck-let.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: typecheck_nil,
+        file: "typecheck-nil.snek",
+        input: "true",
+        expected: "invalid argument"
+    },
+    {
+        name: typecheck_equal,
+        file: "typecheck-equal.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: typecheck_fun,
+        file: "typecheck-fun.snek",
+        expected: "invalid argument"
+    },
+    {
+        name: typed_fun_arg,
+        file: "typed-fun-arg.snek",
+        expected: "invalid argument"
+    },
 }
 
 static_error_tests! {
@@ -206,4 +716,149 @@ static_error_tests! {
         file: "parse_vec_set_invalid_1.snek",
         expected: "Invalid"
     },
+    {
+        name: vec_map_not_fun,
+        file: "vec-map-not-fun.snek",
+        expected: "Invalid: vec-map expects the name of a function"
+    },
+    {
+        name: vec_map_arity,
+        file: "vec-map-arity.snek",
+        expected: "Invalid: function add called with 1 args, expected 2"
+    },
+    {
+        name: apply_undefined,
+        file: "apply-undefined.snek",
+        expected: "Invalid: undefined function pair"
+    },
+    {
+        name: arity_nested_if,
+        file: "arity-nested-if.snek",
+        expected: "Invalid: function f called with 2 args, expected 1"
+    },
+    {
+        name: variadic_arity,
+        file: "variadic-arity.snek",
+        expected: "Invalid: function f called with 1 args, expected 2"
+    },
+    {
+        name: variadic_vec_map,
+        file: "variadic-vec-map.snek",
+        expected: "Invalid: vec-map cannot call variadic function f"
+    },
+    {
+        name: variadic_marker,
+        file: "variadic-marker.snek",
+        expected: "Invalid: . must be followed by exactly one rest parameter"
+    },
+    {
+        name: default_params_order,
+        file: "default-params-order.snek",
+        expected: "Invalid: parameter b without a default value follows one with a default value"
+    },
+    {
+        name: default_params_literal,
+        file: "default-params-literal.snek",
+        expected: "Invalid: default value of parameter b must be a number, boolean or nil"
+    },
+    {
+        name: local_fun_scope,
+        file: "local-fun-scope.snek",
+        expected: "Invalid: undefined function helper"
+    },
+    {
+        name: min_max_empty,
+        file: "min-max-empty.snek",
+        expected: "Invalid: max needs at least one argument"
+    },
+    {
+        name: include_cycle,
+        file: "include-cycle.snek",
+        expected: "Invalid: include-cycle.snek is included by a file it includes"
+    },
+    {
+        name: include_missing,
+        file: "include-missing.snek",
+        expected: "Invalid: cannot read included file"
+    },
+    {
+        name: typed_fun_unknown,
+        file: "typed-fun-unknown.snek",
+        expected: "Invalid: unknown type Int"
+    },
+    {
+        name: hex_overflow,
+        file: "hex-overflow.snek",
+        expected: "Invalid: number must be in the range of a 63-bit signed integer"
+    },
+    {
+        name: inputs_too_many,
+        file: "inputs-too-many.snek",
+        expected: "Invalid: at most 16 inputs are supported"
+    },
+    {
+        name: empty_program,
+        file: "empty.snek",
+        expected: "empty program"
+    },
+    {
+        name: whitespace_program,
+        file: "whitespace.snek",
+        expected: "empty program"
+    },
+    {
+        name: named_let_non_tail,
+        file: "named-let-non-tail.snek",
+        expected: "Invalid: named let sum can only be called in tail position"
+    },
+    {
+        name: named_let_arity,
+        file: "named-let-arity.snek",
+        expected: "Invalid: function go called with 1 args, expected 2"
+    },
+    {
+        name: begin_empty,
+        file: "begin-empty.snek",
+        expected: "Invalid: no expressions for block"
+    },
+    {
+        name: break_unknown_loop,
+        file: "break-unknown-loop.snek",
+        expected: "no surrounding loop named c"
+    },
+    {
+        name: continue_outside_loop,
+        file: "continue-outside-loop.snek",
+        expected: "continue without surrounding loop"
+    },
+    {
+        name: duplicate_function,
+        file: "duplicate-function.snek",
+        expected: "Function clamp already defined: definition 3 (v low high) redefines definition 1 (x lo hi)"
+    },
+    {
+        name: struct_undefined_field,
+        file: "struct-undefined-field.snek",
+        expected: "Invalid: undefined field z"
+    },
+    {
+        name: struct_field_count,
+        file: "struct-field-count.snek",
+        expected: "Invalid: struct point made with 1 values, expected 2"
+    },
+    {
+        name: constant_overflow,
+        file: "constant-overflow.snek",
+        expected: "Invalid: constant expression overflows a 63-bit signed integer"
+    },
+    {
+        name: constant_overflow_nested,
+        file: "constant-overflow-nested.snek",
+        expected: "Invalid: constant expression overflows a 63-bit signed integer"
+    },
+    {
+        name: expt_constant_overflow,
+        file: "expt-constant-overflow.snek",
+        expected: "Invalid: constant expression overflows a 63-bit signed integer"
+    },
 }
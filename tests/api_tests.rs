@@ -0,0 +1,464 @@
+use green_egg_eater::assembly::{instr_to_str, instr_to_str_in, Reg, Section, Syntax, Val};
+use green_egg_eater::{
+    compile_program, compile_program_with_options, compile_source, instructions_to_string,
+    parse_source, program_to_dot, write_instructions, write_program, CompileError, CompileOptions,
+    Expr, Instr, START_LABEL,
+};
+
+#[test]
+fn compile_source_to_assembly() {
+    let asm = compile_source("(fun (double x) (* x 2)) (double 21)").unwrap();
+    assert!(asm.contains(&format!("global {START_LABEL}")));
+    assert!(asm.contains(&format!("{START_LABEL}:")));
+    assert!(asm.contains("double:"));
+    assert!(asm.contains("call double"));
+}
+
+#[test]
+fn parse_and_compile_through_api() {
+    let program = parse_source("(let ((x 5)) (add1 x))").unwrap();
+    assert!(program.defs.is_empty());
+    assert!(matches!(*program.main, Expr::Let(_, _)));
+
+    let instrs: Vec<Instr> = compile_program(&program, START_LABEL.to_string()).unwrap();
+    let strs: Vec<String> = instrs.iter().map(instr_to_str).collect();
+    // The constant binding is propagated and the addition folded
+    assert!(strs.iter().any(|s| s.contains("rax, 12")));
+}
+
+#[test]
+fn compile_errors_are_returned() {
+    let err = compile_source("(fun (f x) x) (f 1 2)").unwrap_err();
+    assert_eq!(
+        err,
+        CompileError::WrongArgCount {
+            name: "f".to_string(),
+            found: 2,
+            expected: 1
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "Invalid: function f called with 2 args, expected 1"
+    );
+
+    let err = compile_source("(let ((x 1)) y)").unwrap_err();
+    assert_eq!(err, CompileError::UnboundId("y".to_string()));
+}
+
+#[test]
+fn empty_source_is_an_error() {
+    assert!(matches!(parse_source(""), Err(CompileError::EmptyProgram)));
+    assert!(matches!(
+        parse_source("  \n\t "),
+        Err(CompileError::EmptyProgram)
+    ));
+}
+
+#[test]
+fn compile_on_separate_threads() {
+    let sources = [
+        "(fun (f x) (if (< x 0) (loop (break x)) x)) (f input)",
+        "(fun (inc x) (+ x 1)) (let ((v (vec 1 2 3))) (if (= (vec-get v 0) 1) (vec-map inc v) v))",
+    ];
+    let sequential: Vec<String> = sources
+        .iter()
+        .map(|source| compile_source(source).unwrap())
+        .collect();
+
+    // Labels are numbered per program, so each thread produces the same output as compiling alone
+    let handles: Vec<_> = sources
+        .iter()
+        .map(|&source| std::thread::spawn(move || compile_source(source).unwrap()))
+        .collect();
+    let parallel: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(sequential, parallel);
+    assert!(parallel[0].contains("ifend_0:"));
+    assert!(parallel[1].contains("ifend_0:"));
+}
+
+#[test]
+fn parallel_compilation_matches_sequential() {
+    let mut source = String::new();
+    for i in 0..50 {
+        source.push_str(&format!(
+            "(fun (f{i} x) (if (< x {i}) (loop (break x)) (f{} (- x 1))))\n",
+            (i + 1) % 50
+        ));
+    }
+    source.push_str("(f0 input)");
+    let program = parse_source(&source).unwrap();
+
+    let sequential = compile_program_with_options(
+        &program,
+        START_LABEL.to_string(),
+        &CompileOptions {
+            parallel: false,
+            ..CompileOptions::default()
+        },
+    )
+    .unwrap();
+    let parallel = compile_program_with_options(
+        &program,
+        START_LABEL.to_string(),
+        &CompileOptions {
+            parallel: true,
+            ..CompileOptions::default()
+        },
+    )
+    .unwrap();
+    let sequential: Vec<String> = sequential.iter().map(instr_to_str).collect();
+    let parallel: Vec<String> = parallel.iter().map(instr_to_str).collect();
+    assert_eq!(sequential, parallel);
+}
+
+#[test]
+fn stable_labels_are_named_by_path() {
+    let compile_stable = |source: &str| -> Vec<String> {
+        let program = parse_source(source).unwrap();
+        let options = CompileOptions {
+            stable_labels: true,
+            ..CompileOptions::default()
+        };
+        let instrs = compile_program_with_options(&program, START_LABEL.to_string(), &options);
+        return instrs.unwrap().iter().map(instr_to_str).collect();
+    };
+    let source = "(fun (f x) (if (< x 0) (loop (break x)) x)) (block (if input 1 2) (loop (break 3)))";
+    let first = compile_stable(source);
+    assert_eq!(first, compile_stable(source));
+    assert!(first.contains(&"f_ifend_0:".to_string()));
+    assert!(first.contains(&"f_loop_0.1:".to_string()));
+    assert!(first.contains(&"loop_0.1:".to_string()));
+
+    // Replacing the if of the main expression leaves the labels of the loop after it unchanged
+    let edited = compile_stable("(fun (f x) x) (block (loop (break 0)) (loop (break 3)))");
+    assert!(edited.contains(&"loop_0.0:".to_string()));
+    assert!(edited.contains(&"loop_0.1:".to_string()));
+}
+
+#[test]
+fn parallel_compilation_reports_first_error() {
+    let mut source = String::new();
+    for i in 0..20 {
+        source.push_str(&format!("(fun (f{i} x) (+ x y{i}))\n"));
+    }
+    source.push_str("(f0 1)");
+    let err = compile_source(&source).unwrap_err();
+    assert_eq!(err, CompileError::UnboundId("y0".to_string()));
+}
+
+#[test]
+fn write_instructions_to_buffer() {
+    let program = parse_source("(+ 1 2)").unwrap();
+    let instrs = compile_program(&program, START_LABEL.to_string()).unwrap();
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write_instructions(&mut buffer, &instrs).unwrap();
+    let written = String::from_utf8(buffer).unwrap();
+    assert_eq!(written.lines().count(), instrs.len());
+    assert_eq!(written.trim_end(), instructions_to_string(instrs));
+
+    let mut buffer: Vec<u8> = Vec::new();
+    write_program(&mut buffer, &[]).unwrap();
+    assert!(String::from_utf8(buffer)
+        .unwrap()
+        .contains(&format!("global {START_LABEL}")));
+}
+
+#[test]
+fn unchecked_omits_bounds_checks() {
+    let program = parse_source("(vec-get (vec 1 2 3) 1)").unwrap();
+    let to_strings = |options: &CompileOptions| -> Vec<String> {
+        compile_program_with_options(&program, START_LABEL.to_string(), options)
+            .unwrap()
+            .iter()
+            .map(instr_to_str)
+            .collect()
+    };
+    let checked = to_strings(&CompileOptions::default());
+    let unchecked = to_strings(&CompileOptions {
+        unchecked: true,
+        ..CompileOptions::default()
+    });
+    let bounds_check = "jge error_index_out_of_bounds".to_string();
+    assert!(checked.contains(&bounds_check));
+    assert!(!unchecked.contains(&bounds_check));
+}
+
+#[test]
+fn literal_index_skips_negative_check() {
+    let negative_check = "jl error_index_out_of_bounds".to_string();
+    let compile = |source: &str| -> Vec<String> {
+        let program = parse_source(source).unwrap();
+        compile_program(&program, START_LABEL.to_string())
+            .unwrap()
+            .iter()
+            .map(instr_to_str)
+            .collect()
+    };
+    assert!(!compile("(vec-get (vec 1 2 3) 1)").contains(&negative_check));
+    assert!(compile("(vec-get (vec 1 2 3) -1)").contains(&negative_check));
+    assert!(compile("(let ((i 1)) (vec-get (vec 1 2 3) i))").contains(&negative_check));
+}
+
+#[test]
+fn instruction_syntax_snapshots() {
+    let instrs = vec![
+        Instr::Mov(Val::Reg(Reg::RAX), Val::Imm(10)),
+        Instr::Mov(Val::RegOff(Reg::RBP, 16), Val::Reg(Reg::RAX)),
+        Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(Reg::RBX, -8)),
+        Instr::Add(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX)),
+        Instr::Cmp(Val::Reg(Reg::RAX), Val::Imm(3)),
+        Instr::Sar(Val::Reg(Reg::RAX), Val::Imm(1)),
+        Instr::Mov(Val::Reg(Reg::EDI), Val::Imm(2)),
+        Instr::Push(Val::Reg(Reg::RBP)),
+        Instr::JumpEqual("done".to_string()),
+    ];
+    let intel: Vec<String> = instrs
+        .iter()
+        .map(|instr| instr_to_str_in(instr, Syntax::Intel))
+        .collect();
+    let att: Vec<String> = instrs
+        .iter()
+        .map(|instr| instr_to_str_in(instr, Syntax::Att))
+        .collect();
+    assert_eq!(
+        intel,
+        vec![
+            "mov qword rax, 10",
+            "mov [rbp - 16], rax",
+            "mov rax, [rbx + 8]",
+            "add rax, rbx",
+            "cmp rax, 3",
+            "sar rax, 1",
+            "mov edi, 2",
+            "push qword rbp",
+            "je done",
+        ]
+    );
+    assert_eq!(
+        att,
+        vec![
+            "movq $10, %rax",
+            "movq %rax, -16(%rbp)",
+            "movq 8(%rbx), %rax",
+            "addq %rbx, %rax",
+            "cmpq $3, %rax",
+            "sarq $1, %rax",
+            "movl $2, %edi",
+            "pushq %rbp",
+            "je done",
+        ]
+    );
+}
+
+#[test]
+fn render_neg() {
+    assert_eq!(instr_to_str(&Instr::Neg(Val::Reg(Reg::RAX))), "neg rax");
+    assert_eq!(
+        instr_to_str(&Instr::Neg(Val::RegOff(Reg::RBP, 8))),
+        "neg qword [rbp - 8]"
+    );
+    assert_eq!(
+        instr_to_str_in(&Instr::Neg(Val::Reg(Reg::RAX)), Syntax::Att),
+        "negq %rax"
+    );
+}
+
+#[test]
+fn render_shr() {
+    let shr = Instr::Shr(Val::Reg(Reg::RAX), Val::Imm(3));
+    assert_eq!(instr_to_str(&shr), "shr rax, 3");
+    assert_eq!(instr_to_str_in(&shr, Syntax::Att), "shrq $3, %rax");
+}
+
+#[test]
+fn render_lea() {
+    let lea = Instr::Lea(Reg::RBX, Val::Scaled(Reg::RBX, Reg::RAX, 8, 8));
+    assert_eq!(instr_to_str(&lea), "lea rbx, [rbx + rax*8 + 8]");
+    assert_eq!(
+        instr_to_str_in(&lea, Syntax::Att),
+        "leaq 8(%rbx,%rax,8), %rbx"
+    );
+    let lea = Instr::Lea(Reg::RBX, Val::Scaled(Reg::RBX, Reg::R10, 8, 0));
+    assert_eq!(instr_to_str(&lea), "lea rbx, [rbx + r10*8]");
+}
+
+#[test]
+fn render_bit_counts() {
+    let popcnt = Instr::Popcnt(Val::Reg(Reg::RAX), Val::Reg(Reg::RAX));
+    assert_eq!(instr_to_str(&popcnt), "popcnt rax, rax");
+    assert_eq!(instr_to_str_in(&popcnt, Syntax::Att), "popcntq %rax, %rax");
+    let tzcnt = Instr::Tzcnt(Val::Reg(Reg::RAX), Val::Reg(Reg::RBX));
+    assert_eq!(instr_to_str(&tzcnt), "tzcnt rax, rbx");
+    assert_eq!(instr_to_str_in(&tzcnt, Syntax::Att), "tzcntq %rbx, %rax");
+}
+
+#[test]
+fn render_new_registers() {
+    for (reg, name) in [(Reg::RCX, "rcx"), (Reg::R8, "r8"), (Reg::R9, "r9")] {
+        assert_eq!(
+            instr_to_str(&Instr::Mov(Val::Reg(reg), Val::Reg(Reg::RAX))),
+            format!("mov {name}, rax")
+        );
+        assert_eq!(
+            instr_to_str(&Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(reg, 8))),
+            format!("mov rax, [{name} - 8]")
+        );
+        assert_eq!(
+            instr_to_str_in(
+                &Instr::Mov(Val::Reg(Reg::RAX), Val::RegOff(reg, -16)),
+                Syntax::Att
+            ),
+            format!("movq 16(%{name}), %rax")
+        );
+    }
+    let shift = Instr::Shl(Val::Reg(Reg::RAX), Val::Reg(Reg::CL));
+    assert_eq!(instr_to_str(&shift), "shl rax, cl");
+    assert_eq!(instr_to_str_in(&shift, Syntax::Att), "shlq %cl, %rax");
+}
+
+#[test]
+fn comments_are_ignored() {
+    // comments.snek is points.snek with comments added
+    let commented = std::fs::read_to_string("tests/comments.snek").unwrap();
+    let uncommented = std::fs::read_to_string("tests/points.snek").unwrap();
+    assert_eq!(
+        compile_source(&commented).unwrap(),
+        compile_source(&uncommented).unwrap()
+    );
+    assert_eq!(
+        compile_source("; only a comment").unwrap_err(),
+        CompileError::EmptyProgram
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_round_trip_compiles_the_same() {
+    use green_egg_eater::{program_from_json, program_to_json, Program};
+
+    let source = std::fs::read_to_string("tests/bst.snek").unwrap();
+    let program = parse_source(&source).unwrap();
+    let json = program_to_json(&program);
+    let restored: Program = program_from_json(&json).unwrap();
+    assert_eq!(program_to_json(&restored), json);
+
+    let compile = |prog: &Program| {
+        instructions_to_string(compile_program(prog, START_LABEL.to_string()).unwrap())
+    };
+    assert_eq!(compile(&restored), compile(&program));
+}
+
+#[test]
+fn dot_graph_of_if() {
+    let program = parse_source("(if (< x 1) 5 (add1 x))").unwrap();
+    assert_eq!(
+        program_to_dot(&program),
+        "digraph program {
+  node [shape=box];
+  n0 [label=\"main\"];
+  n1 [label=\"if\"];
+  n2 [label=\"<\"];
+  n3 [label=\"x\"];
+  n2 -> n3;
+  n4 [label=\"1\"];
+  n2 -> n4;
+  n1 -> n2 [label=\"cond\"];
+  n5 [label=\"5\"];
+  n1 -> n5 [label=\"then\"];
+  n6 [label=\"add1\"];
+  n7 [label=\"x\"];
+  n6 -> n7;
+  n1 -> n6 [label=\"else\"];
+  n0 -> n1 [label=\"body\"];
+}
+"
+    );
+}
+
+#[test]
+fn crlf_and_bom_compile_like_clean_source() {
+    let clean = "; sums the numbers up to the input\n(fun (sum n)\n  (if (= n 0)\n      0\n      (+ n (sum (sub1 n)))))\n\n(sum input)\n";
+    let expected = compile_source(clean).unwrap();
+    for file in ["tests/crlf.snek", "tests/bom.snek"] {
+        let contents = std::fs::read_to_string(file).unwrap();
+        assert_ne!(contents, clean);
+        assert_eq!(compile_source(&contents).unwrap(), expected, "{file}");
+    }
+}
+
+#[test]
+fn constant_vectors_are_static_data() {
+    let compile = |source: &str| -> Vec<String> {
+        let program = parse_source(source).unwrap();
+        compile_program(&program, START_LABEL.to_string())
+            .unwrap()
+            .iter()
+            .map(instr_to_str)
+            .collect()
+    };
+    let asm = compile("(vec 1 (vec true nil))");
+    assert!(asm.contains(&"section .data".to_string()));
+    assert!(asm.contains(&"lea rax, [rel const_vec_1 + 1]".to_string()));
+    assert!(asm.contains(&"dq const_vec_0 + 1".to_string()));
+    // Literals are allocated on the heap if any vector is mutated
+    let asm = compile("(let ((v (vec 1 2))) (vec-set! v 0 3))");
+    assert!(!asm.contains(&"section .data".to_string()));
+    // Only literal elements are known at compile time
+    assert!(!compile("(vec 1 input)").contains(&"section .data".to_string()));
+}
+
+#[test]
+fn identical_constant_vectors_share_storage() {
+    let program = parse_source("(fun (f) (vec 1 (vec 2))) (vec (vec 2) (vec 1 (vec 2)))").unwrap();
+    let asm: Vec<String> = compile_program(&program, START_LABEL.to_string())
+        .unwrap()
+        .iter()
+        .map(instr_to_str)
+        .collect();
+    let labels: Vec<&String> = asm.iter().filter(|s| s.starts_with("const_vec_")).collect();
+    assert_eq!(labels, ["const_vec_0:", "const_vec_1:", "const_vec_2:"]);
+    assert_eq!(
+        asm.iter()
+            .filter(|s| *s == "lea rax, [rel const_vec_1 + 1]")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn render_static_data() {
+    let instrs = [
+        Instr::Section(Section::Data),
+        Instr::Align(8),
+        Instr::Quad(4),
+        Instr::QuadLabel("v".to_string(), 1),
+        Instr::LeaLabel(Reg::RAX, "v".to_string(), 1),
+    ];
+    let intel: Vec<String> = instrs.iter().map(instr_to_str).collect();
+    let att: Vec<String> = instrs
+        .iter()
+        .map(|instr| instr_to_str_in(instr, Syntax::Att))
+        .collect();
+    assert_eq!(
+        intel,
+        vec![
+            "section .data",
+            "align 8, db 0",
+            "dq 4",
+            "dq v + 1",
+            "lea rax, [rel v + 1]"
+        ]
+    );
+    assert_eq!(
+        att,
+        vec![
+            ".data",
+            ".balign 8",
+            ".quad 4",
+            ".quad v+1",
+            "leaq v+1(%rip), %rax"
+        ]
+    );
+}
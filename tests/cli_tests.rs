@@ -0,0 +1,681 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use green_egg_eater::{parse_source, resolve_includes, START_LABEL};
+
+// Compiles the given file with the given flags, returning the output of the compiler
+fn compile(in_path: &Path, name: &str, flags: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_green_egg_eater"))
+        .args(flags)
+        .arg(in_path)
+        .arg(Path::new("tests").join(format!("{name}.s")))
+        .output()
+        .expect("could not run the compiler");
+}
+
+// Compiles and links the given file with --link, returning the path of the executable
+fn compile_and_link(in_path: &Path, name: &str) -> PathBuf {
+    let output = compile(in_path, name, &["--link"]);
+    assert!(
+        output.status.success(),
+        "compilation failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let exe_path = String::from_utf8(output.stdout).unwrap();
+    return PathBuf::from(exe_path.trim());
+}
+
+fn run_executable(exe_path: &Path) -> Output {
+    return Command::new(exe_path)
+        .output()
+        .expect("could not run the executable");
+}
+
+#[test]
+fn link_flag_produces_executable() {
+    let exe_path = compile_and_link(&Path::new("tests").join("points.snek"), "link_cli");
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        "[5, 10]\n[30, 60]\n[35, 70]"
+    );
+}
+
+#[test]
+fn missing_arguments_prints_usage() {
+    let output = Command::new(env!("CARGO_BIN_EXE_green_egg_eater"))
+        .arg("--link")
+        .output()
+        .expect("could not run the compiler");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage"));
+}
+
+#[test]
+fn deeply_nested_expression() {
+    const DEPTH: usize = 10000;
+    let source = format!("{}0{}", "(add1 ".repeat(DEPTH), ")".repeat(DEPTH));
+    let in_path = std::env::temp_dir().join("deep_nesting.snek");
+    std::fs::write(&in_path, source).unwrap();
+
+    let exe_path = compile_and_link(&in_path, "deep_nesting");
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        DEPTH.to_string()
+    );
+}
+
+#[test]
+fn deeply_nested_expression_in_loop() {
+    // The loop-invariant analysis must stay linear in the size of the loop body
+    const DEPTH: usize = 10000;
+    let source = format!(
+        "(let ((i 0)) (loop (block (set! i (add1 i)) (break {}i{}))))",
+        "(add1 ".repeat(DEPTH),
+        ")".repeat(DEPTH)
+    );
+    let in_path = std::env::temp_dir().join("deep_nesting_loop.snek");
+    std::fs::write(&in_path, source).unwrap();
+
+    let exe_path = compile_and_link(&in_path, "deep_nesting_loop");
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        (DEPTH + 1).to_string()
+    );
+}
+
+#[test]
+fn print_many_lines() {
+    let exe_path = compile_and_link(&Path::new("tests").join("print-many.snek"), "print_many");
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    // Each number is printed, followed by the result of the program
+    assert_eq!(lines.len(), 5001);
+    for (i, line) in lines.iter().take(5000).enumerate() {
+        assert_eq!(*line, i.to_string());
+    }
+    assert_eq!(lines[5000], "5000");
+}
+
+#[test]
+fn output_is_flushed_before_runtime_error() {
+    let exe_path = compile_and_link(
+        &Path::new("tests").join("print-many-error.snek"),
+        "print_many_error",
+    );
+    let output = run_executable(&exe_path);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 5000);
+    assert_eq!(lines[4999], "4999");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("index out of bounds"));
+}
+
+#[test]
+fn print_deep_structure() {
+    const DEPTH: usize = 100000;
+    let exe_path = compile_and_link(&Path::new("tests").join("print-deep.snek"), "print_deep");
+    let output = run_executable(&exe_path);
+    assert!(
+        output.status.success(),
+        "execution failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let expected = format!("{}nil{}", "[".repeat(DEPTH), "]".repeat(DEPTH));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), expected);
+}
+
+#[test]
+fn print_to_stdout_and_stderr() {
+    let exe_path = compile_and_link(&Path::new("tests").join("print-to.snek"), "print_to");
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "10\n10");
+    assert_eq!(String::from_utf8(output.stderr).unwrap().trim(), "[5, 1]");
+}
+
+#[test]
+fn user_error_exit_code() {
+    let exe_path = compile_and_link(
+        &Path::new("tests").join("user-error.snek"),
+        "user_error_cli",
+    );
+    let output = run_executable(&exe_path);
+    assert_eq!(output.status.code(), Some(42));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "1");
+}
+
+#[test]
+fn error_exit_code_override() {
+    let runs: [(&str, &[&str], i32); 2] = [
+        ("error-bounds.snek", &["5"], 3),
+        ("user-error.snek", &[], 42),
+    ];
+    for (file, args, code) in runs {
+        let exe_path = compile_and_link(&Path::new("tests").join(file), "error_exit_cli");
+        let run = |exit_code: Option<&str>| {
+            let mut command = Command::new(&exe_path);
+            command.args(args);
+            if let Some(exit_code) = exit_code {
+                command.env("SNEK_ERROR_EXIT", exit_code);
+            }
+            return command.output().expect("could not run the executable");
+        };
+        assert_eq!(run(None).status.code(), Some(code));
+        assert_eq!(run(Some("9")).status.code(), Some(9));
+        assert!(String::from_utf8(run(Some("9")).stderr)
+            .unwrap()
+            .contains("an error occurred"));
+        let invalid = run(Some("0"));
+        assert_eq!(invalid.status.code(), Some(1));
+        let stderr = String::from_utf8(invalid.stderr).unwrap();
+        assert!(stderr.contains("an error occurred"));
+        assert!(stderr.contains("Invalid: SNEK_ERROR_EXIT must be an exit code from 1 to 255"));
+    }
+}
+
+#[test]
+fn unused_function_warning() {
+    let in_path = Path::new("tests").join("unused-function.snek");
+    let output = compile(&in_path, "unused_function", &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("function cube is never used"));
+    assert!(!stderr.contains("square"));
+
+    let output = compile(&in_path, "unused_function", &["--deny-unused"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("function cube is never used"));
+}
+
+#[test]
+fn unused_binding_warning() {
+    let output = compile(
+        &Path::new("tests").join("unused-binding.snek"),
+        "unused_binding",
+        &[],
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.trim(), "Warning: binding heigth is never used");
+}
+
+#[test]
+fn att_syntax_links_with_as() {
+    let output = compile(
+        &Path::new("tests").join("points.snek"),
+        "att_cli",
+        &["--att", "--link"],
+    );
+    assert!(
+        output.status.success(),
+        "compilation failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let exe_path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        "[5, 10]\n[30, 60]\n[35, 70]"
+    );
+}
+
+#[test]
+fn input_flag_inlines_input() {
+    let in_path = Path::new("tests").join("if_expr_input.snek");
+    let output = compile(&in_path, "if_expr_input", &["--input=635", "--link"]);
+    assert!(
+        output.status.success(),
+        "compilation failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // The multiplication is computed at compile time
+    let asm = std::fs::read_to_string(Path::new("tests").join("if_expr_input.s")).unwrap();
+    assert!(asm.contains("rax, 2540"));
+    assert!(!asm.contains("[r13"));
+
+    let exe_path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    let output = run_executable(&exe_path);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), "1270");
+
+    let output = compile(&in_path, "if_expr_input", &["--input=abc"]);
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap().trim(),
+        "Invalid: error occurred parsing input abc"
+    );
+}
+
+#[test]
+fn too_many_inputs() {
+    let exe_path = compile_and_link(&Path::new("tests").join("inputs.snek"), "too_many_inputs");
+    let output = Command::new(&exe_path)
+        .args(["1"; 17])
+        .output()
+        .expect("could not run the executable");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Invalid: at most 16 inputs are supported"));
+}
+
+#[test]
+fn random_is_deterministic_with_seed() {
+    let exe_path = compile_and_link(&Path::new("tests").join("random.snek"), "random");
+    let run_with_seed = |seed: &str| {
+        let output = Command::new(&exe_path)
+            .env("SNEK_SEED", seed)
+            .output()
+            .expect("could not run the executable");
+        assert!(output.status.success());
+        return String::from_utf8(output.stdout).unwrap().trim().to_string();
+    };
+    assert_eq!(run_with_seed("42"), "[5, 2, 4, 630398, 0]");
+    assert_eq!(run_with_seed("42"), "[5, 2, 4, 630398, 0]");
+    assert_eq!(run_with_seed("7"), "[4, 9, 9, 272746, 0]");
+
+    let output = Command::new(&exe_path)
+        .env("SNEK_SEED", "abc")
+        .output()
+        .expect("could not run the executable");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Invalid: SNEK_SEED must be a non-negative integer"));
+}
+
+#[test]
+fn print_depth_limit() {
+    let exe_path = compile_and_link(&Path::new("tests").join("print-depth.snek"), "print-depth");
+    let output = run_executable(&exe_path);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        "[1, [2, [3, [4, [5]]]]]"
+    );
+
+    let output = Command::new(&exe_path)
+        .env("SNEK_PRINT_DEPTH", "2")
+        .output()
+        .expect("could not run the executable");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        "[1, [2, ...]]"
+    );
+
+    let output = Command::new(&exe_path)
+        .env("SNEK_PRINT_DEPTH", "abc")
+        .output()
+        .expect("could not run the executable");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Invalid: SNEK_PRINT_DEPTH must be a non-negative integer"));
+}
+
+#[test]
+fn heap_stats() {
+    let exe_path = compile_and_link(&Path::new("tests").join("points.snek"), "stats_cli");
+    let output = run_executable(&exe_path);
+    assert!(output.stderr.is_empty());
+
+    // Three vectors of two elements, each with a size word
+    let output = Command::new(&exe_path)
+        .env("SNEK_STATS", "1")
+        .output()
+        .expect("could not run the executable");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap().trim(),
+        "stats: 9 words allocated, peak heap usage 9 words"
+    );
+
+    // The summary is also printed when the program fails; its vector is in the data section
+    let exe_path = compile_and_link(&Path::new("tests").join("error-bounds.snek"), "stats_error");
+    let output = Command::new(&exe_path)
+        .arg("5")
+        .env("SNEK_STATS", "1")
+        .output()
+        .expect("could not run the executable");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap().trim(),
+        "an error occurred: index out of bounds\nstats: 0 words allocated, peak heap usage 0 words"
+    );
+}
+
+#[test]
+fn print_width_limit() {
+    let exe_path = compile_and_link(&Path::new("tests").join("print-width.snek"), "print-width");
+    let output = run_executable(&exe_path);
+    let expected: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        format!("[{}]", expected.join(", "))
+    );
+
+    let output = Command::new(&exe_path)
+        .env("SNEK_PRINT_WIDTH", "5")
+        .output()
+        .expect("could not run the executable");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap().trim(),
+        "[0, 1, 2, 3, 4, ...]"
+    );
+
+    let output = Command::new(&exe_path)
+        .env("SNEK_PRINT_WIDTH", "-1")
+        .output()
+        .expect("could not run the executable");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Invalid: SNEK_PRINT_WIDTH must be a non-negative integer"));
+}
+
+#[test]
+fn numeric_equal_rejects_non_numbers() {
+    let in_path = Path::new("tests").join("numeric-equal.snek");
+    let output = compile(&in_path, "numeric-equal", &["--numeric-equal", "--link"]);
+    assert!(
+        output.status.success(),
+        "compilation failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let exe_path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+    let output = run_executable(&exe_path);
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "true\ntrue\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("invalid argument"));
+}
+
+// Compares each pair of a number, a boolean, a vector, and nil with the operator in the file,
+// checking the results against the table, where None is an invalid argument error
+fn check_equality_types(file: &str, expected: [[Option<&str>; 4]; 4]) {
+    let exe_path = compile_and_link(&Path::new("tests").join(file), file);
+    for (i, row) in expected.iter().enumerate() {
+        for (j, expected) in row.iter().enumerate() {
+            let output = Command::new(&exe_path)
+                .args([i.to_string(), j.to_string()])
+                .output()
+                .expect("could not run the executable");
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            match expected {
+                Some(result) => {
+                    assert!(output.status.success(), "{file} {i} {j}: {stderr}");
+                    assert_eq!(stdout.trim(), *result, "{file} {i} {j}");
+                }
+                None => {
+                    assert!(!output.status.success(), "{file} {i} {j}: {stdout}");
+                    assert!(
+                        stderr.contains("invalid argument"),
+                        "{file} {i} {j}: {stderr}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn equality_of_every_type_pair() {
+    let (t, f) = (Some("true"), Some("false"));
+    // Vectors are compared by identity with =, and by their contents with ==
+    check_equality_types(
+        "equal-types.snek",
+        [
+            [t, None, None, None],
+            [None, t, None, None],
+            [None, None, f, f],
+            [None, None, f, t],
+        ],
+    );
+    check_equality_types(
+        "struct-equal-types.snek",
+        [
+            [t, None, None, None],
+            [None, t, None, None],
+            [None, None, t, f],
+            [None, None, f, t],
+        ],
+    );
+}
+
+#[test]
+fn type_mismatch_warning() {
+    let in_path = Path::new("tests").join("compare-number-bool.snek");
+    let output = compile(&in_path, "compare_number_bool", &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.trim(), "Warning: = compares a number with a boolean");
+
+    let output = compile(&in_path, "compare_number_bool", &["--deny-type-warnings"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("= compares a number with a boolean"));
+}
+
+#[test]
+fn no_color_prints_plain_errors() {
+    let output = Command::new(env!("CARGO_BIN_EXE_green_egg_eater"))
+        .arg(Path::new("tests").join("apply-undefined.snek"))
+        .arg(Path::new("tests").join("no_color_static.s"))
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("could not run the compiler");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Invalid: undefined function pair\n"
+    );
+
+    let exe_path = compile_and_link(&Path::new("tests").join("error-bounds.snek"), "no_color");
+    let output = Command::new(&exe_path)
+        .arg("5")
+        .env("NO_COLOR", "1")
+        .output()
+        .expect("could not run the executable");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "an error occurred: index out of bounds\n"
+    );
+}
+
+#[test]
+fn verbose_logs_each_phase() {
+    let output = compile(
+        &Path::new("tests").join("points.snek"),
+        "verbose_cli",
+        &["--verbose", "--link"],
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let phases: Vec<&str> = stderr
+        .lines()
+        .map(|line| line.split(':').next().unwrap())
+        .collect();
+    assert_eq!(phases, ["parse", "codegen", "analysis", "write", "link"]);
+    assert!(stderr.contains(" instructions\n"));
+}
+
+#[test]
+fn typecheck_rejects_ill_typed_programs() {
+    let cases = [
+        (
+            "typecheck-plus-bool.snek",
+            "Type error: argument 1 of + must be a number, found a boolean",
+        ),
+        (
+            "typecheck-let.snek",
+            "Type error: argument 1 of add1 must be a number, found a vector",
+        ),
+        (
+            "typecheck-nil.snek",
+            "Type error: argument 1 of vec-len must be a vector, found nil",
+        ),
+        (
+            "typecheck-equal.snek",
+            "Type error: argument 2 of = must be a number, found a boolean",
+        ),
+        (
+            "typecheck-fun.snek",
+            "Type error: argument 2 of vec-get must be a number, found a boolean",
+        ),
+        (
+            "typed-fun-return.snek",
+            "Type error: function positive must return a number, found a boolean",
+        ),
+        (
+            "typed-fun-arg.snek",
+            "Type error: argument 2 of area must be a number, found a boolean",
+        ),
+        (
+            "typed-fun-result.snek",
+            "Type error: argument 1 of vec-len must be a vector, found a number",
+        ),
+    ];
+    for (file, message) in cases {
+        let output = compile(
+            &Path::new("tests").join(file),
+            "typecheck_cli",
+            &["--typecheck"],
+        );
+        assert!(!output.status.success(), "{file} should not typecheck");
+        assert_eq!(String::from_utf8(output.stderr).unwrap().trim(), message);
+    }
+}
+
+#[test]
+fn typecheck_accepts_well_typed_programs() {
+    for file in [
+        "typecheck-set.snek",
+        "typed-fun.snek",
+        "points.snek",
+        "bst.snek",
+        "match-vec2.snek",
+        "vec-fold.snek",
+        "default-params.snek",
+        "variadic.snek",
+    ] {
+        let output = compile(
+            &Path::new("tests").join(file),
+            "typecheck_cli",
+            &["--typecheck"],
+        );
+        assert!(
+            output.status.success(),
+            "{file}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn linemap_covers_every_function_label() {
+    let cases = [
+        ("linemap.snek", "scale 4\ntotal 8\nour_code_starts_here 11\n"),
+        (
+            "local-fun.snek",
+            "sum_of_squares 1\nsum_of_squares.square 3\nsum_of_squares.add_square 4\nsquare 13\nmain.double 16\nour_code_starts_here 15\n",
+        ),
+        (
+            "include.snek",
+            "double 4\nsquare 1 tests/include-square.snek\nsum_of_squares 3 tests/include-helpers.snek\nour_code_starts_here 6\n",
+        ),
+    ];
+    for (file, expected) in cases {
+        let in_path = Path::new("tests").join(file);
+        let output = compile(&in_path, "linemap_cli", &["--emit-linemap"]);
+        assert!(output.status.success());
+        let linemap =
+            std::fs::read_to_string(Path::new("tests").join("linemap_cli.linemap")).unwrap();
+        assert_eq!(linemap, expected);
+
+        // Each function and the main expression start at a label of the assembly that is mapped
+        let asm = std::fs::read_to_string(Path::new("tests").join("linemap_cli.s")).unwrap();
+        let program = parse_source(&std::fs::read_to_string(&in_path).unwrap()).unwrap();
+        let (program, _) = resolve_includes(program, &in_path).unwrap();
+        let labels = program.defs.iter().map(|def| def.name.as_str());
+        for label in labels.chain([START_LABEL]) {
+            assert!(asm.contains(&format!("\n{label}:")), "{label} is not emitted");
+            assert!(
+                linemap
+                    .lines()
+                    .any(|line| line.split(' ').next() == Some(label)),
+                "{label} is not mapped"
+            );
+        }
+    }
+}
+
+#[test]
+fn shadowed_parameter_warning() {
+    let in_path = Path::new("tests").join("shadow-param.snek");
+    let output = compile(&in_path, "shadow_param", &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.trim(),
+        "Warning: binding x shadows a parameter of function area"
+    );
+
+    let output = compile(&in_path, "shadow_param", &["--deny-shadow"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("binding x shadows a parameter of function area"));
+}
+
+#[test]
+fn stable_labels_link_and_run() {
+    let programs = [
+        ("case.snek", "[1, 1]\n[2, 2]\n255\n-1\nnil"),
+        ("match-vec2.snek", "[2, 1]\nnil\n2\n30"),
+        ("points.snek", "[5, 10]\n[30, 60]\n[35, 70]"),
+    ];
+    for (file, expected) in programs {
+        let in_path = Path::new("tests").join(file);
+        let output = compile(&in_path, "stable_labels", &["--stable-labels", "--link"]);
+        assert!(
+            output.status.success(),
+            "compilation of {file} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let exe_path = PathBuf::from(String::from_utf8(output.stdout).unwrap().trim());
+        let output = run_executable(&exe_path);
+        assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), expected);
+    }
+}
+
+#[test]
+fn unreachable_code_warning() {
+    let in_path = Path::new("tests").join("unreachable-break.snek");
+    let output = compile(&in_path, "unreachable_break", &[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.trim(),
+        "Warning: code after break in function first_negative is unreachable\n\
+         Warning: code after break in the main expression is unreachable"
+    );
+}
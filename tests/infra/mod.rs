@@ -1,6 +1,7 @@
 use std::{
+    io::Write,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Stdio},
 };
 
 pub(crate) enum TestKind {
@@ -32,6 +33,7 @@ macro_rules! tests {
                 name: $name:ident,
                 file: $file:literal,
                 $(input: $input:literal,)?
+                $(stdin: $stdin:literal,)?
                 expected: $expected:literal $(,)?
                 $(" $(tt:$tt)* ")?
             }
@@ -44,8 +46,11 @@ macro_rules! tests {
                 #[allow(unused_assignments, unused_mut)]
                 let mut input = None;
                 $(input = Some($input);)?
+                #[allow(unused_assignments, unused_mut)]
+                let mut stdin = None;
+                $(stdin = Some($stdin);)?
                 let kind = $crate::infra::TestKind::$kind;
-                $crate::infra::run_test(stringify!($name), $file, input, $expected, kind);
+                $crate::infra::run_test(stringify!($name), $file, input, stdin, $expected, kind);
             }
         )*
     };
@@ -55,22 +60,29 @@ pub(crate) fn run_test(
     name: &str,
     file: &str,
     input: Option<&str>,
+    stdin: Option<&str>,
     expected: &str,
     kind: TestKind,
 ) {
     let file = Path::new("tests").join(file);
     match kind {
-        TestKind::Success => run_success_test(name, &file, expected, input),
-        TestKind::RuntimeError => run_runtime_error_test(name, &file, expected, input),
+        TestKind::Success => run_success_test(name, &file, expected, input, stdin),
+        TestKind::RuntimeError => run_runtime_error_test(name, &file, expected, input, stdin),
         TestKind::StaticError => run_static_error_test(name, &file, expected),
     }
 }
 
-fn run_success_test(name: &str, file: &Path, expected: &str, input: Option<&str>) {
+fn run_success_test(
+    name: &str,
+    file: &Path,
+    expected: &str,
+    input: Option<&str>,
+    stdin: Option<&str>,
+) {
     if let Err(err) = compile(name, file) {
         panic!("expected a successful compilation, but got an error: `{err}`");
     }
-    match run(name, input) {
+    match run(name, input, stdin) {
         Err(err) => {
             panic!("expected a successful execution, but got an error: `{err}`");
         }
@@ -80,11 +92,17 @@ fn run_success_test(name: &str, file: &Path, expected: &str, input: Option<&str>
     }
 }
 
-fn run_runtime_error_test(name: &str, file: &Path, expected: &str, input: Option<&str>) {
+fn run_runtime_error_test(
+    name: &str,
+    file: &Path,
+    expected: &str,
+    input: Option<&str>,
+    stdin: Option<&str>,
+) {
     if let Err(err) = compile(name, file) {
         panic!("expected a successful compilation, but got an error: `{err}`");
     }
-    match run(name, input) {
+    match run(name, input, stdin) {
         Ok(out) => {
             panic!("expected a runtime error, but program executed succesfully - expected error: `{expected}`, output: `{out}`");
         }
@@ -125,15 +143,39 @@ fn compile(name: &str, file: &Path) -> Result<(), String> {
     Ok(())
 }
 
-fn run(name: &str, input: Option<&str>) -> Result<String, String> {
+fn run(name: &str, input: Option<&str>, stdin: Option<&str>) -> Result<String, String> {
     let mut cmd = Command::new(&mk_path(name, Ext::Run));
     if let Some(input) = input {
-        cmd.arg(input);
+        // Multiple inputs are separated by whitespace
+        cmd.args(input.split_whitespace());
     }
-    let output = cmd.output().unwrap();
+    let output = match stdin {
+        Some(stdin) => {
+            let mut child = cmd
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child
+                .stdin
+                .take()
+                .unwrap()
+                .write_all(stdin.as_bytes())
+                .unwrap();
+            child.wait_with_output().unwrap()
+        }
+        None => cmd.output().unwrap(),
+    };
     if output.status.success() {
         Ok(String::from_utf8(output.stdout).unwrap().trim().to_string())
     } else {
+        // Runtime errors exit with an error code instead of crashing the process
+        assert!(
+            output.status.code().is_some(),
+            "the program was terminated by a signal: {}",
+            output.status
+        );
         Err(String::from_utf8(output.stderr).unwrap().trim().to_string())
     }
 }
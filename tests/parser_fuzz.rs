@@ -0,0 +1,146 @@
+// Randomized test that the parser rejects malformed programs in a controlled way: parsing may
+// return an error or raise an "Invalid" panic, but must never hit any other panic.
+//
+// The inputs come from a fixed seed, so failures are reproducible. To search further, run
+//     FUZZ_SEED=<n> FUZZ_ITERATIONS=<n> cargo test --test parser_fuzz
+// and add any input that fails as a file in tests/fuzz-corpus, which is checked on every run.
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+use green_egg_eater::{constants::COMPILER_STACK_SIZE, parse_source};
+
+const DEFAULT_SEED: u64 = 0x5eed_e66e_a7e4;
+const DEFAULT_ITERATIONS: usize = 1000;
+
+// Fragments the generated programs are built from, so that most inputs get past the s-expression
+// reader and exercise the parser itself
+#[rustfmt::skip]
+const TOKENS: [&str; 48] = [
+    "(", "(", "(", ")", ")", ")", " ", " ", "\n", "; comment\n", "let", "fun", "struct", "if",
+    "block", "loop", "break", "continue", "set!", "match", "case", "else", "vec", "vec-get",
+    "vec-set!", "make", "get-field", "add1", "+", "==", "input", "input3", "input99", "true",
+    "nil", "_", "x", "f", "0", "-1", "4611686018427387904", "99999999999999999999", "0x10",
+    "0b", "-0x8000000000000000", "1.5", "\"", "#",
+];
+
+// Linear congruential generator, so the inputs do not depend on a random number crate
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        return self.0 >> 33;
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        return self.next() as usize % n;
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} must be a number")),
+        Err(_) => default,
+    }
+}
+
+fn random_input(rng: &mut Rng) -> String {
+    let len = rng.below(64);
+    match rng.below(4) {
+        // Arbitrary bytes
+        0 => {
+            let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            return String::from_utf8_lossy(&bytes).into_owned();
+        }
+        // Deep nesting around a random program
+        1 => {
+            let depth = 1000 + rng.below(10000);
+            let inner: String = (0..len).map(|_| TOKENS[rng.below(TOKENS.len())]).collect();
+            return "(add1 ".repeat(depth) + &inner + &")".repeat(depth);
+        }
+        // Token soup, balanced often enough to be read as s-expressions
+        _ => {
+            let mut input = String::new();
+            let mut open = 0;
+            for _ in 0..len {
+                let token = TOKENS[rng.below(TOKENS.len())];
+                match token {
+                    "(" => open += 1,
+                    ")" if open == 0 => continue,
+                    ")" => open -= 1,
+                    _ => {}
+                }
+                input.push_str(token);
+                input.push(' ');
+            }
+            if rng.below(4) != 0 {
+                input.push_str(&")".repeat(open));
+            }
+            return input;
+        }
+    }
+}
+
+// Parses the input, returning a description of the failure if parsing panicked with anything
+// other than an "Invalid" message
+fn check(input: &str) -> Result<(), String> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _ = parse_source(input);
+    }));
+    let Err(payload) = result else {
+        return Ok(());
+    };
+    let message = if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else {
+        "<non-string panic>"
+    };
+    if message.starts_with("Invalid") {
+        return Ok(());
+    }
+    return Err(format!("parsing {input:?} panicked with {message:?}"));
+}
+
+// Runs the check on a thread with the compiler's stack size, since deep nesting is supported
+fn check_on_compiler_stack(inputs: Vec<String>) {
+    let failures = thread::Builder::new()
+        .stack_size(COMPILER_STACK_SIZE)
+        .spawn(move || {
+            return inputs
+                .iter()
+                .filter_map(|input| check(input).err())
+                .collect::<Vec<String>>();
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn random_inputs_are_rejected_cleanly() {
+    let mut rng = Rng(env_or("FUZZ_SEED", DEFAULT_SEED));
+    let iterations = env_or("FUZZ_ITERATIONS", DEFAULT_ITERATIONS);
+    let inputs: Vec<String> = (0..iterations).map(|_| random_input(&mut rng)).collect();
+    check_on_compiler_stack(inputs);
+}
+
+#[test]
+fn corpus_inputs_are_rejected_cleanly() {
+    let mut inputs: Vec<String> = Vec::new();
+    for entry in fs::read_dir("tests/fuzz-corpus").unwrap() {
+        inputs.push(fs::read_to_string(entry.unwrap().path()).unwrap());
+    }
+    assert!(!inputs.is_empty());
+    check_on_compiler_stack(inputs);
+}
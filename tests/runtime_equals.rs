@@ -0,0 +1,124 @@
+use quickcheck::{quickcheck, Arbitrary, Gen};
+
+// The runtime that compiled programs are linked against, without its entry point
+#[path = "../runtime/start.rs"]
+#[allow(dead_code)]
+mod runtime;
+
+use runtime::snek_equals;
+
+const TRUE: i64 = 7;
+const FALSE: i64 = 3;
+const NIL: i64 = 1;
+
+// Shape of a generated value. Vectors may refer back to a vector containing them, which makes
+// the structure cyclic.
+#[derive(Clone, Debug)]
+enum Shape {
+    Num(i64),
+    Bool(bool),
+    Nil,
+    Vec(Vec<Shape>),
+    Back(usize), // one of the enclosing vectors, counting outwards; nil outside of vectors
+}
+
+impl Arbitrary for Shape {
+    fn arbitrary(g: &mut Gen) -> Shape {
+        return arbitrary_shape(g, g.size());
+    }
+
+    // Shrinks vectors to each of their elements and to smaller vectors, so a failure is reported
+    // for the smallest structure that still fails
+    fn shrink(&self) -> Box<dyn Iterator<Item = Shape>> {
+        match self {
+            Shape::Num(num) => Box::new(num.shrink().map(Shape::Num)),
+            Shape::Vec(elems) => Box::new(
+                elems
+                    .clone()
+                    .into_iter()
+                    .chain(elems.shrink().map(Shape::Vec)),
+            ),
+            Shape::Bool(_) | Shape::Back(_) => Box::new(std::iter::once(Shape::Nil)),
+            Shape::Nil => quickcheck::empty_shrinker(),
+        }
+    }
+}
+
+// Generates a shape with at most size vectors, half of the values being vectors. Numbers are small
+// so that generated structures are often equal.
+fn arbitrary_shape(g: &mut Gen, size: usize) -> Shape {
+    match u8::arbitrary(g) % 8 {
+        0 => Shape::Num(i64::from(u8::arbitrary(g) % 4)),
+        1 => Shape::Bool(bool::arbitrary(g)),
+        2 => Shape::Nil,
+        3 => Shape::Back(usize::arbitrary(g) % 3),
+        _ if size == 0 => Shape::Nil,
+        _ => {
+            let len = usize::arbitrary(g) % 4;
+            let elem_size = (size - 1) / len.max(1);
+            Shape::Vec((0..len).map(|_| arbitrary_shape(g, elem_size)).collect())
+        }
+    }
+}
+
+// Simulated heap holding generated values. Each vector is allocated separately so it never moves.
+#[derive(Default)]
+struct Heap {
+    vectors: Vec<Box<[i64]>>,
+}
+
+impl Heap {
+    // Allocates the value of the shape, returning its runtime representation. ancestors holds the
+    // vectors containing the value, innermost last.
+    fn alloc(&mut self, shape: &Shape, ancestors: &mut Vec<i64>) -> i64 {
+        match shape {
+            Shape::Num(num) => num << 1,
+            Shape::Bool(true) => TRUE,
+            Shape::Bool(false) => FALSE,
+            Shape::Nil => NIL,
+            Shape::Back(_) if ancestors.is_empty() => NIL,
+            Shape::Back(up) => ancestors[ancestors.len() - 1 - up % ancestors.len()],
+            Shape::Vec(elems) => {
+                let mut words = vec![0; elems.len() + 1].into_boxed_slice();
+                words[0] = elems.len() as i64;
+                let addr = words.as_mut_ptr();
+                self.vectors.push(words);
+
+                let val = addr as i64 + 1;
+                ancestors.push(val);
+                for (i, elem) in elems.iter().enumerate() {
+                    let elem_val = self.alloc(elem, ancestors);
+                    unsafe { addr.add(i + 1).write(elem_val) };
+                }
+                ancestors.pop();
+                val
+            }
+        }
+    }
+}
+
+// Allocates the value of the shape on the heap
+fn alloc(heap: &mut Heap, shape: &Shape) -> i64 {
+    return heap.alloc(shape, &mut Vec::new());
+}
+
+quickcheck! {
+    fn equals_is_reflexive(shape: Shape) -> bool {
+        let mut heap = Heap::default();
+        let val = alloc(&mut heap, &shape);
+        return unsafe { snek_equals(val, val) } == TRUE;
+    }
+
+    fn equals_is_symmetric(shape1: Shape, shape2: Shape) -> bool {
+        let mut heap = Heap::default();
+        let (val1, val2) = (alloc(&mut heap, &shape1), alloc(&mut heap, &shape2));
+        return unsafe { snek_equals(val1, val2) == snek_equals(val2, val1) };
+    }
+
+    // Separately allocated copies of the same structure, cyclic or not, are equal
+    fn copies_are_equal(shape: Shape) -> bool {
+        let mut heap = Heap::default();
+        let (val1, val2) = (alloc(&mut heap, &shape), alloc(&mut heap, &shape));
+        return unsafe { snek_equals(val1, val2) } == TRUE;
+    }
+}
@@ -0,0 +1,34 @@
+// The runtime that compiled programs are linked against, without its entry point
+#[path = "../runtime/start.rs"]
+#[allow(dead_code)]
+mod runtime;
+
+use runtime::{align_heap, vector_addr};
+
+const HEAP_BYTES: usize = 1024;
+
+#[test]
+fn misaligned_heap_is_aligned() {
+    let mut mem = vec![0u8; HEAP_BYTES + 8];
+    for skew in 0..8 {
+        let base = mem.as_mut_ptr().wrapping_add(skew);
+        let (start, end) = align_heap(base, HEAP_BYTES);
+        assert_eq!(start as usize % 8, 0, "skew {skew}");
+        assert!(start as usize >= base as usize);
+        assert!(end as usize <= base as usize + HEAP_BYTES);
+        assert_eq!((end as usize - start as usize) % 8, 0);
+        // A vector at the start of the heap is told apart from numbers and booleans by its tag
+        assert_eq!((start as i64 + 1) & 0b11, 1);
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "misaligned vector address")]
+fn misaligned_vector_is_caught() {
+    let mem = [0i64; 2];
+    let aligned = mem.as_ptr() as i64;
+    assert_eq!(vector_addr(aligned + 1) as i64, aligned);
+    // Still has a vector tag, but its address is 4 bytes past an aligned word
+    vector_addr(aligned + 4 + 1);
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use green_egg_eater::{compile_program, instructions_to_string, parse_source, START_LABEL};
+
+// Set to regenerate the golden files in tests/snapshots instead of comparing against them
+const UPDATE_VAR: &str = "UPDATE_SNAPSHOTS";
+
+macro_rules! snapshot_tests {
+    ($($name:ident: $file:literal,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                check_snapshot($file);
+            }
+        )*
+    };
+}
+
+snapshot_tests! {
+    num: "num.snek",
+    add1: "add1.snek",
+    vec_get: "vec-get.snek",
+}
+
+fn check_snapshot(file: &str) {
+    let source = fs::read_to_string(Path::new("tests").join(file)).unwrap();
+    let program = parse_source(&source).unwrap();
+    let instrs = compile_program(&program, START_LABEL.to_string()).unwrap();
+    let actual = normalize_labels(&instructions_to_string(instrs)) + "\n";
+
+    let snapshot = Path::new("tests")
+        .join("snapshots")
+        .join(Path::new(file).with_extension("s"));
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        fs::create_dir_all(snapshot.parent().unwrap()).unwrap();
+        fs::write(&snapshot, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&snapshot).unwrap_or_else(|_| {
+        panic!(
+            "missing snapshot {}; rerun with {UPDATE_VAR}=1 to create it",
+            snapshot.display()
+        )
+    });
+    if expected != actual {
+        panic!(
+            "assembly for {file} does not match {}; rerun with {UPDATE_VAR}=1 if the change is intended\n{}",
+            snapshot.display(),
+            prettydiff::diff_lines(&expected, &actual)
+        );
+    }
+}
+
+// Renumbers generated labels (those ending in `_<counter>`) in order of definition, so
+// snapshots do not change when an unrelated construct allocates more labels
+fn normalize_labels(asm: &str) -> String {
+    let mut renamed: HashMap<&str, String> = HashMap::new();
+    for line in asm.lines() {
+        let Some(label) = line.strip_suffix(':') else {
+            continue;
+        };
+        let Some((base, counter)) = label.rsplit_once('_') else {
+            continue;
+        };
+        if !counter.is_empty() && counter.bytes().all(|b| b.is_ascii_digit()) {
+            let name = format!("{base}_{}", renamed.len());
+            renamed.insert(label, name);
+        }
+    }
+
+    let lines: Vec<String> = asm
+        .lines()
+        .map(|line| {
+            line.split(' ')
+                .map(|word| {
+                    let (label, suffix) = match word.strip_suffix(':') {
+                        Some(label) => (label, ":"),
+                        None => (word, ""),
+                    };
+                    match renamed.get(label) {
+                        Some(name) => format!("{name}{suffix}"),
+                        None => word.to_string(),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .collect();
+    return lines.join("\n");
+}
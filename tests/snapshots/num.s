@@ -0,0 +1,52 @@
+error_numeric_overflow:
+mov edi, 1
+mov rsi, r15
+call snek_error
+error_invalid_type:
+mov edi, 2
+mov rsi, r15
+call snek_error
+error_index_out_of_bounds:
+mov edi, 3
+mov rsi, r15
+call snek_error
+error_invalid_vec_size:
+mov edi, 4
+mov rsi, r15
+call snek_error
+error_match_failure:
+mov edi, 5
+mov rsi, r15
+call snek_error
+error_assertion_failed:
+mov edi, 6
+mov rsi, r15
+call snek_error
+error_divide_by_zero:
+mov edi, 7
+mov rsi, r15
+call snek_error
+error_wrong_arg_count:
+mov edi, 8
+mov rsi, r15
+call snek_error
+our_code_starts_here:
+push qword rbp
+push qword r11
+push qword r12
+push qword r13
+mov rbp, rsp
+sub rsp, 8
+mov qword [rbp - 8], 1
+mov r15, rsi
+mov r13, rdi
+mov r14, rdx
+mov r11, rsi
+mov qword rax, 84
+mov rdx, r15
+add rsp, 8
+pop r13
+pop r12
+pop r11
+pop rbp
+ret
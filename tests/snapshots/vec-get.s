@@ -0,0 +1,87 @@
+error_numeric_overflow:
+mov edi, 1
+mov rsi, r15
+call snek_error
+error_invalid_type:
+mov edi, 2
+mov rsi, r15
+call snek_error
+error_index_out_of_bounds:
+mov edi, 3
+mov rsi, r15
+call snek_error
+error_invalid_vec_size:
+mov edi, 4
+mov rsi, r15
+call snek_error
+error_match_failure:
+mov edi, 5
+mov rsi, r15
+call snek_error
+error_assertion_failed:
+mov edi, 6
+mov rsi, r15
+call snek_error
+error_divide_by_zero:
+mov edi, 7
+mov rsi, r15
+call snek_error
+error_wrong_arg_count:
+mov edi, 8
+mov rsi, r15
+call snek_error
+our_code_starts_here:
+push qword rbp
+push qword r11
+push qword r12
+push qword r13
+mov rbp, rsp
+sub rsp, 24
+mov qword [rbp - 8], 1
+mov qword [rbp - 16], 1
+mov qword [rbp - 24], 1
+mov r15, rsi
+mov r13, rdi
+mov r14, rdx
+mov r11, rsi
+lea rax, [rel const_vec_0 + 1]
+mov [rbp - 8], rax
+mov rax, [rbp - 8]
+mov rbx, rax
+cmp rbx, 1
+je error_invalid_type
+and rbx, 1
+cmp rbx, 1
+jne error_invalid_type
+mov [rbp - 16], rax
+mov rax, [r13]
+mov rbx, rax
+not rbx
+and rbx, 1
+cmp rbx, 1
+jne error_invalid_type
+sar rax, 1
+mov rbx, [rbp - 16]
+sub rbx, 1
+mov r10, [rbx]
+cmp rax, r10
+jge error_index_out_of_bounds
+cmp rax, 0
+jl error_index_out_of_bounds
+lea rbx, [rbx + rax*8 + 8]
+mov rax, [rbx]
+mov rdx, r15
+add rsp, 24
+pop r13
+pop r12
+pop r11
+pop rbp
+ret
+section .data
+align 8, db 0
+const_vec_0:
+dq 4
+dq 20
+dq 40
+dq 60
+dq 80